@@ -0,0 +1,136 @@
+use std::io::{Cursor, Read};
+
+use anyhow::{anyhow, Context};
+use flate2::read::GzDecoder;
+use zip::ZipArchive;
+
+const GZIP_MAGIC: &[u8] = &[0x1F, 0x8B];
+const ZIP_MAGIC: &[u8] = b"PK\x03\x04";
+/// the XO-CHIP memory, the emulator checks the exact limit for the running mode
+const MAX_ROM_SIZE: u64 = 0x10000;
+/// entries picked from a zip archive with more than one file
+const ROM_EXTENSIONS: [&str; 2] = [".ch8", ".rom"];
+
+/// Returns the ROM in `bytes`, unpacked if it is a gzip file or a zip archive.
+/// Both are recognized by their first bytes, anything else is returned as is.
+pub fn unpack(bytes: Vec<u8>) -> anyhow::Result<Vec<u8>> {
+    if bytes.starts_with(GZIP_MAGIC) {
+        read_rom(GzDecoder::new(&bytes[..])).context("Could not unpack gzip file.")
+    } else if bytes.starts_with(ZIP_MAGIC) {
+        unzip(&bytes).context("Could not unpack zip archive.")
+    } else {
+        Ok(bytes)
+    }
+}
+
+/// Reads the only file of the archive, or the first `ROM_EXTENSIONS` file
+/// if there are more.
+fn unzip(bytes: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let mut archive = ZipArchive::new(Cursor::new(bytes))?;
+    let mut names = vec![];
+    for index in 0..archive.len() {
+        let entry = archive.by_index(index)?;
+        if entry.is_file() {
+            names.push((index, entry.name().to_string()));
+        }
+    }
+
+    let index = match names.as_slice() {
+        [(index, _)] => Some(*index),
+        _ => names
+            .iter()
+            .find(|(_, name)| {
+                let name = name.to_lowercase();
+                ROM_EXTENSIONS.iter().any(|ext| name.ends_with(ext))
+            })
+            .map(|(index, _)| *index),
+    };
+    let Some(index) = index else {
+        if names.is_empty() {
+            return Err(anyhow!("the archive contains no files"));
+        }
+        let names: Vec<_> = names.into_iter().map(|(_, name)| name).collect();
+        return Err(anyhow!(
+            "no {} file among {}",
+            ROM_EXTENSIONS.join(" or "),
+            names.join(", ")
+        ));
+    };
+    let entry = archive.by_index(index)?;
+    read_rom(entry)
+}
+
+/// Reads all of `reader`, failing once it exceeds `MAX_ROM_SIZE`.
+fn read_rom(reader: impl Read) -> anyhow::Result<Vec<u8>> {
+    let mut rom = vec![];
+    reader.take(MAX_ROM_SIZE + 1).read_to_end(&mut rom)?;
+    if rom.len() as u64 > MAX_ROM_SIZE {
+        return Err(anyhow!(
+            "the unpacked ROM is larger than {} bytes",
+            MAX_ROM_SIZE
+        ));
+    }
+    Ok(rom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use zip::write::FileOptions;
+    use zip::ZipWriter;
+
+    const ROM: &[u8] = &[0x00, 0xE0, 0x12, 0x02];
+
+    fn gzip(bytes: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(bytes).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn zip(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut writer = ZipWriter::new(Cursor::new(vec![]));
+        for (name, bytes) in files {
+            writer.start_file(*name, FileOptions::default()).unwrap();
+            writer.write_all(bytes).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn test_unpack_directory_only() {
+        let mut writer = ZipWriter::new(Cursor::new(vec![]));
+        writer
+            .add_directory("roms/", FileOptions::default())
+            .unwrap();
+        let bytes = writer.finish().unwrap().into_inner();
+        let e = unpack(bytes).unwrap_err();
+        assert_eq!(e.root_cause().to_string(), "the archive contains no files");
+    }
+
+    #[test]
+    fn test_unpack() {
+        assert_eq!(unpack(ROM.to_vec()).unwrap(), ROM);
+        assert_eq!(unpack(gzip(ROM)).unwrap(), ROM);
+        assert_eq!(unpack(zip(&[("pong", ROM)])).unwrap(), ROM);
+        assert_eq!(
+            unpack(zip(&[("README.txt", b"Pong"), ("PONG.CH8", ROM)])).unwrap(),
+            ROM
+        );
+
+        let e = unpack(zip(&[("README.txt", b"Pong"), ("pong.bin", ROM)])).unwrap_err();
+        assert_eq!(
+            e.root_cause().to_string(),
+            "no .ch8 or .rom file among README.txt, pong.bin"
+        );
+        let e = unpack(gzip(&vec![0; MAX_ROM_SIZE as usize + 1])).unwrap_err();
+        assert_eq!(
+            e.root_cause().to_string(),
+            "the unpacked ROM is larger than 65536 bytes"
+        );
+        assert!(unpack(gzip(&vec![0; MAX_ROM_SIZE as usize])).is_ok());
+    }
+}
@@ -0,0 +1,73 @@
+use std::collections::{HashMap, HashSet};
+
+use chip8_emulator_lib::emulator::{Key, FPS};
+
+/// Turns holding one of the configured keys into repeated presses.
+pub struct Autofire {
+    keys: HashSet<Key>,
+    /// length of one press/release cycle in frames
+    period: u64,
+    held_since: HashMap<Key, u64>,
+}
+
+impl Autofire {
+    /// `rate` is in presses per second, 0 disables auto-fire.
+    pub fn new(rate: u32, keys: HashSet<Key>) -> Option<Self> {
+        if rate == 0 || keys.is_empty() {
+            return None;
+        }
+        Some(Self {
+            keys,
+            period: (FPS / rate).max(2) as u64,
+            held_since: HashMap::new(),
+        })
+    }
+
+    /// Releases held auto-fire keys during the second half of each cycle.
+    pub fn apply(&mut self, frame: u64, keys_pressed: &mut HashSet<Key>) {
+        self.held_since.retain(|key, _| keys_pressed.contains(key));
+        for &key in &self.keys {
+            if keys_pressed.contains(&key) {
+                let held_since = *self.held_since.entry(key).or_insert(frame);
+                if (frame - held_since) % self.period >= self.period / 2 {
+                    keys_pressed.remove(&key);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply() {
+        // 15 presses per second => 2 frames pressed, 2 frames released
+        let mut autofire = Autofire::new(15, HashSet::from([Key::Key2])).unwrap();
+
+        let mut pressed = vec![];
+        for frame in 10..18 {
+            let mut keys_pressed = HashSet::from([Key::Key2, Key::Key5]);
+            autofire.apply(frame, &mut keys_pressed);
+            assert!(keys_pressed.contains(&Key::Key5));
+            pressed.push(keys_pressed.contains(&Key::Key2));
+        }
+        assert_eq!(
+            pressed,
+            [true, true, false, false, true, true, false, false]
+        );
+
+        // releasing the key restarts the cycle
+        autofire.apply(18, &mut HashSet::new());
+        let mut keys_pressed = HashSet::from([Key::Key2]);
+        autofire.apply(19, &mut keys_pressed);
+        assert!(keys_pressed.contains(&Key::Key2));
+    }
+
+    #[test]
+    fn test_disabled() {
+        assert!(Autofire::new(0, HashSet::from([Key::Key2])).is_none());
+        assert!(Autofire::new(10, HashSet::new()).is_none());
+    }
+}
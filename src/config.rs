@@ -1,31 +1,181 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fs;
+use std::path::{Path, PathBuf};
 
 use anyhow::{anyhow, Context, Ok};
 use platform_dirs::AppDirs;
 use serde::{Deserialize, Serialize};
 use winit::event::VirtualKeyCode;
 
-use chip8_emulator_lib::emulator::{self, Key};
+use chip8_emulator_lib::beeper::{self, Waveform};
+use chip8_emulator_lib::emulator::{self, Key, Quirks, QUIRK_PROFILES};
 
-#[derive(Deserialize, Debug)]
+use crate::scancode::scancode;
+
+/// Parses a CHIP-8 key given as a single hex digit.
+pub fn str_to_key(s: &str) -> anyhow::Result<Key> {
+    u8::from_str_radix(s, 16)
+        .ok()
+        .filter(|_| s.len() == 1)
+        .and_then(Key::from_num)
+        .ok_or_else(|| anyhow!("Invalid key: {}.", s))
+}
+
+const DEFAULT_PAUSE_KEY: VirtualKeyCode = VirtualKeyCode::P;
+const DEFAULT_RESET_KEY: VirtualKeyCode = VirtualKeyCode::F5;
+const DEFAULT_SPEED_UP_KEY: VirtualKeyCode = VirtualKeyCode::PageUp;
+const DEFAULT_SPEED_DOWN_KEY: VirtualKeyCode = VirtualKeyCode::PageDown;
+const DEFAULT_FULLSCREEN_KEY: VirtualKeyCode = VirtualKeyCode::F11;
+const DEFAULT_STEP_KEY: VirtualKeyCode = VirtualKeyCode::F10;
+const DEFAULT_REWIND_KEY: VirtualKeyCode = VirtualKeyCode::Back;
+const DEFAULT_FAST_FORWARD_KEY: VirtualKeyCode = VirtualKeyCode::Tab;
+/// range of beep frequencies in Hz that people can hear
+const AUDIBLE_FREQUENCIES: std::ops::RangeInclusive<f32> = 20.0..=20000.0;
+
+#[derive(Serialize, Deserialize, Debug)]
 pub struct TomlConfig {
     pixel_size: u32,
+    pixel_width: Option<u32>,
+    pixel_height: Option<u32>,
+    /// width to height of a pixel, scaling `pixel_size`
+    pixel_aspect: Option<(u32, u32)>,
     on_color: (u8, u8, u8),
+    #[serde(default)]
+    off_color: (u8, u8, u8),
+    #[serde(default)]
+    scanlines: bool,
+    #[serde(default)]
+    collision_sound: bool,
+    #[serde(default)]
+    autofire_rate: u32,
+    #[serde(default)]
+    autofire_keys: Vec<String>,
+    #[serde(default)]
+    beep_sound: Option<PathBuf>,
+    beep_frequency: Option<f32>,
+    #[serde(default)]
+    beep_waveform: Waveform,
+    volume: Option<f32>,
+    pause_key: Option<String>,
+    reset_key: Option<String>,
+    speed_up_key: Option<String>,
+    speed_down_key: Option<String>,
+    fullscreen_key: Option<String>,
+    step_key: Option<String>,
+    rewind_key: Option<String>,
+    fast_forward_key: Option<String>,
+    #[serde(default)]
+    quirks: Option<String>,
+    #[serde(default)]
+    key_mapping: KeyMapping,
     keys: TomlKeys,
 }
 
 impl TomlConfig {
     fn to_config(&self) -> anyhow::Result<Config> {
+        let (aspect_width, aspect_height) = self.pixel_aspect.unwrap_or((1, 1));
+        if aspect_width == 0 || aspect_height == 0 {
+            return Err(anyhow!("Pixel aspect ratio must not contain 0."));
+        }
+        let pixel_width = self.pixel_width.unwrap_or(self.pixel_size * aspect_width);
+        let pixel_height = self.pixel_height.unwrap_or(self.pixel_size * aspect_height);
+        if pixel_width == 0 || pixel_height == 0 {
+            return Err(anyhow!("Pixel width and height must not be 0."));
+        }
+        let beep_frequency = self.beep_frequency.unwrap_or(beeper::DEFAULT_FREQUENCY);
+        if !AUDIBLE_FREQUENCIES.contains(&beep_frequency) {
+            return Err(anyhow!(
+                "Beep frequency must be between {} and {} Hz.",
+                AUDIBLE_FREQUENCIES.start(),
+                AUDIBLE_FREQUENCIES.end()
+            ));
+        }
+
+        let keys = self.keys.to_keys()?;
+        if self.key_mapping == KeyMapping::Scancode {
+            if let Some(code) = keys.keys().find(|&&code| scancode(code).is_none()) {
+                return Err(anyhow!(
+                    "{:?} has no scancode, only digits and letters can be bound by scancode.",
+                    code
+                ));
+            }
+        }
+
         let config = Config {
-            pixel_size: self.pixel_size,
+            pixel_width,
+            pixel_height,
             on_color: self.on_color,
-            keys: self.keys.to_keys()?,
+            off_color: self.off_color,
+            scanlines: self.scanlines,
+            collision_sound: self.collision_sound,
+            autofire_rate: self.autofire_rate,
+            autofire_keys: self
+                .autofire_keys
+                .iter()
+                .map(|k| str_to_key(k))
+                .collect::<anyhow::Result<_>>()?,
+            beep_sound: self.beep_sound.clone(),
+            beep_frequency,
+            beep_waveform: self.beep_waveform,
+            volume: self.volume.unwrap_or(1.0),
+            pause_key: key_or_default(&self.pause_key, DEFAULT_PAUSE_KEY)?,
+            reset_key: key_or_default(&self.reset_key, DEFAULT_RESET_KEY)?,
+            speed_up_key: key_or_default(&self.speed_up_key, DEFAULT_SPEED_UP_KEY)?,
+            speed_down_key: key_or_default(&self.speed_down_key, DEFAULT_SPEED_DOWN_KEY)?,
+            fullscreen_key: key_or_default(&self.fullscreen_key, DEFAULT_FULLSCREEN_KEY)?,
+            step_key: key_or_default(&self.step_key, DEFAULT_STEP_KEY)?,
+            rewind_key: key_or_default(&self.rewind_key, DEFAULT_REWIND_KEY)?,
+            fast_forward_key: key_or_default(&self.fast_forward_key, DEFAULT_FAST_FORWARD_KEY)?,
+            quirks: self.quirks.as_deref().map(quirk_profile).transpose()?,
+            key_mapping: self.key_mapping,
+            keys,
         };
         Ok(config)
     }
 }
 
+impl From<&Config> for TomlConfig {
+    fn from(config: &Config) -> Self {
+        let key_name = |code: &VirtualKeyCode| format!("{:?}", code);
+        Self {
+            pixel_size: config.pixel_width,
+            pixel_width: Some(config.pixel_width),
+            pixel_height: Some(config.pixel_height),
+            pixel_aspect: None,
+            on_color: config.on_color,
+            off_color: config.off_color,
+            scanlines: config.scanlines,
+            collision_sound: config.collision_sound,
+            autofire_rate: config.autofire_rate,
+            autofire_keys: config
+                .autofire_keys
+                .iter()
+                .map(|key| format!("{:X}", key.to_num()))
+                .collect(),
+            beep_sound: config.beep_sound.clone(),
+            beep_frequency: Some(config.beep_frequency),
+            beep_waveform: config.beep_waveform,
+            volume: Some(config.volume),
+            pause_key: Some(key_name(&config.pause_key)),
+            reset_key: Some(key_name(&config.reset_key)),
+            speed_up_key: Some(key_name(&config.speed_up_key)),
+            speed_down_key: Some(key_name(&config.speed_down_key)),
+            fullscreen_key: Some(key_name(&config.fullscreen_key)),
+            step_key: Some(key_name(&config.step_key)),
+            rewind_key: Some(key_name(&config.rewind_key)),
+            fast_forward_key: Some(key_name(&config.fast_forward_key)),
+            quirks: config.quirks.and_then(|quirks| {
+                QUIRK_PROFILES
+                    .into_iter()
+                    .find(|name| Quirks::profile(name) == Some(quirks))
+                    .map(String::from)
+            }),
+            key_mapping: config.key_mapping,
+            keys: TomlKeys::from_keys(&config.keys),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct TomlKeys {
     key_0: String,
@@ -46,205 +196,329 @@ pub struct TomlKeys {
     key_f: String,
 }
 
+/// Looks up a quirk profile, listing the valid names if there is none called `name`.
+pub fn quirk_profile(name: &str) -> anyhow::Result<Quirks> {
+    Quirks::profile(name).ok_or_else(|| {
+        anyhow!(
+            "Unknown quirk profile \"{}\", valid profiles are {}.",
+            name,
+            QUIRK_PROFILES.join(", ")
+        )
+    })
+}
+
+fn key_or_default(key: &Option<String>, default: VirtualKeyCode) -> anyhow::Result<VirtualKeyCode> {
+    key.as_deref().map_or(Ok(default), str_to_virtkeycode)
+}
+
+/// Parses a physical key named like winit's `VirtualKeyCode`, e.g. `"Key1"` or `"F5"`.
+fn str_to_virtkeycode(s: &str) -> anyhow::Result<VirtualKeyCode> {
+    match s {
+        "Key1" => Ok(VirtualKeyCode::Key1),
+        "Key2" => Ok(VirtualKeyCode::Key2),
+        "Key3" => Ok(VirtualKeyCode::Key3),
+        "Key4" => Ok(VirtualKeyCode::Key4),
+        "Key5" => Ok(VirtualKeyCode::Key5),
+        "Key6" => Ok(VirtualKeyCode::Key6),
+        "Key7" => Ok(VirtualKeyCode::Key7),
+        "Key8" => Ok(VirtualKeyCode::Key8),
+        "Key9" => Ok(VirtualKeyCode::Key9),
+        "Key0" => Ok(VirtualKeyCode::Key0),
+        "A" => Ok(VirtualKeyCode::A),
+        "B" => Ok(VirtualKeyCode::B),
+        "C" => Ok(VirtualKeyCode::C),
+        "D" => Ok(VirtualKeyCode::D),
+        "E" => Ok(VirtualKeyCode::E),
+        "F" => Ok(VirtualKeyCode::F),
+        "G" => Ok(VirtualKeyCode::G),
+        "H" => Ok(VirtualKeyCode::H),
+        "I" => Ok(VirtualKeyCode::I),
+        "J" => Ok(VirtualKeyCode::J),
+        "K" => Ok(VirtualKeyCode::K),
+        "L" => Ok(VirtualKeyCode::L),
+        "M" => Ok(VirtualKeyCode::M),
+        "N" => Ok(VirtualKeyCode::N),
+        "O" => Ok(VirtualKeyCode::O),
+        "P" => Ok(VirtualKeyCode::P),
+        "Q" => Ok(VirtualKeyCode::Q),
+        "R" => Ok(VirtualKeyCode::R),
+        "S" => Ok(VirtualKeyCode::S),
+        "T" => Ok(VirtualKeyCode::T),
+        "U" => Ok(VirtualKeyCode::U),
+        "V" => Ok(VirtualKeyCode::V),
+        "W" => Ok(VirtualKeyCode::W),
+        "X" => Ok(VirtualKeyCode::X),
+        "Y" => Ok(VirtualKeyCode::Y),
+        "Z" => Ok(VirtualKeyCode::Z),
+        "Escape" => Ok(VirtualKeyCode::Escape),
+        "F1" => Ok(VirtualKeyCode::F1),
+        "F2" => Ok(VirtualKeyCode::F2),
+        "F3" => Ok(VirtualKeyCode::F3),
+        "F4" => Ok(VirtualKeyCode::F4),
+        "F5" => Ok(VirtualKeyCode::F5),
+        "F6" => Ok(VirtualKeyCode::F6),
+        "F7" => Ok(VirtualKeyCode::F7),
+        "F8" => Ok(VirtualKeyCode::F8),
+        "F9" => Ok(VirtualKeyCode::F9),
+        "F10" => Ok(VirtualKeyCode::F10),
+        "F11" => Ok(VirtualKeyCode::F11),
+        "F12" => Ok(VirtualKeyCode::F12),
+        "F13" => Ok(VirtualKeyCode::F13),
+        "F14" => Ok(VirtualKeyCode::F14),
+        "F15" => Ok(VirtualKeyCode::F15),
+        "F16" => Ok(VirtualKeyCode::F16),
+        "F17" => Ok(VirtualKeyCode::F17),
+        "F18" => Ok(VirtualKeyCode::F18),
+        "F19" => Ok(VirtualKeyCode::F19),
+        "F20" => Ok(VirtualKeyCode::F20),
+        "F21" => Ok(VirtualKeyCode::F21),
+        "F22" => Ok(VirtualKeyCode::F22),
+        "F23" => Ok(VirtualKeyCode::F23),
+        "F24" => Ok(VirtualKeyCode::F24),
+        "Snapshot" => Ok(VirtualKeyCode::Snapshot),
+        "Scroll" => Ok(VirtualKeyCode::Scroll),
+        "Pause" => Ok(VirtualKeyCode::Pause),
+        "Insert" => Ok(VirtualKeyCode::Insert),
+        "Home" => Ok(VirtualKeyCode::Home),
+        "Delete" => Ok(VirtualKeyCode::Delete),
+        "End" => Ok(VirtualKeyCode::End),
+        "PageDown" => Ok(VirtualKeyCode::PageDown),
+        "PageUp" => Ok(VirtualKeyCode::PageUp),
+        "Left" => Ok(VirtualKeyCode::Left),
+        "Up" => Ok(VirtualKeyCode::Up),
+        "Right" => Ok(VirtualKeyCode::Right),
+        "Down" => Ok(VirtualKeyCode::Down),
+        "Back" => Ok(VirtualKeyCode::Back),
+        "Return" => Ok(VirtualKeyCode::Return),
+        "Space" => Ok(VirtualKeyCode::Space),
+        "Compose" => Ok(VirtualKeyCode::Compose),
+        "Caret" => Ok(VirtualKeyCode::Caret),
+        "Numlock" => Ok(VirtualKeyCode::Numlock),
+        "Numpad0" => Ok(VirtualKeyCode::Numpad0),
+        "Numpad1" => Ok(VirtualKeyCode::Numpad1),
+        "Numpad2" => Ok(VirtualKeyCode::Numpad2),
+        "Numpad3" => Ok(VirtualKeyCode::Numpad3),
+        "Numpad4" => Ok(VirtualKeyCode::Numpad4),
+        "Numpad5" => Ok(VirtualKeyCode::Numpad5),
+        "Numpad6" => Ok(VirtualKeyCode::Numpad6),
+        "Numpad7" => Ok(VirtualKeyCode::Numpad7),
+        "Numpad8" => Ok(VirtualKeyCode::Numpad8),
+        "Numpad9" => Ok(VirtualKeyCode::Numpad9),
+        "NumpadAdd" => Ok(VirtualKeyCode::NumpadAdd),
+        "NumpadDivide" => Ok(VirtualKeyCode::NumpadDivide),
+        "NumpadDecimal" => Ok(VirtualKeyCode::NumpadDecimal),
+        "NumpadComma" => Ok(VirtualKeyCode::NumpadComma),
+        "NumpadEnter" => Ok(VirtualKeyCode::NumpadEnter),
+        "NumpadEquals" => Ok(VirtualKeyCode::NumpadEquals),
+        "NumpadMultiply" => Ok(VirtualKeyCode::NumpadMultiply),
+        "NumpadSubtract" => Ok(VirtualKeyCode::NumpadSubtract),
+        "AbntC1" => Ok(VirtualKeyCode::AbntC1),
+        "AbntC2" => Ok(VirtualKeyCode::AbntC2),
+        "Apostrophe" => Ok(VirtualKeyCode::Apostrophe),
+        "Apps" => Ok(VirtualKeyCode::Apps),
+        "Asterisk" => Ok(VirtualKeyCode::Asterisk),
+        "At" => Ok(VirtualKeyCode::At),
+        "Ax" => Ok(VirtualKeyCode::Ax),
+        "Backslash" => Ok(VirtualKeyCode::Backslash),
+        "Calculator" => Ok(VirtualKeyCode::Calculator),
+        "Capital" => Ok(VirtualKeyCode::Capital),
+        "Colon" => Ok(VirtualKeyCode::Colon),
+        "Comma" => Ok(VirtualKeyCode::Comma),
+        "Convert" => Ok(VirtualKeyCode::Convert),
+        "Equals" => Ok(VirtualKeyCode::Equals),
+        "Grave" => Ok(VirtualKeyCode::Grave),
+        "Kana" => Ok(VirtualKeyCode::Kana),
+        "Kanji" => Ok(VirtualKeyCode::Kanji),
+        "LAlt" => Ok(VirtualKeyCode::LAlt),
+        "LBracket" => Ok(VirtualKeyCode::LBracket),
+        "LControl" => Ok(VirtualKeyCode::LControl),
+        "LShift" => Ok(VirtualKeyCode::LShift),
+        "LWin" => Ok(VirtualKeyCode::LWin),
+        "Mail" => Ok(VirtualKeyCode::Mail),
+        "MediaSelect" => Ok(VirtualKeyCode::MediaSelect),
+        "MediaStop" => Ok(VirtualKeyCode::MediaStop),
+        "Minus" => Ok(VirtualKeyCode::Minus),
+        "Mute" => Ok(VirtualKeyCode::Mute),
+        "MyComputer" => Ok(VirtualKeyCode::MyComputer),
+        "NavigateForward" => Ok(VirtualKeyCode::NavigateForward),
+        "NavigateBackward" => Ok(VirtualKeyCode::NavigateBackward),
+        "NextTrack" => Ok(VirtualKeyCode::NextTrack),
+        "NoConvert" => Ok(VirtualKeyCode::NoConvert),
+        "OEM102" => Ok(VirtualKeyCode::OEM102),
+        "Period" => Ok(VirtualKeyCode::Period),
+        "PlayPause" => Ok(VirtualKeyCode::PlayPause),
+        "Plus" => Ok(VirtualKeyCode::Plus),
+        "Power" => Ok(VirtualKeyCode::Power),
+        "PrevTrack" => Ok(VirtualKeyCode::PrevTrack),
+        "RAlt" => Ok(VirtualKeyCode::RAlt),
+        "RBracket" => Ok(VirtualKeyCode::RBracket),
+        "RControl" => Ok(VirtualKeyCode::RControl),
+        "RShift" => Ok(VirtualKeyCode::RShift),
+        "RWin" => Ok(VirtualKeyCode::RWin),
+        "Semicolon" => Ok(VirtualKeyCode::Semicolon),
+        "Slash" => Ok(VirtualKeyCode::Slash),
+        "Sleep" => Ok(VirtualKeyCode::Sleep),
+        "Stop" => Ok(VirtualKeyCode::Stop),
+        "Sysrq" => Ok(VirtualKeyCode::Sysrq),
+        "Tab" => Ok(VirtualKeyCode::Tab),
+        "Underline" => Ok(VirtualKeyCode::Underline),
+        "Unlabeled" => Ok(VirtualKeyCode::Unlabeled),
+        "VolumeDown" => Ok(VirtualKeyCode::VolumeDown),
+        "VolumeUp" => Ok(VirtualKeyCode::VolumeUp),
+        "Wake" => Ok(VirtualKeyCode::Wake),
+        "WebBack" => Ok(VirtualKeyCode::WebBack),
+        "WebFavorites" => Ok(VirtualKeyCode::WebFavorites),
+        "WebForward" => Ok(VirtualKeyCode::WebForward),
+        "WebHome" => Ok(VirtualKeyCode::WebHome),
+        "WebRefresh" => Ok(VirtualKeyCode::WebRefresh),
+        "WebSearch" => Ok(VirtualKeyCode::WebSearch),
+        "WebStop" => Ok(VirtualKeyCode::WebStop),
+        "Yen" => Ok(VirtualKeyCode::Yen),
+        "Copy" => Ok(VirtualKeyCode::Copy),
+        "Paste" => Ok(VirtualKeyCode::Paste),
+        "Cut" => Ok(VirtualKeyCode::Cut),
+        _ => Err(anyhow!("Invalid Keycode: {}.", s)),
+    }
+}
+
 impl TomlKeys {
-    fn to_keys(&self) -> anyhow::Result<HashMap<VirtualKeyCode, emulator::Key>> {
-        fn str_to_virtkeycode(s: &str) -> anyhow::Result<VirtualKeyCode> {
-            match s {
-                "Key1" => Ok(VirtualKeyCode::Key1),
-                "Key2" => Ok(VirtualKeyCode::Key2),
-                "Key3" => Ok(VirtualKeyCode::Key3),
-                "Key4" => Ok(VirtualKeyCode::Key4),
-                "Key5" => Ok(VirtualKeyCode::Key5),
-                "Key6" => Ok(VirtualKeyCode::Key6),
-                "Key7" => Ok(VirtualKeyCode::Key7),
-                "Key8" => Ok(VirtualKeyCode::Key8),
-                "Key9" => Ok(VirtualKeyCode::Key9),
-                "Key0" => Ok(VirtualKeyCode::Key0),
-                "A" => Ok(VirtualKeyCode::A),
-                "B" => Ok(VirtualKeyCode::B),
-                "C" => Ok(VirtualKeyCode::C),
-                "D" => Ok(VirtualKeyCode::D),
-                "E" => Ok(VirtualKeyCode::E),
-                "F" => Ok(VirtualKeyCode::F),
-                "G" => Ok(VirtualKeyCode::G),
-                "H" => Ok(VirtualKeyCode::H),
-                "I" => Ok(VirtualKeyCode::I),
-                "J" => Ok(VirtualKeyCode::J),
-                "K" => Ok(VirtualKeyCode::K),
-                "L" => Ok(VirtualKeyCode::L),
-                "M" => Ok(VirtualKeyCode::M),
-                "N" => Ok(VirtualKeyCode::N),
-                "O" => Ok(VirtualKeyCode::O),
-                "P" => Ok(VirtualKeyCode::P),
-                "Q" => Ok(VirtualKeyCode::Q),
-                "R" => Ok(VirtualKeyCode::R),
-                "S" => Ok(VirtualKeyCode::S),
-                "T" => Ok(VirtualKeyCode::T),
-                "U" => Ok(VirtualKeyCode::U),
-                "V" => Ok(VirtualKeyCode::V),
-                "W" => Ok(VirtualKeyCode::W),
-                "X" => Ok(VirtualKeyCode::X),
-                "Y" => Ok(VirtualKeyCode::Y),
-                "Z" => Ok(VirtualKeyCode::Z),
-                "Escape" => Ok(VirtualKeyCode::Escape),
-                "F1" => Ok(VirtualKeyCode::F1),
-                "F2" => Ok(VirtualKeyCode::F2),
-                "F3" => Ok(VirtualKeyCode::F3),
-                "F4" => Ok(VirtualKeyCode::F4),
-                "F5" => Ok(VirtualKeyCode::F5),
-                "F6" => Ok(VirtualKeyCode::F6),
-                "F7" => Ok(VirtualKeyCode::F7),
-                "F8" => Ok(VirtualKeyCode::F8),
-                "F9" => Ok(VirtualKeyCode::F9),
-                "F10" => Ok(VirtualKeyCode::F10),
-                "F11" => Ok(VirtualKeyCode::F11),
-                "F12" => Ok(VirtualKeyCode::F12),
-                "F13" => Ok(VirtualKeyCode::F13),
-                "F14" => Ok(VirtualKeyCode::F14),
-                "F15" => Ok(VirtualKeyCode::F15),
-                "F16" => Ok(VirtualKeyCode::F16),
-                "F17" => Ok(VirtualKeyCode::F17),
-                "F18" => Ok(VirtualKeyCode::F18),
-                "F19" => Ok(VirtualKeyCode::F19),
-                "F20" => Ok(VirtualKeyCode::F20),
-                "F21" => Ok(VirtualKeyCode::F21),
-                "F22" => Ok(VirtualKeyCode::F22),
-                "F23" => Ok(VirtualKeyCode::F23),
-                "F24" => Ok(VirtualKeyCode::F24),
-                "Snapshot" => Ok(VirtualKeyCode::Snapshot),
-                "Scroll" => Ok(VirtualKeyCode::Scroll),
-                "Pause" => Ok(VirtualKeyCode::Pause),
-                "Insert" => Ok(VirtualKeyCode::Insert),
-                "Home" => Ok(VirtualKeyCode::Home),
-                "Delete" => Ok(VirtualKeyCode::Delete),
-                "End" => Ok(VirtualKeyCode::End),
-                "PageDown" => Ok(VirtualKeyCode::PageDown),
-                "PageUp" => Ok(VirtualKeyCode::PageUp),
-                "Left" => Ok(VirtualKeyCode::Left),
-                "Up" => Ok(VirtualKeyCode::Up),
-                "Right" => Ok(VirtualKeyCode::Right),
-                "Down" => Ok(VirtualKeyCode::Down),
-                "Back" => Ok(VirtualKeyCode::Back),
-                "Return" => Ok(VirtualKeyCode::Return),
-                "Space" => Ok(VirtualKeyCode::Space),
-                "Compose" => Ok(VirtualKeyCode::Compose),
-                "Caret" => Ok(VirtualKeyCode::Caret),
-                "Numlock" => Ok(VirtualKeyCode::Numlock),
-                "Numpad0" => Ok(VirtualKeyCode::Numpad0),
-                "Numpad1" => Ok(VirtualKeyCode::Numpad1),
-                "Numpad2" => Ok(VirtualKeyCode::Numpad2),
-                "Numpad3" => Ok(VirtualKeyCode::Numpad3),
-                "Numpad4" => Ok(VirtualKeyCode::Numpad4),
-                "Numpad5" => Ok(VirtualKeyCode::Numpad5),
-                "Numpad6" => Ok(VirtualKeyCode::Numpad6),
-                "Numpad7" => Ok(VirtualKeyCode::Numpad7),
-                "Numpad8" => Ok(VirtualKeyCode::Numpad8),
-                "Numpad9" => Ok(VirtualKeyCode::Numpad9),
-                "NumpadAdd" => Ok(VirtualKeyCode::NumpadAdd),
-                "NumpadDivide" => Ok(VirtualKeyCode::NumpadDivide),
-                "NumpadDecimal" => Ok(VirtualKeyCode::NumpadDecimal),
-                "NumpadComma" => Ok(VirtualKeyCode::NumpadComma),
-                "NumpadEnter" => Ok(VirtualKeyCode::NumpadEnter),
-                "NumpadEquals" => Ok(VirtualKeyCode::NumpadEquals),
-                "NumpadMultiply" => Ok(VirtualKeyCode::NumpadMultiply),
-                "NumpadSubtract" => Ok(VirtualKeyCode::NumpadSubtract),
-                "AbntC1" => Ok(VirtualKeyCode::AbntC1),
-                "AbntC2" => Ok(VirtualKeyCode::AbntC2),
-                "Apostrophe" => Ok(VirtualKeyCode::Apostrophe),
-                "Apps" => Ok(VirtualKeyCode::Apps),
-                "Asterisk" => Ok(VirtualKeyCode::Asterisk),
-                "At" => Ok(VirtualKeyCode::At),
-                "Ax" => Ok(VirtualKeyCode::Ax),
-                "Backslash" => Ok(VirtualKeyCode::Backslash),
-                "Calculator" => Ok(VirtualKeyCode::Calculator),
-                "Capital" => Ok(VirtualKeyCode::Capital),
-                "Colon" => Ok(VirtualKeyCode::Colon),
-                "Comma" => Ok(VirtualKeyCode::Comma),
-                "Convert" => Ok(VirtualKeyCode::Convert),
-                "Equals" => Ok(VirtualKeyCode::Equals),
-                "Grave" => Ok(VirtualKeyCode::Grave),
-                "Kana" => Ok(VirtualKeyCode::Kana),
-                "Kanji" => Ok(VirtualKeyCode::Kanji),
-                "LAlt" => Ok(VirtualKeyCode::LAlt),
-                "LBracket" => Ok(VirtualKeyCode::LBracket),
-                "LControl" => Ok(VirtualKeyCode::LControl),
-                "LShift" => Ok(VirtualKeyCode::LShift),
-                "LWin" => Ok(VirtualKeyCode::LWin),
-                "Mail" => Ok(VirtualKeyCode::Mail),
-                "MediaSelect" => Ok(VirtualKeyCode::MediaSelect),
-                "MediaStop" => Ok(VirtualKeyCode::MediaStop),
-                "Minus" => Ok(VirtualKeyCode::Minus),
-                "Mute" => Ok(VirtualKeyCode::Mute),
-                "MyComputer" => Ok(VirtualKeyCode::MyComputer),
-                "NavigateForward" => Ok(VirtualKeyCode::NavigateForward),
-                "NavigateBackward" => Ok(VirtualKeyCode::NavigateBackward),
-                "NextTrack" => Ok(VirtualKeyCode::NextTrack),
-                "NoConvert" => Ok(VirtualKeyCode::NoConvert),
-                "OEM102" => Ok(VirtualKeyCode::OEM102),
-                "Period" => Ok(VirtualKeyCode::Period),
-                "PlayPause" => Ok(VirtualKeyCode::PlayPause),
-                "Plus" => Ok(VirtualKeyCode::Plus),
-                "Power" => Ok(VirtualKeyCode::Power),
-                "PrevTrack" => Ok(VirtualKeyCode::PrevTrack),
-                "RAlt" => Ok(VirtualKeyCode::RAlt),
-                "RBracket" => Ok(VirtualKeyCode::RBracket),
-                "RControl" => Ok(VirtualKeyCode::RControl),
-                "RShift" => Ok(VirtualKeyCode::RShift),
-                "RWin" => Ok(VirtualKeyCode::RWin),
-                "Semicolon" => Ok(VirtualKeyCode::Semicolon),
-                "Slash" => Ok(VirtualKeyCode::Slash),
-                "Sleep" => Ok(VirtualKeyCode::Sleep),
-                "Stop" => Ok(VirtualKeyCode::Stop),
-                "Sysrq" => Ok(VirtualKeyCode::Sysrq),
-                "Tab" => Ok(VirtualKeyCode::Tab),
-                "Underline" => Ok(VirtualKeyCode::Underline),
-                "Unlabeled" => Ok(VirtualKeyCode::Unlabeled),
-                "VolumeDown" => Ok(VirtualKeyCode::VolumeDown),
-                "VolumeUp" => Ok(VirtualKeyCode::VolumeUp),
-                "Wake" => Ok(VirtualKeyCode::Wake),
-                "WebBack" => Ok(VirtualKeyCode::WebBack),
-                "WebFavorites" => Ok(VirtualKeyCode::WebFavorites),
-                "WebForward" => Ok(VirtualKeyCode::WebForward),
-                "WebHome" => Ok(VirtualKeyCode::WebHome),
-                "WebRefresh" => Ok(VirtualKeyCode::WebRefresh),
-                "WebSearch" => Ok(VirtualKeyCode::WebSearch),
-                "WebStop" => Ok(VirtualKeyCode::WebStop),
-                "Yen" => Ok(VirtualKeyCode::Yen),
-                "Copy" => Ok(VirtualKeyCode::Copy),
-                "Paste" => Ok(VirtualKeyCode::Paste),
-                "Cut" => Ok(VirtualKeyCode::Cut),
-                _ => Err(anyhow!("Invalid Keycode: {}.", s)),
-            }
+    /// The inverse of `to_keys`, unmapped keys get an empty name.
+    fn from_keys(keys: &HashMap<VirtualKeyCode, Key>) -> Self {
+        let name = |key: Key| {
+            keys.iter()
+                .find(|(_, &k)| k == key)
+                .map(|(code, _)| format!("{:?}", code))
+                .unwrap_or_default()
+        };
+        Self {
+            key_0: name(Key::Key0),
+            key_1: name(Key::Key1),
+            key_2: name(Key::Key2),
+            key_3: name(Key::Key3),
+            key_4: name(Key::Key4),
+            key_5: name(Key::Key5),
+            key_6: name(Key::Key6),
+            key_7: name(Key::Key7),
+            key_8: name(Key::Key8),
+            key_9: name(Key::Key9),
+            key_a: name(Key::KeyA),
+            key_b: name(Key::KeyB),
+            key_c: name(Key::KeyC),
+            key_d: name(Key::KeyD),
+            key_e: name(Key::KeyE),
+            key_f: name(Key::KeyF),
         }
+    }
 
-        let mut keys = HashMap::with_capacity(15);
-        keys.insert(str_to_virtkeycode(&self.key_0)?, emulator::Key::Key0);
-        keys.insert(str_to_virtkeycode(&self.key_1)?, emulator::Key::Key1);
-        keys.insert(str_to_virtkeycode(&self.key_2)?, emulator::Key::Key2);
-        keys.insert(str_to_virtkeycode(&self.key_3)?, emulator::Key::Key3);
-        keys.insert(str_to_virtkeycode(&self.key_4)?, emulator::Key::Key4);
-        keys.insert(str_to_virtkeycode(&self.key_5)?, emulator::Key::Key5);
-        keys.insert(str_to_virtkeycode(&self.key_6)?, emulator::Key::Key6);
-        keys.insert(str_to_virtkeycode(&self.key_7)?, emulator::Key::Key7);
-        keys.insert(str_to_virtkeycode(&self.key_8)?, emulator::Key::Key8);
-        keys.insert(str_to_virtkeycode(&self.key_9)?, emulator::Key::Key9);
-        keys.insert(str_to_virtkeycode(&self.key_a)?, emulator::Key::KeyA);
-        keys.insert(str_to_virtkeycode(&self.key_b)?, emulator::Key::KeyB);
-        keys.insert(str_to_virtkeycode(&self.key_c)?, emulator::Key::KeyC);
-        keys.insert(str_to_virtkeycode(&self.key_d)?, emulator::Key::KeyD);
-        keys.insert(str_to_virtkeycode(&self.key_e)?, emulator::Key::KeyE);
-        keys.insert(str_to_virtkeycode(&self.key_f)?, emulator::Key::KeyF);
+    /// Fails if a name is invalid or two CHIP-8 keys are bound to the same physical key.
+    fn to_keys(&self) -> anyhow::Result<HashMap<VirtualKeyCode, emulator::Key>> {
+        let bindings = [
+            ("key_0", &self.key_0, emulator::Key::Key0),
+            ("key_1", &self.key_1, emulator::Key::Key1),
+            ("key_2", &self.key_2, emulator::Key::Key2),
+            ("key_3", &self.key_3, emulator::Key::Key3),
+            ("key_4", &self.key_4, emulator::Key::Key4),
+            ("key_5", &self.key_5, emulator::Key::Key5),
+            ("key_6", &self.key_6, emulator::Key::Key6),
+            ("key_7", &self.key_7, emulator::Key::Key7),
+            ("key_8", &self.key_8, emulator::Key::Key8),
+            ("key_9", &self.key_9, emulator::Key::Key9),
+            ("key_a", &self.key_a, emulator::Key::KeyA),
+            ("key_b", &self.key_b, emulator::Key::KeyB),
+            ("key_c", &self.key_c, emulator::Key::KeyC),
+            ("key_d", &self.key_d, emulator::Key::KeyD),
+            ("key_e", &self.key_e, emulator::Key::KeyE),
+            ("key_f", &self.key_f, emulator::Key::KeyF),
+        ];
 
+        let mut keys = HashMap::with_capacity(bindings.len());
+        let mut bound_by = HashMap::with_capacity(bindings.len());
+        for (field, name, key) in bindings {
+            let code = str_to_virtkeycode(name)?;
+            if let Some(other) = bound_by.insert(code, field) {
+                return Err(anyhow!(
+                    "{} and {} are both bound to {}.",
+                    other,
+                    field,
+                    name
+                ));
+            }
+            keys.insert(code, key);
+        }
         Ok(keys)
     }
 }
 
+/// How the keys in `[keys]` are found on the keyboard.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyMapping {
+    /// by the character on the key in the current layout
+    #[default]
+    Virtual,
+    /// by the position of the key, named as on a US QWERTY keyboard
+    Scancode,
+}
+
 pub struct Config {
-    pub pixel_size: u32,
+    pub pixel_width: u32,
+    pub pixel_height: u32,
     pub on_color: (u8, u8, u8),
+    /// background, black unless configured
+    pub off_color: (u8, u8, u8),
+    /// dims every other row of screen pixels like a CRT
+    pub scanlines: bool,
+    pub collision_sound: bool,
+    /// presses per second while an auto-fire key is held, 0 = off
+    pub autofire_rate: u32,
+    pub autofire_keys: HashSet<Key>,
+    /// sound file looped while beeping instead of the default tone
+    pub beep_sound: Option<PathBuf>,
+    /// the default tone in Hz, within `AUDIBLE_FREQUENCIES`
+    pub beep_frequency: f32,
+    pub beep_waveform: Waveform,
+    /// loudness of all sounds from 0 to 1, clamped by `Beeper::set_volume`
+    pub volume: f32,
+    /// pauses and resumes the emulation
+    pub pause_key: VirtualKeyCode,
+    /// restarts the running ROM
+    pub reset_key: VirtualKeyCode,
+    /// raise and lower the clock speed while running
+    pub speed_up_key: VirtualKeyCode,
+    pub speed_down_key: VirtualKeyCode,
+    /// switches between a window and borderless fullscreen
+    pub fullscreen_key: VirtualKeyCode,
+    /// executes the next instruction with `--step`
+    pub step_key: VirtualKeyCode,
+    /// runs the emulation backwards while held
+    pub rewind_key: VirtualKeyCode,
+    /// runs the emulation several times as fast while held
+    pub fast_forward_key: VirtualKeyCode,
+    /// quirks of a profile in `QUIRK_PROFILES`, replacing the default quirks
+    pub quirks: Option<Quirks>,
+    pub key_mapping: KeyMapping,
     pub keys: HashMap<VirtualKeyCode, Key>,
 }
 
+impl Config {
+    /// The configuration as a config file, with every setting spelled out.
+    pub fn to_toml(&self) -> anyhow::Result<String> {
+        Ok(toml::to_string(&TomlConfig::from(self))?)
+    }
+
+    /// Maps the physical keys for which `is_down` returns true to emulator keys.
+    pub fn pressed_keys<F>(&self, is_down: F) -> HashSet<Key>
+    where
+        F: Fn(VirtualKeyCode) -> bool,
+    {
+        self.keys
+            .iter()
+            .filter(|(&c, _)| is_down(c))
+            .map(|(_, &k)| k)
+            .collect()
+    }
+}
+
 impl Default for Config {
     fn default() -> Self {
         let keys = HashMap::from([
@@ -267,18 +541,51 @@ impl Default for Config {
         ]);
 
         Self {
-            pixel_size: 10,
+            pixel_width: 10,
+            pixel_height: 10,
             on_color: (0, 0, 255),
+            off_color: (0, 0, 0),
+            scanlines: false,
+            collision_sound: false,
+            autofire_rate: 0,
+            autofire_keys: HashSet::new(),
+            beep_sound: None,
+            beep_frequency: beeper::DEFAULT_FREQUENCY,
+            beep_waveform: Waveform::default(),
+            volume: 1.0,
+            pause_key: DEFAULT_PAUSE_KEY,
+            reset_key: DEFAULT_RESET_KEY,
+            speed_up_key: DEFAULT_SPEED_UP_KEY,
+            speed_down_key: DEFAULT_SPEED_DOWN_KEY,
+            fullscreen_key: DEFAULT_FULLSCREEN_KEY,
+            step_key: DEFAULT_STEP_KEY,
+            rewind_key: DEFAULT_REWIND_KEY,
+            fast_forward_key: DEFAULT_FAST_FORWARD_KEY,
+            quirks: None,
+            key_mapping: KeyMapping::default(),
             keys,
         }
     }
 }
 
-pub fn load() -> anyhow::Result<Config> {
-    fn use_default_config() -> anyhow::Result<Config> {
-        println!("No config file found, using default configuration.");
-        Ok(Config::default())
+/// Writes the default configuration to `path` as a template to edit.
+fn write_default_config(path: &Path) -> anyhow::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
     }
+    fs::write(path, Config::default().to_toml()?)?;
+    Ok(())
+}
+
+/// `quiet` suppresses the notice that the default configuration is used.
+/// Without a config file the default configuration is written to where it is looked for.
+pub fn load(quiet: bool) -> anyhow::Result<Config> {
+    let use_default_config = || {
+        if !quiet {
+            println!("No config file found, using default configuration.");
+        }
+        Ok(Config::default())
+    };
 
     if let Some(app_dirs) = AppDirs::new(Some("chip8-emulator"), true) {
         let config_file_path = app_dirs.config_dir.join("config.toml");
@@ -291,9 +598,260 @@ pub fn load() -> anyhow::Result<Config> {
                 toml::from_str(&toml_str).context("Could not parse configuration file.")?;
             toml_comfig.to_config()
         } else {
-            use_default_config()
+            match write_default_config(&config_file_path) {
+                Result::Ok(()) if !quiet => println!(
+                    "No config file found, wrote the default configuration to {}.",
+                    config_file_path.display()
+                ),
+                Result::Ok(()) => {}
+                Err(e) => eprintln!(
+                    "Could not write default configuration to {}: {}.",
+                    config_file_path.display(),
+                    e
+                ),
+            }
+            Ok(Config::default())
         }
     } else {
         use_default_config()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pixel_width_and_height() {
+        let toml_str = include_str!("config.toml");
+        let config = toml::from_str::<TomlConfig>(toml_str)
+            .unwrap()
+            .to_config()
+            .unwrap();
+        assert_eq!((config.pixel_width, config.pixel_height), (15, 15));
+
+        let toml_str = format!("pixel_width = 20\n{}", toml_str);
+        let config = toml::from_str::<TomlConfig>(&toml_str)
+            .unwrap()
+            .to_config()
+            .unwrap();
+        assert_eq!((config.pixel_width, config.pixel_height), (20, 15));
+
+        let toml_str = format!("pixel_height = 0\n{}", toml_str);
+        let toml_config = toml::from_str::<TomlConfig>(&toml_str).unwrap();
+        assert!(toml_config.to_config().is_err());
+    }
+
+    #[test]
+    fn test_pixel_aspect() {
+        let toml_str = include_str!("config.toml");
+        let toml_str = format!("pixel_aspect = [2, 1]\n{}", toml_str);
+        let config = toml::from_str::<TomlConfig>(&toml_str)
+            .unwrap()
+            .to_config()
+            .unwrap();
+        assert_eq!((config.pixel_width, config.pixel_height), (30, 15));
+
+        // an explicit pixel_height wins over the ratio
+        let with_height = format!("pixel_height = 10\n{}", toml_str);
+        let config = toml::from_str::<TomlConfig>(&with_height)
+            .unwrap()
+            .to_config()
+            .unwrap();
+        assert_eq!((config.pixel_width, config.pixel_height), (30, 10));
+
+        let toml_str = toml_str.replace("[2, 1]", "[2, 0]");
+        let e = toml::from_str::<TomlConfig>(&toml_str)
+            .unwrap()
+            .to_config()
+            .err()
+            .unwrap();
+        assert_eq!(e.to_string(), "Pixel aspect ratio must not contain 0.");
+    }
+
+    #[test]
+    fn test_beep_tone() {
+        let toml_str = include_str!("config.toml");
+        let config = toml::from_str::<TomlConfig>(toml_str)
+            .unwrap()
+            .to_config()
+            .unwrap();
+        assert_eq!(config.beep_frequency, beeper::DEFAULT_FREQUENCY);
+        assert_eq!(config.beep_waveform, Waveform::Sine);
+
+        let with_tone = format!(
+            "beep_frequency = 440.0\nbeep_waveform = \"square\"\n{}",
+            toml_str
+        );
+        let config = toml::from_str::<TomlConfig>(&with_tone)
+            .unwrap()
+            .to_config()
+            .unwrap();
+        assert_eq!(config.beep_frequency, 440.0);
+        assert_eq!(config.beep_waveform, Waveform::Square);
+
+        for frequency in ["10.0", "25000.0"] {
+            let toml_str = format!("beep_frequency = {}\n{}", frequency, toml_str);
+            let toml_config = toml::from_str::<TomlConfig>(&toml_str).unwrap();
+            assert!(toml_config.to_config().is_err(), "{}", frequency);
+        }
+        let toml_str = format!("beep_waveform = \"noise\"\n{}", toml_str);
+        assert!(toml::from_str::<TomlConfig>(&toml_str).is_err());
+    }
+
+    #[test]
+    fn test_quirk_profile() {
+        let toml_str = include_str!("config.toml");
+        let config = toml::from_str::<TomlConfig>(toml_str)
+            .unwrap()
+            .to_config()
+            .unwrap();
+        assert_eq!(config.quirks, None);
+
+        let with_profile = format!("quirks = \"schip\"\n{}", toml_str);
+        let config = toml::from_str::<TomlConfig>(&with_profile)
+            .unwrap()
+            .to_config()
+            .unwrap();
+        assert_eq!(config.quirks, Some(Quirks::schip()));
+        let round_trip = toml::from_str::<TomlConfig>(&config.to_toml().unwrap())
+            .unwrap()
+            .to_config()
+            .unwrap();
+        assert_eq!(round_trip.quirks, Some(Quirks::schip()));
+
+        let toml_str = format!("quirks = \"superchip\"\n{}", toml_str);
+        let e = toml::from_str::<TomlConfig>(&toml_str)
+            .unwrap()
+            .to_config()
+            .err()
+            .unwrap();
+        assert_eq!(
+            e.to_string(),
+            "Unknown quirk profile \"superchip\", valid profiles are chip8, schip, xochip."
+        );
+    }
+
+    #[test]
+    fn test_default_config_round_trip() {
+        let default = Config::default();
+        let toml_str = default.to_toml().unwrap();
+        let config = toml::from_str::<TomlConfig>(&toml_str)
+            .unwrap()
+            .to_config()
+            .unwrap();
+        assert_eq!(config.keys, default.keys);
+        assert_eq!(config.pause_key, default.pause_key);
+        assert_eq!(config.speed_down_key, default.speed_down_key);
+        assert_eq!(config.on_color, default.on_color);
+        assert_eq!(config.scanlines, default.scanlines);
+        assert_eq!(config.beep_frequency, default.beep_frequency);
+        assert_eq!(config.volume, default.volume);
+
+        let path = std::env::temp_dir()
+            .join(format!("chip8-emulator-test-{}", std::process::id()))
+            .join("config.toml");
+        write_default_config(&path).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), toml_str);
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_key_bindings() {
+        let toml_str = include_str!("config.toml");
+        let config = toml::from_str::<TomlConfig>(toml_str)
+            .unwrap()
+            .to_config()
+            .unwrap();
+        assert_eq!(config.keys.len(), 16);
+        assert_eq!(config.keys[&VirtualKeyCode::X], Key::Key0);
+        assert_eq!(config.keys[&VirtualKeyCode::V], Key::KeyF);
+
+        let duplicate = toml_str.replace("key_e = \"F\"", "key_e = \"W\"");
+        let toml_config = toml::from_str::<TomlConfig>(&duplicate).unwrap();
+        let Err(error) = toml_config.to_config() else {
+            panic!("duplicate binding accepted");
+        };
+        assert_eq!(error.to_string(), "key_5 and key_e are both bound to W.");
+
+        let invalid = toml_str.replace("key_e = \"F\"", "key_e = \"Nope\"");
+        let toml_config = toml::from_str::<TomlConfig>(&invalid).unwrap();
+        assert!(toml_config.to_config().is_err());
+    }
+
+    #[test]
+    fn test_key_mapping() {
+        let toml_str = include_str!("config.toml");
+        let config = toml::from_str::<TomlConfig>(toml_str)
+            .unwrap()
+            .to_config()
+            .unwrap();
+        assert_eq!(config.key_mapping, KeyMapping::Virtual);
+
+        let toml_str = format!("key_mapping = \"scancode\"\n{}", toml_str);
+        let config = toml::from_str::<TomlConfig>(&toml_str)
+            .unwrap()
+            .to_config()
+            .unwrap();
+        assert_eq!(config.key_mapping, KeyMapping::Scancode);
+
+        let arrow = toml_str.replace("key_e = \"F\"", "key_e = \"Up\"");
+        let Err(error) = toml::from_str::<TomlConfig>(&arrow).unwrap().to_config() else {
+            panic!("arrow key bound by scancode");
+        };
+        assert_eq!(
+            error.to_string(),
+            "Up has no scancode, only digits and letters can be bound by scancode."
+        );
+    }
+
+    #[test]
+    fn test_control_keys() {
+        let toml_str = include_str!("config.toml");
+        let config = toml::from_str::<TomlConfig>(toml_str)
+            .unwrap()
+            .to_config()
+            .unwrap();
+        assert_eq!(config.pause_key, DEFAULT_PAUSE_KEY);
+        assert_eq!(config.reset_key, DEFAULT_RESET_KEY);
+        assert_eq!(config.speed_up_key, DEFAULT_SPEED_UP_KEY);
+        assert_eq!(config.fullscreen_key, DEFAULT_FULLSCREEN_KEY);
+        assert_eq!(config.step_key, DEFAULT_STEP_KEY);
+        assert_eq!(config.rewind_key, DEFAULT_REWIND_KEY);
+        assert_eq!(config.fast_forward_key, DEFAULT_FAST_FORWARD_KEY);
+
+        let toml_str = format!(
+            "pause_key = \"Space\"\nreset_key = \"Back\"\nspeed_down_key = \"Minus\"\n{}",
+            toml_str
+        );
+        let config = toml::from_str::<TomlConfig>(&toml_str)
+            .unwrap()
+            .to_config()
+            .unwrap();
+        assert_eq!(config.pause_key, VirtualKeyCode::Space);
+        assert_eq!(config.reset_key, VirtualKeyCode::Back);
+        assert_eq!(config.speed_down_key, VirtualKeyCode::Minus);
+
+        let toml_str = format!("pause_key = \"Nope\"\n{}", include_str!("config.toml"));
+        let toml_config = toml::from_str::<TomlConfig>(&toml_str).unwrap();
+        assert!(toml_config.to_config().is_err());
+    }
+
+    #[test]
+    fn test_key_press_reaches_emulator() {
+        let toml_config: TomlConfig = toml::from_str(include_str!("config.toml")).unwrap();
+        let config = toml_config.to_config().unwrap();
+
+        // V0 = 5, I = font "0", skip the draw if key 5 is pressed, then loop forever
+        let program = vec![0x60, 0x05, 0xA0, 0x50, 0xE0, 0x9E, 0xD1, 0x15, 0x12, 0x08];
+
+        for (pressed, expect_drawn) in [(VirtualKeyCode::W, false), (VirtualKeyCode::Q, true)] {
+            let mut emulator = emulator::Emulator::new(400, program.clone()).unwrap();
+            emulator.set_keys_pressed(config.pressed_keys(|c| c == pressed));
+            for _ in 0..4 {
+                emulator.tick().unwrap();
+            }
+            assert_eq!(emulator.get_pixel(0, 0), expect_drawn);
+        }
+    }
+}
@@ -0,0 +1,119 @@
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::Path;
+
+use anyhow::Context;
+
+use chip8_emulator_lib::emulator::Emulator;
+
+use crate::config::Config;
+use crate::pixelbuffer::{apply_scanlines, color};
+use crate::script::InputScript;
+
+/// Runs `frames` frames without a window and writes every redrawn screen to `out`
+/// as `frame_00000.png` and so on, numbered by frame and scaled like the window.
+pub fn run(
+    emulator: &mut Emulator,
+    frames: u64,
+    out: &Path,
+    config: &Config,
+    mut input_script: Option<InputScript>,
+) -> anyhow::Result<()> {
+    fs::create_dir_all(out).context(format!("Could not create {}.", out.display()))?;
+    for _ in 0..frames {
+        let frame = emulator.frame_count();
+        if let Some(input_script) = &mut input_script {
+            emulator.set_keys_pressed(input_script.keys_pressed(frame));
+        }
+        emulator.run_frame()?;
+        if emulator.should_redraw() {
+            let path = out.join(format!("frame_{:05}.png", frame));
+            write_png(&path, emulator, config)
+                .context(format!("Could not write {}.", path.display()))?;
+            emulator.clear_redraw();
+        }
+    }
+    Ok(())
+}
+
+/// The screen as RGBA pixels, each emulator pixel `pixel_width` by `pixel_height` large.
+fn render(emulator: &Emulator, config: &Config) -> (u32, u32, Vec<u8>) {
+    let (width, height) = emulator.resolution();
+    let on_color = [
+        config.on_color.0,
+        config.on_color.1,
+        config.on_color.2,
+        0xff,
+    ];
+    let off_color = [
+        config.off_color.0,
+        config.off_color.1,
+        config.off_color.2,
+        0xff,
+    ];
+    let (pixel_width, pixel_height) = (config.pixel_width as usize, config.pixel_height as usize);
+
+    let mut rgba = Vec::with_capacity(width * pixel_width * height * pixel_height * 4);
+    for y in 0..height {
+        let mut line = Vec::with_capacity(width * pixel_width * 4);
+        for x in 0..width {
+            let level = emulator.get_pixel(x, y) as u8 as f32;
+            for _ in 0..pixel_width {
+                line.extend_from_slice(&color(off_color, on_color, level));
+            }
+        }
+        for _ in 0..pixel_height {
+            rgba.extend_from_slice(&line);
+        }
+    }
+    if config.scanlines {
+        apply_scanlines(&mut rgba, width * pixel_width, off_color);
+    }
+    (
+        (width * pixel_width) as u32,
+        (height * pixel_height) as u32,
+        rgba,
+    )
+}
+
+fn write_png(path: &Path, emulator: &Emulator, config: &Config) -> anyhow::Result<()> {
+    let (width, height, rgba) = render(emulator, config);
+    let mut encoder = png::Encoder::new(BufWriter::new(File::create(path)?), width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.write_header()?.write_image_data(&rgba)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        // draw the top row of font "0" at (0, 0)
+        let mut emulator = Emulator::new(400, vec![0xA0, 0x50, 0xD0, 0x01]).unwrap();
+        emulator.tick().unwrap();
+        emulator.tick().unwrap();
+        let config = Config {
+            pixel_width: 2,
+            pixel_height: 3,
+            on_color: (255, 176, 0),
+            off_color: (60, 30, 10),
+            ..Config::default()
+        };
+
+        let (width, height, rgba) = render(&emulator, &config);
+        assert_eq!((width, height), (128, 96));
+        assert_eq!(rgba.len(), 128 * 96 * 4);
+        let pixel = |x: u32, y: u32| {
+            let i = ((y * width + x) * 4) as usize;
+            rgba[i..i + 4].to_vec()
+        };
+        // font "0" starts with 0xF0
+        assert_eq!(pixel(0, 0), [255, 176, 0, 0xff]);
+        assert_eq!(pixel(7, 2), [255, 176, 0, 0xff]);
+        assert_eq!(pixel(8, 0), [60, 30, 10, 0xff]);
+        assert_eq!(pixel(0, 3), [60, 30, 10, 0xff]);
+    }
+}
@@ -1,32 +1,60 @@
-use rodio::{OutputStream, OutputStreamHandle, Sink};
+use serde::{Deserialize, Serialize};
 
-pub struct Beeper {
-    sink: Sink,
-    _stream: OutputStream,
-    _stream_handle: OutputStreamHandle,
-}
+#[cfg(feature = "audio")]
+mod audio;
+#[cfg(not(feature = "audio"))]
+mod silent;
+
+#[cfg(feature = "audio")]
+pub use audio::Beeper;
+#[cfg(not(feature = "audio"))]
+pub use silent::Beeper;
+
+/// The sound output the emulator drives while the sound timer runs. `Beeper`
+/// plays through the audio device, front-ends may supply their own.
+pub trait AudioBackend {
+    /// The sound timer was set to a nonzero value.
+    fn start(&mut self);
+
+    /// The sound timer ran out, or the program was reset or paused.
+    fn stop(&mut self);
+
+    /// Replaces the tone with an XO-CHIP audio pattern played at `pitch`.
+    fn play_pattern(&mut self, _pattern: [u8; 16], _pitch: u8) {}
 
-impl Beeper {
-    pub fn new() -> Self {
-        let (_stream, _stream_handle) = rodio::OutputStream::try_default().unwrap();
-        let sink = Sink::try_new(&_stream_handle).unwrap();
+    /// Goes back to the tone after `play_pattern`.
+    fn clear_pattern(&mut self) {}
+}
 
-        let source = rodio::source::SineWave::new(680.0);
-        sink.append(source);
-        sink.pause();
+impl AudioBackend for Beeper {
+    fn start(&mut self) {
+        Beeper::start(self)
+    }
 
-        Self {
-            sink,
-            _stream,
-            _stream_handle,
-        }
+    fn stop(&mut self) {
+        Beeper::stop(self)
     }
 
-    pub fn start(&mut self) {
-        self.sink.play();
+    fn play_pattern(&mut self, pattern: [u8; 16], pitch: u8) {
+        Beeper::play_pattern(self, pattern, pitch)
     }
 
-    pub fn stop(&mut self) {
-        self.sink.pause();
+    fn clear_pattern(&mut self) {
+        Beeper::clear_pattern(self)
     }
 }
+
+/// Frequency of the beep in Hz unless configured otherwise.
+pub const DEFAULT_FREQUENCY: f32 = 680.0;
+
+/// Shape of the beep tone.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Waveform {
+    #[default]
+    Sine,
+    /// the buzz of the original hardware
+    Square,
+    Triangle,
+    Sawtooth,
+}
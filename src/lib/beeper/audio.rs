@@ -0,0 +1,346 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::time::Duration;
+
+use rodio::cpal::FromSample;
+use rodio::decoder::LoopedDecoder;
+use rodio::{Decoder, OutputStream, OutputStreamHandle, Sample, Sink, Source};
+
+use super::{Waveform, DEFAULT_FREQUENCY};
+
+const TONE_SAMPLE_RATE: u32 = 44100;
+const CLICK_FREQUENCY: f32 = 1400.0;
+const CLICK_DURATION: Duration = Duration::from_millis(30);
+const PATTERN_SAMPLE_RATE: u32 = 44100;
+const PATTERN_VOLUME: f32 = 0.25;
+
+/// Plays the beep through the default audio output with rodio.
+pub struct Beeper {
+    sink: Option<Sink>,
+    /// plays an XO-CHIP audio pattern instead of `sink` while set
+    pattern_sink: Option<Sink>,
+    /// from 0 to 1, see `set_volume`
+    volume: f32,
+    _stream: Option<OutputStream>,
+    stream_handle: Option<OutputStreamHandle>,
+}
+
+impl Beeper {
+    /// Falls back to a `silent` beeper if no audio output device is available.
+    pub fn new() -> Self {
+        Self::with_tone(DEFAULT_FREQUENCY, Waveform::default())
+    }
+
+    /// Beeps with a tone of the given frequency in Hz and waveform.
+    pub fn with_tone(frequency: f32, waveform: Waveform) -> Self {
+        Self::with_source(Tone::new(frequency, waveform))
+    }
+
+    /// Loops the sound file at `path` instead of the sine tone.
+    /// Falls back to the tone with a warning, unless `set_quiet`, if the file cannot be
+    /// read or decoded.
+    pub fn with_sound(path: &Path) -> Self {
+        match open_looped(path) {
+            Ok(source) => Self::with_source(source),
+            Err(e) => {
+                if !crate::is_quiet() {
+                    eprintln!(
+                        "Could not load beep sound {}: {}. Using the default tone.",
+                        path.display(),
+                        e
+                    );
+                }
+                Self::new()
+            }
+        }
+    }
+
+    /// A beeper that never makes a sound, e.g. for headless use.
+    pub fn silent() -> Self {
+        Self {
+            sink: None,
+            pattern_sink: None,
+            volume: 1.0,
+            _stream: None,
+            stream_handle: None,
+        }
+    }
+
+    /// True if this beeper has no audio output.
+    pub fn is_silent(&self) -> bool {
+        self.sink.is_none()
+    }
+
+    fn with_source<S>(source: S) -> Self
+    where
+        S: Source + Send + 'static,
+        S::Item: Sample + Send,
+        f32: FromSample<S::Item>,
+    {
+        let Ok((_stream, stream_handle)) = rodio::OutputStream::try_default() else {
+            return Self::silent();
+        };
+        let Ok(sink) = Sink::try_new(&stream_handle) else {
+            return Self::silent();
+        };
+
+        sink.append(source);
+        sink.pause();
+
+        Self {
+            sink: Some(sink),
+            pattern_sink: None,
+            volume: 1.0,
+            _stream: Some(_stream),
+            stream_handle: Some(stream_handle),
+        }
+    }
+
+    /// Scales the loudness of the beep and clicks, clamped to 0 (mute) to 1 (full volume).
+    /// Muting keeps the sound playing silently, so `start` and `stop` work as usual.
+    pub fn set_volume(&mut self, volume: f32) {
+        self.volume = volume.clamp(0.0, 1.0);
+        for sink in self.sink.iter().chain(&self.pattern_sink) {
+            sink.set_volume(self.volume);
+        }
+    }
+
+    pub fn volume(&self) -> f32 {
+        self.volume
+    }
+
+    fn active_sink(&self) -> Option<&Sink> {
+        self.pattern_sink.as_ref().or(self.sink.as_ref())
+    }
+
+    pub fn start(&mut self) {
+        if let Some(sink) = self.active_sink() {
+            sink.play();
+        }
+    }
+
+    pub fn stop(&mut self) {
+        if let Some(sink) = self.active_sink() {
+            sink.pause();
+        }
+    }
+
+    /// Plays the XO-CHIP audio `pattern`, 128 one-bit samples, in a loop instead of
+    /// the tone from now on. `pitch` sets the sample rate, 64 being 4000 samples per
+    /// second. Keeps playing or stays stopped like the tone did.
+    pub fn play_pattern(&mut self, pattern: [u8; 16], pitch: u8) {
+        let Some(stream_handle) = &self.stream_handle else {
+            return;
+        };
+        let Ok(sink) = Sink::try_new(stream_handle) else {
+            return;
+        };
+        let playing = self.active_sink().is_some_and(|sink| !sink.is_paused());
+        sink.set_volume(self.volume);
+        sink.append(PatternSource::new(pattern, pitch));
+        if !playing {
+            sink.pause();
+        }
+        self.stop();
+        self.pattern_sink = Some(sink);
+    }
+
+    /// Goes back to the tone after `play_pattern`.
+    pub fn clear_pattern(&mut self) {
+        if let Some(pattern_sink) = self.pattern_sink.take() {
+            if !pattern_sink.is_paused() {
+                self.start();
+            }
+        }
+    }
+
+    /// Plays a short tone at a higher pitch than the beep, independent of start/stop.
+    pub fn click(&mut self) {
+        if let Some(stream_handle) = &self.stream_handle {
+            let source = rodio::source::SineWave::new(CLICK_FREQUENCY)
+                .take_duration(CLICK_DURATION)
+                .amplify(self.volume);
+            let _ = stream_handle.play_raw(source);
+        }
+    }
+}
+
+/// An endless tone, one period being `TONE_SAMPLE_RATE / frequency` samples.
+struct Tone {
+    waveform: Waveform,
+    /// fraction of a period per sample
+    step: f32,
+    /// position within the current period, from 0 to 1
+    phase: f32,
+}
+
+impl Tone {
+    fn new(frequency: f32, waveform: Waveform) -> Self {
+        Self {
+            waveform,
+            step: frequency / TONE_SAMPLE_RATE as f32,
+            phase: 0.0,
+        }
+    }
+}
+
+impl Iterator for Tone {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let phase = self.phase;
+        self.phase = (self.phase + self.step) % 1.0;
+        Some(match self.waveform {
+            Waveform::Sine => (phase * std::f32::consts::TAU).sin(),
+            Waveform::Square if phase < 0.5 => 1.0,
+            Waveform::Square => -1.0,
+            Waveform::Triangle => 1.0 - 4.0 * (phase - 0.5).abs(),
+            Waveform::Sawtooth => 2.0 * phase - 1.0,
+        })
+    }
+}
+
+impl Source for Tone {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    fn sample_rate(&self) -> u32 {
+        TONE_SAMPLE_RATE
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+/// Loops the 128 bits of an XO-CHIP audio pattern as a square-ish wave.
+struct PatternSource {
+    pattern: [u8; 16],
+    /// bits of the pattern played per output sample
+    step: f32,
+    position: f32,
+}
+
+impl PatternSource {
+    fn new(pattern: [u8; 16], pitch: u8) -> Self {
+        let rate = 4000.0 * 2_f32.powf((pitch as f32 - 64.0) / 48.0);
+        Self {
+            pattern,
+            step: rate / PATTERN_SAMPLE_RATE as f32,
+            position: 0.0,
+        }
+    }
+}
+
+impl Iterator for PatternSource {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let bit = self.position as usize;
+        let on = self.pattern[bit / 8] & (0x80 >> (bit % 8)) != 0;
+        self.position = (self.position + self.step) % 128.0;
+        Some(if on { PATTERN_VOLUME } else { -PATTERN_VOLUME })
+    }
+}
+
+impl Source for PatternSource {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    fn sample_rate(&self) -> u32 {
+        PATTERN_SAMPLE_RATE
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+impl Default for Beeper {
+    fn default() -> Self {
+        Self::silent()
+    }
+}
+
+fn open_looped(path: &Path) -> Result<LoopedDecoder<BufReader<File>>, String> {
+    let file = File::open(path).map_err(|e| e.to_string())?;
+    Decoder::new_looped(BufReader::new(file)).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_silent() {
+        let mut beeper = Beeper::silent();
+        assert!(beeper.is_silent());
+        beeper.start();
+        beeper.click();
+        beeper.play_pattern([0xFF; 16], 64);
+        beeper.clear_pattern();
+        beeper.stop();
+    }
+
+    #[test]
+    fn test_volume() {
+        let mut beeper = Beeper::silent();
+        assert_eq!(beeper.volume(), 1.0);
+        beeper.set_volume(0.25);
+        assert_eq!(beeper.volume(), 0.25);
+        beeper.set_volume(2.0);
+        assert_eq!(beeper.volume(), 1.0);
+        beeper.set_volume(-1.0);
+        assert_eq!(beeper.volume(), 0.0);
+    }
+
+    #[test]
+    fn test_tone() {
+        // a period of 8 samples
+        let samples = |waveform| -> Vec<f32> { Tone::new(5512.5, waveform).take(8).collect() };
+        let close = |a: &[f32], b: &[f32]| a.iter().zip(b).all(|(a, b)| (a - b).abs() < 1e-4);
+
+        assert!(close(
+            &samples(Waveform::Sine)[..3],
+            &[0.0, std::f32::consts::FRAC_1_SQRT_2, 1.0]
+        ));
+        assert_eq!(samples(Waveform::Square), [[1.0; 4], [-1.0; 4]].concat());
+        assert_eq!(
+            samples(Waveform::Triangle)[..5],
+            [-1.0, -0.5, 0.0, 0.5, 1.0]
+        );
+        assert_eq!(samples(Waveform::Sawtooth)[..3], [-1.0, -0.75, -0.5]);
+    }
+
+    #[test]
+    fn test_pattern_source() {
+        let mut pattern = [0; 16];
+        pattern[0] = 0b1000_0000;
+        // pitch 64 plays 4000 bits per second, each bit lasting about 11 samples
+        let samples: Vec<f32> = PatternSource::new(pattern, 64).take(23).collect();
+        assert!(samples[..11].iter().all(|&s| s == PATTERN_VOLUME));
+        assert!(samples[12..].iter().all(|&s| s == -PATTERN_VOLUME));
+
+        // 48 steps higher doubles the rate
+        let source = PatternSource::new(pattern, 112);
+        assert_eq!(source.step, 2.0 * PatternSource::new(pattern, 64).step);
+    }
+
+    #[test]
+    fn test_open_looped_errors() {
+        assert!(open_looped(Path::new("does-not-exist.wav")).is_err());
+        // not a sound file
+        assert!(open_looped(Path::new(file!())).is_err());
+    }
+}
@@ -0,0 +1,58 @@
+use std::path::Path;
+
+use super::Waveform;
+
+/// Stands in for the audio beeper in builds without the `audio` feature, e.g. in the
+/// browser. Never makes a sound but keeps its settings, so the front-end can play the
+/// beep itself while the sound timer runs.
+pub struct Beeper {
+    /// from 0 to 1, see `set_volume`
+    volume: f32,
+}
+
+impl Beeper {
+    pub fn new() -> Self {
+        Self::silent()
+    }
+
+    pub fn with_tone(_frequency: f32, _waveform: Waveform) -> Self {
+        Self::silent()
+    }
+
+    pub fn with_sound(_path: &Path) -> Self {
+        Self::silent()
+    }
+
+    pub fn silent() -> Self {
+        Self { volume: 1.0 }
+    }
+
+    pub fn is_silent(&self) -> bool {
+        true
+    }
+
+    /// Clamped to 0 (mute) to 1 (full volume) like the audio beeper.
+    pub fn set_volume(&mut self, volume: f32) {
+        self.volume = volume.clamp(0.0, 1.0);
+    }
+
+    pub fn volume(&self) -> f32 {
+        self.volume
+    }
+
+    pub fn start(&mut self) {}
+
+    pub fn stop(&mut self) {}
+
+    pub fn play_pattern(&mut self, _pattern: [u8; 16], _pitch: u8) {}
+
+    pub fn clear_pattern(&mut self) {}
+
+    pub fn click(&mut self) {}
+}
+
+impl Default for Beeper {
+    fn default() -> Self {
+        Self::silent()
+    }
+}
@@ -1,19 +1,52 @@
-use std::cmp;
-use std::collections::HashSet;
+use std::collections::{HashSet, VecDeque};
+use std::fmt;
+use std::fmt::Write;
+use std::ops::Range;
 
 use rand::prelude::*;
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
-use crate::beeper::Beeper;
+use crate::beeper::{AudioBackend, Beeper};
 use crate::instruction::Instruction;
 
 pub const WIDTH: usize = 64;
 pub const HEIGHT: usize = 32;
+/// Resolution of the SUPER-CHIP high-resolution mode, switched on by 00FF.
+pub const HIRES_WIDTH: usize = 128;
+pub const HIRES_HEIGHT: usize = 64;
 pub const FPS: u32 = 60;
 
 const MEMORY_SIZE: usize = 4096;
-const PROGRAM_START_ADDRESS: usize = 512;
+const XO_MEMORY_SIZE: usize = 65536;
+/// XO-CHIP pitch at which audio patterns play at 4000 bits per second
+const DEFAULT_PITCH: u8 = 64;
+pub const PROGRAM_START_ADDRESS: usize = 512;
 const FONT_START_ADDRESS: usize = 80;
+/// the SUPER-CHIP 8x10 font follows the small one
+const BIG_FONT_START_ADDRESS: usize = FONT_START_ADDRESS + 16 * 5;
+/// the number of SUPER-CHIP RPL user flags, see FX75 and FX85
+const RPL_FLAGS: usize = 8;
+/// the end of both fonts, below which no program may be loaded
+const FONT_END_ADDRESS: usize = BIG_FONT_START_ADDRESS + 16 * 10;
+/// nested subroutine calls before 2NNN fails with a stack overflow
+const STACK_SIZE: usize = 16;
+
+/// Version of the `EmulatorState` format written by this build, raised with every
+/// format change. Older states are upgraded by `EmulatorState::migrate`.
+///
+/// 1. the first format
+/// 2. the XO-CHIP `plane2` and `selected_planes`
+/// 3. the XO-CHIP `audio_pattern` and `pitch`
+/// 4. the SUPER-CHIP `rpl_flags`
+pub const STATE_VERSION: u32 = 4;
+
+/// A watchdog limit that no sane ROM should reach, about 4 minutes at 700 instructions per second.
+pub const WATCHDOG_DEFAULT_LIMIT: u64 = 10_000_000;
+
+/// 64-bit FNV-1a parameters for `framebuffer_hash`
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
 
 #[derive(PartialEq, Eq, Error, Debug)]
 pub enum EmulatorError {
@@ -23,6 +56,14 @@ pub enum EmulatorError {
     MemoryAccess,
     #[error("stack underflow")]
     StackUnderflow,
+    #[error("stack overflow")]
+    StackOverflow,
+    #[error("the ROM is {size} bytes, but only {max} fit into memory")]
+    RomTooLarge { size: usize, max: usize },
+    #[error("watchdog timeout, no progress for too many instructions")]
+    Watchdog,
+    #[error("invalid state: {0}")]
+    InvalidState(String),
 }
 
 static FONT: [[u8; 5]; 16] = [
@@ -44,6 +85,245 @@ static FONT: [[u8; 5]; 16] = [
     [0xF0, 0x80, 0xF0, 0x80, 0x80], // F
 ];
 
+static BIG_FONT: [[u8; 10]; 16] = [
+    [0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF], // 0
+    [0x18, 0x78, 0x78, 0x18, 0x18, 0x18, 0x18, 0x18, 0xFF, 0xFF], // 1
+    [0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF], // 2
+    [0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF], // 3
+    [0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0x03, 0x03], // 4
+    [0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF], // 5
+    [0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF], // 6
+    [0xFF, 0xFF, 0x03, 0x03, 0x06, 0x0C, 0x18, 0x18, 0x18, 0x18], // 7
+    [0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF], // 8
+    [0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF], // 9
+    [0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3], // A
+    [0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC], // B
+    [0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C], // C
+    [0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC], // D
+    [0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF], // E
+    [0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0], // F
+];
+
+/// How 0NNN (call machine code routine) is handled, since running RCA 1802 code is not supported.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum MachineCallPolicy {
+    /// Fail with `EmulatorError::Instruction`.
+    #[default]
+    Error,
+    /// Skip the call like a no-op.
+    Ignore,
+    /// Skip the call and print a warning to stderr, unless `set_quiet`.
+    Log,
+}
+
+/// What happens when the program counter runs past the end of memory.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum PcOverflowPolicy {
+    /// Fail with `EmulatorError::MemoryAccess`.
+    #[default]
+    Error,
+    /// Continue executing from address 0.
+    Wrap,
+}
+
+/// Memory contents at power-on, before the font and program are loaded.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum MemoryInit {
+    #[default]
+    Zeros,
+    Ones,
+    Pattern(u8),
+    Random,
+}
+
+/// How FX55/FX65 change `i` after storing/loading V0..=VX.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum IndexIncrement {
+    /// `i` is left unchanged (SUPER-CHIP).
+    None,
+    /// `i` is incremented by X (CHIP-48).
+    X,
+    /// `i` is incremented by X + 1 (COSMAC VIP).
+    XPlusOne,
+}
+
+/// CHIP-8 variants, each one a superset of the previous.
+#[derive(Copy, Clone, Debug, Default, Eq, Ord, PartialEq, PartialOrd)]
+pub enum Mode {
+    #[default]
+    Chip8,
+    SuperChip,
+    XoChip,
+}
+
+impl Mode {
+    /// Every variant, oldest first.
+    pub const ALL: [Mode; 3] = [Mode::Chip8, Mode::SuperChip, Mode::XoChip];
+
+    /// The name the variant is known by, e.g. `SUPER-CHIP`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Mode::Chip8 => "CHIP-8",
+            Mode::SuperChip => "SUPER-CHIP",
+            Mode::XoChip => "XO-CHIP",
+        }
+    }
+
+    /// The first variant that defines `opcode`.
+    fn of_opcode(opcode: u16) -> Self {
+        match opcode {
+            0x00D0..=0x00DF | 0xF000 | 0xF002 => Mode::XoChip,
+            _ if opcode & 0xF00F == 0x5002 || opcode & 0xF00F == 0x5003 => Mode::XoChip,
+            _ if opcode & 0xF0FF == 0xF001 || opcode & 0xF0FF == 0xF03A => Mode::XoChip,
+            0x00C0..=0x00CF | 0x00FB..=0x00FF => Mode::SuperChip,
+            _ if opcode & 0xF00F == 0xD000 => Mode::SuperChip,
+            _ if matches!(opcode & 0xF0FF, 0xF030 | 0xF075 | 0xF085) => Mode::SuperChip,
+            _ => Mode::Chip8,
+        }
+    }
+}
+
+/// Behaviors that differ between CHIP-8 interpreters.
+/// The defaults keep this emulator's original behavior.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Quirks {
+    /// 8XY6/8XYE shift VY into VX, otherwise VX is shifted in place.
+    pub shift_uses_vy: bool,
+    pub index_increment_on_load_store: IndexIncrement,
+    /// BXNN jumps to XNN + VX instead of NNN + V0.
+    pub jump_offset_uses_vx: bool,
+    /// 8XY1/8XY2/8XY3 reset VF to 0.
+    pub reset_vf_on_logic: bool,
+    /// DXYN waits for the vertical blank, ending the current frame.
+    pub display_wait: bool,
+    /// Sprites crossing the right or bottom edge continue on the opposite edge
+    /// instead of being clipped.
+    ///
+    /// Either way the start position wraps first, so a sprite at x = 64 is drawn
+    /// at x = 0. Only the pixels past the edge differ: an 8 pixel wide sprite at
+    /// x = 63 shows its first column at 63 and the other seven at 0 to 6 when
+    /// wrapping, and only the first column when clipping.
+    pub draw_wraps: bool,
+    /// FX1E sets VF to 1 if I passes 0xFFF and wraps I to 12 bits, otherwise to 0,
+    /// like the Amiga interpreter. Spacefight 2091! relies on it.
+    pub index_overflow_flag: bool,
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Self {
+            shift_uses_vy: true,
+            index_increment_on_load_store: IndexIncrement::None,
+            jump_offset_uses_vx: false,
+            reset_vf_on_logic: false,
+            display_wait: false,
+            draw_wraps: false,
+            index_overflow_flag: false,
+        }
+    }
+}
+
+/// Names accepted by `Quirks::profile`.
+pub const QUIRK_PROFILES: [&str; 3] = ["chip8", "schip", "xochip"];
+
+impl Quirks {
+    /// The original CHIP-8 interpreter on the COSMAC VIP.
+    pub fn chip8() -> Self {
+        Self {
+            shift_uses_vy: true,
+            index_increment_on_load_store: IndexIncrement::XPlusOne,
+            jump_offset_uses_vx: false,
+            reset_vf_on_logic: true,
+            display_wait: true,
+            draw_wraps: false,
+            index_overflow_flag: false,
+        }
+    }
+
+    /// SUPER-CHIP 1.1 on the HP 48 calculators.
+    pub fn schip() -> Self {
+        Self {
+            shift_uses_vy: false,
+            index_increment_on_load_store: IndexIncrement::None,
+            jump_offset_uses_vx: true,
+            reset_vf_on_logic: false,
+            display_wait: false,
+            draw_wraps: false,
+            index_overflow_flag: false,
+        }
+    }
+
+    /// XO-CHIP as implemented by Octo.
+    pub fn xochip() -> Self {
+        Self {
+            shift_uses_vy: true,
+            index_increment_on_load_store: IndexIncrement::XPlusOne,
+            jump_offset_uses_vx: false,
+            reset_vf_on_logic: false,
+            display_wait: false,
+            draw_wraps: true,
+            index_overflow_flag: false,
+        }
+    }
+
+    /// The preset named `name`, one of `QUIRK_PROFILES`.
+    pub fn profile(name: &str) -> Option<Self> {
+        match name {
+            "chip8" => Some(Self::chip8()),
+            "schip" => Some(Self::schip()),
+            "xochip" => Some(Self::xochip()),
+            _ => None,
+        }
+    }
+}
+
+/// Hardware whose quirks and timing can be approximated.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Target {
+    /// The original interpreter on the COSMAC VIP.
+    Cosmac,
+    /// CHIP-48 on the HP 48 calculators.
+    Chip48,
+    /// SUPER-CHIP 1.1 on the HP 48 calculators.
+    SuperChip,
+}
+
+impl Target {
+    pub fn quirks(&self) -> Quirks {
+        match self {
+            Target::Cosmac => Quirks::chip8(),
+            Target::Chip48 => Quirks {
+                shift_uses_vy: false,
+                index_increment_on_load_store: IndexIncrement::X,
+                jump_offset_uses_vx: true,
+                reset_vf_on_logic: false,
+                display_wait: false,
+                draw_wraps: false,
+                index_overflow_flag: false,
+            },
+            Target::SuperChip => Quirks::schip(),
+        }
+    }
+
+    /// Approximate instructions per second.
+    pub fn clock_speed(&self) -> u16 {
+        match self {
+            Target::Cosmac => 540,
+            Target::Chip48 => 900,
+            Target::SuperChip => 1800,
+        }
+    }
+
+    /// Sprite rows drawn per instruction slot that a DXYN takes on top of its own,
+    /// 0 if drawing is free. The VIP needs about a frame for a 15 row sprite.
+    pub fn draw_rows_per_slot(&self) -> u8 {
+        match self {
+            Target::Cosmac => 2,
+            Target::Chip48 | Target::SuperChip => 0,
+        }
+    }
+}
+
 #[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
 pub enum Key {
     Key0,
@@ -86,8 +366,9 @@ impl Key {
         }
     }
 
-    pub fn from_num(n: u8) -> Self {
-        match n {
+    /// Returns `None` for values above 0xF.
+    pub fn from_num(n: u8) -> Option<Self> {
+        let key = match n {
             0x0 => Key::Key0,
             0x1 => Key::Key1,
             0x2 => Key::Key2,
@@ -104,203 +385,1518 @@ impl Key {
             0xD => Key::KeyD,
             0xE => Key::KeyE,
             0xF => Key::KeyF,
-            _ => panic!(),
+            _ => return None,
+        };
+        Some(key)
+    }
+
+    /// Returns true if the key with the number in `n` is pressed, false for values above 0xF.
+    fn is_pressed(n: u8, keys_pressed: &HashSet<Key>) -> bool {
+        Key::from_num(n).is_some_and(|key| keys_pressed.contains(&key))
+    }
+}
+
+/// Legal but suspicious behavior, reported through the trace hook when diagnostics are enabled.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Diagnostic {
+    /// DXYN reads sprite rows `start..end` from more than one of the interpreter area,
+    /// the font and the program, which usually means I was not set before the draw.
+    SpriteCrossesRegion { start: usize, end: usize },
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Diagnostic::SpriteCrossesRegion { start, end } => write!(
+                f,
+                "sprite data {:#05X}..{:#05X} crosses a font/program boundary",
+                start, end
+            ),
+        }
+    }
+}
+
+/// An instruction about to be executed, as passed to the trace hook.
+pub struct Trace<'a> {
+    pub program_counter: usize,
+    pub opcode: u16,
+    pub instruction: &'a Instruction,
+    pub registers: &'a [u8; 16],
+    pub i: usize,
+    pub diagnostic: Option<Diagnostic>,
+}
+
+pub type TraceHook = Box<dyn FnMut(&Trace)>;
+
+/// A write to a watched address.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct WatchpointHit {
+    /// address of the instruction that wrote
+    pub program_counter: usize,
+    pub address: usize,
+    pub old: u8,
+    pub new: u8,
+}
+
+/// Why `run_frame_debug` returned.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum RunOutcome {
+    FrameComplete,
+    /// Execution stopped right after the instruction that wrote to a watched address.
+    Watchpoint(WatchpointHit),
+    /// Execution stopped before the instruction at this breakpoint address.
+    Breakpoint(usize),
+}
+
+/// A snapshot of the machine taken by `Emulator::save_state`, e.g. for save slots.
+/// Settings like quirks, the beeper and the random number generator are not part of it.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct EmulatorState {
+    /// `STATE_VERSION` of the build that saved the state
+    pub version: u32,
+    pub memory: Vec<u8>,
+    pub stack: Vec<usize>,
+    pub registers: [u8; 16],
+    pub i: usize,
+    pub program_counter: usize,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+    /// see `Emulator::framebuffer_packed`, all rows of the high resolution
+    pub frame_buf: Vec<u128>,
+    /// the second XO-CHIP plane like `frame_buf`, empty in states saved before planes existed
+    #[serde(default)]
+    pub plane2: Vec<u128>,
+    #[serde(default = "default_selected_planes")]
+    pub selected_planes: u8,
+    pub hires: bool,
+    /// the XO-CHIP audio pattern, `None` while the tone is used
+    #[serde(default)]
+    pub audio_pattern: Option<[u8; 16]>,
+    #[serde(default = "default_pitch")]
+    pub pitch: u8,
+    #[serde(default)]
+    pub rpl_flags: [u8; RPL_FLAGS],
+}
+
+impl EmulatorState {
+    /// Upgrades a state saved by an older version to `STATE_VERSION`, the fields added
+    /// since get their defaults. Fails for versions that never existed or are newer.
+    pub fn migrate(mut self) -> Result<Self, EmulatorError> {
+        if self.version == 0 || self.version > STATE_VERSION {
+            return Err(EmulatorError::InvalidState(format!(
+                "version {} is not supported, only 1 to {}",
+                self.version, STATE_VERSION
+            )));
+        }
+        if self.version < 2 {
+            self.plane2 = vec![];
+            self.selected_planes = default_selected_planes();
+        }
+        if self.version < 3 {
+            self.audio_pattern = None;
+            self.pitch = default_pitch();
         }
+        if self.version < 4 {
+            self.rpl_flags = [0; RPL_FLAGS];
+        }
+        self.version = STATE_VERSION;
+        Ok(self)
+    }
+}
+
+/// The checks of `Emulator::validate`, shared with `load_state` to reject a state
+/// before anything is restored.
+fn validate_machine(
+    memory_size: usize,
+    program_counter: usize,
+    selected_planes: u8,
+    stack: &[usize],
+) -> Result<(), EmulatorError> {
+    if program_counter >= memory_size {
+        return Err(EmulatorError::InvalidState(format!(
+            "program counter {:#06X} is outside of memory",
+            program_counter
+        )));
+    }
+    // I may point past memory, e.g. after FX1E, every access through it is checked
+    if selected_planes > 0b11 {
+        return Err(EmulatorError::InvalidState(format!(
+            "plane mask {:#b} selects planes that don't exist",
+            selected_planes
+        )));
+    }
+    if stack.len() > STACK_SIZE {
+        return Err(EmulatorError::InvalidState(format!(
+            "stack depth {} exceeds {}",
+            stack.len(),
+            STACK_SIZE
+        )));
+    }
+    if let Some(adr) = stack.iter().find(|&&adr| adr >= memory_size) {
+        return Err(EmulatorError::InvalidState(format!(
+            "return address {:#06X} is outside of memory",
+            adr
+        )));
     }
+    Ok(())
+}
+
+fn default_selected_planes() -> u8 {
+    1
+}
+
+fn default_pitch() -> u8 {
+    DEFAULT_PITCH
+}
+
+/// Indexes of the planes whose bits are set in `planes`.
+fn planes_in(planes: u8) -> impl Iterator<Item = usize> {
+    (0..2).filter(move |plane| planes & (1 << plane) != 0)
+}
+
+/// Everything a front-end needs after a frame, borrowed from the emulator in one call.
+///
+/// A minimal loop calls `set_keys_pressed` and `run_frame` `FPS` times per second,
+/// draws `framebuffer` if `redraw` is set, plays a tone while `sound_timer` is
+/// non-zero and then calls `clear_redraw`.
+#[derive(Debug)]
+pub struct CoreState<'a> {
+    /// one row per line of the screen, see `Emulator::framebuffer_packed`
+    pub framebuffer: &'a [u128],
+    pub width: usize,
+    pub height: usize,
+    pub redraw: bool,
+    pub collision: bool,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+    pub keys_pressed: &'a HashSet<Key>,
 }
 
 pub struct Emulator {
-    memory: [u8; MEMORY_SIZE],
+    /// `MEMORY_SIZE` bytes, `XO_MEMORY_SIZE` in XO-CHIP mode
+    memory: Vec<u8>,
+    xo_chip: bool,
+    /// the loaded program as it was before running, for `reset`
+    program: Vec<u8>,
+    /// where the program is loaded and starts, `PROGRAM_START_ADDRESS` unless set by `new_with_start`
+    program_start: usize,
     stack: Vec<usize>,
     registers: [u8; 16],
     i: usize,
     program_counter: usize,
     delay_timer: u8,
     sound_timer: u8,
-    frame_buf: [[bool; 64]; 32],
+    timers_frozen: bool,
+    /// one bit per pixel, bit `width - 1` is the leftmost pixel;
+    /// only the top `height` rows are used in low resolution
+    frame_buf: [u128; HIRES_HEIGHT],
+    /// the second XO-CHIP plane, `frame_buf` being the first
+    plane2: [u128; HIRES_HEIGHT],
+    /// bit n is set if DXYN, 00E0 and the scrolls act on plane n + 1, see FN01
+    selected_planes: u8,
+    hires: bool,
 
     keys_pressed: HashSet<Key>,
+    /// keys that were down when FX0A last checked, one of them has to be released
+    keys_awaiting_release: HashSet<Key>,
+    /// see `push_key_event`
+    key_events: VecDeque<(Key, bool)>,
 
-    inst_count: u8,
-    ticks_per_frame: u8,
-    timers_update_interval: u8,
+    quirks: Quirks,
+    detected_variant: Mode,
+    machine_call_policy: MachineCallPolicy,
+    pc_overflow_policy: PcOverflowPolicy,
+    watchdog_limit: Option<u64>,
+    instructions_without_progress: u64,
 
-    rand_num_gen: ThreadRng,
-    beeper: Beeper,
+    frame_count: u64,
+    cycles: u64,
+    clock_speed: u16,
+    ticks_per_frame: u16,
+
+    memory_init: MemoryInit,
+    rand_num_gen: StdRng,
+    /// see `set_seed`
+    seed: Option<u64>,
+    beeper: Box<dyn AudioBackend>,
+    /// set by F002, the beeper plays the tone until then
+    audio_pattern: Option<[u8; 16]>,
+    /// set by FX3A, the playback rate of `audio_pattern`
+    pitch: u8,
+    /// the SUPER-CHIP RPL user flags, written by FX75 and read by FX85
+    rpl_flags: [u8; RPL_FLAGS],
 
     redraw: bool,
+    /// makes the next frame redraw even if it does not draw, e.g. after loading a program
+    redraw_requested: bool,
+    /// set by DXYN under the display wait quirk until the next frame
+    waiting_for_vblank: bool,
+    /// see `set_draw_rows_per_slot`
+    draw_rows_per_slot: u8,
+    /// instruction slots the last DXYN still takes, possibly into the next frame
+    busy_slots: u16,
+    /// bit n is set if row n changed since the last `clear_redraw`
+    dirty_rows: u64,
+    collision: bool,
+    /// DXYN executed since the current frame started
+    draws: u32,
+    /// see `set_flicker_log`
+    flicker_threshold: Option<u8>,
+    /// how often each pixel was toggled in the current frame, empty unless flicker logging is on
+    toggles: Vec<u8>,
+
+    trace_hook: Option<TraceHook>,
+    diagnostics: bool,
+    watchpoints: HashSet<usize>,
+    watchpoint_hit: Option<WatchpointHit>,
+    breakpoints: HashSet<usize>,
+
+    /// states saved at the start of a frame, oldest first, with their `frame_count`
+    rewind_buffer: VecDeque<(u64, EmulatorState)>,
+    rewind_depth: u64,
+    rewind_interval: u64,
 }
 
 impl Emulator {
     pub fn new(clock_speed: u16, program: Vec<u8>) -> Result<Emulator, EmulatorError> {
-        let ticks_per_frame = (clock_speed as f64 / FPS as f64).round() as u8;
-        let timers_update_interval = (clock_speed as f64 / 60_f64).round() as u8;
+        Self::new_with_memory_init(clock_speed, program, MemoryInit::default())
+    }
 
+    /// Like `new`, copying the program, e.g. from `include_bytes!`.
+    pub fn from_bytes(clock_speed: u16, program: &[u8]) -> Result<Emulator, EmulatorError> {
+        Self::new(clock_speed, program.to_vec())
+    }
+
+    pub fn new_with_memory_init(
+        clock_speed: u16,
+        program: Vec<u8>,
+        memory_init: MemoryInit,
+    ) -> Result<Emulator, EmulatorError> {
+        Self::create(clock_speed, program, memory_init, Box::new(Beeper::new()))
+    }
+
+    /// Like `new`, with sound going to `audio` instead of the default `Beeper`,
+    /// which is never created.
+    pub fn new_with_audio(
+        clock_speed: u16,
+        program: Vec<u8>,
+        audio: Box<dyn AudioBackend>,
+    ) -> Result<Emulator, EmulatorError> {
+        Self::create(clock_speed, program, MemoryInit::default(), audio)
+    }
+
+    fn create(
+        clock_speed: u16,
+        program: Vec<u8>,
+        memory_init: MemoryInit,
+        beeper: Box<dyn AudioBackend>,
+    ) -> Result<Emulator, EmulatorError> {
         let mut e = Self {
-            memory: [0; MEMORY_SIZE],
+            memory: vec![0; MEMORY_SIZE],
+            xo_chip: false,
+            program: vec![],
+            program_start: PROGRAM_START_ADDRESS,
             stack: vec![],
             registers: [0; 16],
             i: 0,
             program_counter: PROGRAM_START_ADDRESS,
             delay_timer: 0,
             sound_timer: 0,
-            frame_buf: [[false; WIDTH]; HEIGHT],
+            timers_frozen: false,
+            frame_buf: [0; HIRES_HEIGHT],
+            plane2: [0; HIRES_HEIGHT],
+            selected_planes: 1,
+            hires: false,
 
             keys_pressed: HashSet::new(),
+            keys_awaiting_release: HashSet::new(),
+            key_events: VecDeque::new(),
 
-            inst_count: 0,
-            ticks_per_frame,
-            timers_update_interval,
+            quirks: Quirks::default(),
+            detected_variant: Mode::default(),
+            machine_call_policy: MachineCallPolicy::default(),
+            pc_overflow_policy: PcOverflowPolicy::default(),
+            watchdog_limit: None,
+            instructions_without_progress: 0,
 
-            rand_num_gen: thread_rng(),
-            beeper: Beeper::new(),
+            frame_count: 0,
+            cycles: 0,
+            clock_speed: 0,
+            ticks_per_frame: 0,
+
+            memory_init,
+            rand_num_gen: StdRng::from_entropy(),
+            seed: None,
+            beeper,
+            audio_pattern: None,
+            pitch: DEFAULT_PITCH,
+            rpl_flags: [0; RPL_FLAGS],
 
             redraw: false,
+            redraw_requested: false,
+            waiting_for_vblank: false,
+            draw_rows_per_slot: 0,
+            busy_slots: 0,
+            dirty_rows: 0,
+            collision: false,
+            draws: 0,
+            flicker_threshold: None,
+            toggles: vec![],
+
+            trace_hook: None,
+            diagnostics: false,
+            watchpoints: HashSet::new(),
+            watchpoint_hit: None,
+            breakpoints: HashSet::new(),
+            rewind_buffer: VecDeque::new(),
+            rewind_depth: 0,
+            rewind_interval: 1,
         };
-        e.write_to_memory(PROGRAM_START_ADDRESS, &program)?;
-        e.write_to_memory(FONT_START_ADDRESS, &FONT.concat())?;
+        e.set_clock_speed(clock_speed);
+        e.load_program(&program)?;
 
         Ok(e)
     }
 
-    fn write_to_memory(&mut self, start_address: usize, buf: &[u8]) -> Result<(), EmulatorError> {
-        if start_address + buf.len() > MEMORY_SIZE {
+    pub fn new_with_quirks(
+        clock_speed: u16,
+        program: Vec<u8>,
+        quirks: Quirks,
+    ) -> Result<Emulator, EmulatorError> {
+        let mut e = Self::new(clock_speed, program)?;
+        e.set_quirks(quirks);
+        Ok(e)
+    }
+
+    /// Makes CXNN and `MemoryInit::Random` deterministic, see `set_seed`.
+    pub fn new_with_seed(
+        clock_speed: u16,
+        program: Vec<u8>,
+        seed: u64,
+    ) -> Result<Emulator, EmulatorError> {
+        let mut e = Self::new(clock_speed, program)?;
+        e.set_seed(seed);
+        e.reset();
+        Ok(e)
+    }
+
+    /// Loads the program at `start` instead of `PROGRAM_START_ADDRESS`, e.g. 0x600 for ETI-660
+    /// ROMs. `start` has to be above the fonts and leave room for the program.
+    pub fn new_with_start(
+        clock_speed: u16,
+        program: Vec<u8>,
+        start: usize,
+    ) -> Result<Emulator, EmulatorError> {
+        if !(FONT_END_ADDRESS..MEMORY_SIZE).contains(&start) {
             return Err(EmulatorError::MemoryAccess);
         }
-        for (mem, data) in self.memory[start_address..].iter_mut().zip(buf.iter()) {
-            *mem = *data
+        let mut e = Self::new(clock_speed, vec![])?;
+        e.program_start = start;
+        e.load_program(&program)?;
+        Ok(e)
+    }
+
+    /// Uses the clock speed and quirks of `target`.
+    pub fn new_with_target(program: Vec<u8>, target: Target) -> Result<Emulator, EmulatorError> {
+        let mut e = Self::new_with_quirks(target.clock_speed(), program, target.quirks())?;
+        e.set_draw_rows_per_slot(target.draw_rows_per_slot());
+        Ok(e)
+    }
+
+    /// Replaces the program and restarts the machine as if it had just been switched on.
+    /// Settings like quirks, policies and hooks are kept.
+    /// On error the emulator is left unchanged.
+    pub fn load_program(&mut self, program: &[u8]) -> Result<(), EmulatorError> {
+        if program.len() > self.max_program_size() {
+            return Err(EmulatorError::RomTooLarge {
+                size: program.len(),
+                max: self.max_program_size(),
+            });
+        }
+        self.program = program.to_vec();
+        self.reset();
+        Ok(())
+    }
+
+    /// Restarts the loaded program as if the machine had just been switched on,
+    /// undoing any changes the program made to memory. Settings are kept.
+    pub fn reset(&mut self) {
+        if let Some(seed) = self.seed {
+            self.rand_num_gen = StdRng::seed_from_u64(seed);
+        }
+        self.stack.clear();
+        self.registers = [0; 16];
+        self.i = 0;
+        self.program_counter = self.program_start;
+        self.delay_timer = 0;
+        self.sound_timer = 0;
+        self.beeper.stop();
+        self.beeper.clear_pattern();
+        self.audio_pattern = None;
+        self.pitch = DEFAULT_PITCH;
+        self.rpl_flags = [0; RPL_FLAGS];
+        self.keys_awaiting_release.clear();
+        self.key_events.clear();
+        self.clear_planes(0b11);
+        self.selected_planes = 1;
+        self.hires = false;
+        self.redraw_requested = true;
+        self.waiting_for_vblank = false;
+        self.busy_slots = 0;
+        self.collision = false;
+        self.draws = 0;
+        self.toggles.fill(0);
+        self.frame_count = 0;
+        self.cycles = 0;
+        self.detected_variant = Mode::default();
+        self.instructions_without_progress = 0;
+        self.watchpoint_hit = None;
+        self.rewind_buffer.clear();
+
+        self.init_memory(self.memory_init);
+        self.memory[self.program_start..][..self.program.len()].copy_from_slice(&self.program);
+        let font = FONT.concat();
+        self.memory[FONT_START_ADDRESS..][..font.len()].copy_from_slice(&font);
+        let big_font = BIG_FONT.concat();
+        self.memory[BIG_FONT_START_ADDRESS..][..big_font.len()].copy_from_slice(&big_font);
+    }
+
+    /// Switches between the classic 4K memory and the 64K of XO-CHIP, which also
+    /// enables `F000 NNNN`. Restarts the program, which has to fit into the new memory.
+    pub fn set_xo_chip(&mut self, xo_chip: bool) -> Result<(), EmulatorError> {
+        let size = if xo_chip { XO_MEMORY_SIZE } else { MEMORY_SIZE };
+        if self.program_start + self.program.len() > size {
+            return Err(EmulatorError::RomTooLarge {
+                size: self.program.len(),
+                max: size - self.program_start,
+            });
         }
+        self.xo_chip = xo_chip;
+        self.memory = vec![0; size];
+        self.reset();
         Ok(())
     }
 
-    fn draw_to_fb(&mut self, x: usize, y: usize, sprite: &[u8]) -> bool {
-        let x = x & (WIDTH - 1);
-        let y = y & (HEIGHT - 1);
+    pub fn xo_chip(&self) -> bool {
+        self.xo_chip
+    }
+
+    /// The largest program that `load_program` accepts in the current mode.
+    pub fn max_program_size(&self) -> usize {
+        self.memory.len() - self.program_start
+    }
 
-        let row_iter = cmp::min(HEIGHT - y, sprite.len());
-        let col_iter = cmp::min(WIDTH - x, 8);
+    /// Where the program is loaded and starts, see `new_with_start`.
+    pub fn program_start(&self) -> usize {
+        self.program_start
+    }
 
-        let mut any_px_erased = false;
-        for row in 0..row_iter {
-            let mut sprite_row = sprite[row];
-            for col in 0..col_iter {
-                let sprite_px_on = (sprite_row & 128) != 0;
-                sprite_row <<= 1;
-
-                if sprite_px_on {
-                    let x_coord = x + col;
-                    let y_coord = y + row;
-
-                    if self.frame_buf[y_coord][x_coord] {
-                        self.frame_buf[y_coord][x_coord] = false;
-                        any_px_erased = true;
-                    } else {
-                        self.frame_buf[y_coord][x_coord] = true;
-                    }
-                }
+    /// Seeds the random number generator, so the same program with the same input
+    /// runs the same way every time. `reset` starts over from the seed.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = Some(seed);
+        self.rand_num_gen = StdRng::seed_from_u64(seed);
+    }
+
+    /// Makes every DXYN take one instruction slot per `rows` sprite rows on top of its
+    /// own, like the slow drawing of the COSMAC VIP. 0 turns it off, the default.
+    pub fn set_draw_rows_per_slot(&mut self, rows: u8) {
+        self.draw_rows_per_slot = rows;
+    }
+
+    pub fn draw_rows_per_slot(&self) -> u8 {
+        self.draw_rows_per_slot
+    }
+
+    /// Changes the instructions executed per second, taking effect with the next frame.
+    /// Speeds below `FPS` are raised to `FPS`, so every frame executes an instruction.
+    pub fn set_clock_speed(&mut self, clock_speed: u16) {
+        self.clock_speed = clock_speed.max(FPS as u16);
+        self.ticks_per_frame = (self.clock_speed as f64 / FPS as f64).round() as u16;
+    }
+
+    pub fn clock_speed(&self) -> u16 {
+        self.clock_speed
+    }
+
+    /// Stops or resumes the beep while the front-end pauses the emulation,
+    /// since the sound timer doesn't count down while no frames run.
+    pub fn set_sound_paused(&mut self, paused: bool) {
+        if paused || self.sound_timer == 0 {
+            self.beeper.stop();
+        } else {
+            self.beeper.start();
+        }
+    }
+
+    fn init_memory(&mut self, memory_init: MemoryInit) {
+        match memory_init {
+            MemoryInit::Zeros => self.memory.fill(0),
+            MemoryInit::Ones => self.memory.fill(0xFF),
+            MemoryInit::Pattern(pattern) => self.memory.fill(pattern),
+            MemoryInit::Random => self.rand_num_gen.fill(&mut self.memory[..]),
+        }
+    }
+
+    /// Keeps the states of the last `frames` frames for `rewind`, 0 turns rewinding off.
+    pub fn set_rewind_depth(&mut self, frames: u64) {
+        self.rewind_depth = frames;
+        self.trim_rewind_buffer();
+    }
+
+    /// Saves a state for `rewind` only every `frames` frames to save memory,
+    /// a state takes about 6 KiB, or 66 KiB with the XO-CHIP memory. Defaults to 1.
+    pub fn set_rewind_interval(&mut self, frames: u64) {
+        self.rewind_interval = frames.max(1);
+    }
+
+    /// Goes back to the start of the frame `frames` frames ago, or to the newest
+    /// saved state before it. Rewinding further than `set_rewind_depth` stops
+    /// at the oldest state. Returns false if there is no state to go back to.
+    pub fn rewind(&mut self, frames: u64) -> Result<bool, EmulatorError> {
+        let target = self.frame_count.saturating_sub(frames);
+        let Some(oldest) = self.rewind_buffer.front().map(|(frame, _)| *frame) else {
+            return Ok(false);
+        };
+        let target = target.max(oldest);
+        // the restored state is saved again when its frame runs once more
+        while let Some((frame, state)) = self.rewind_buffer.pop_back() {
+            if frame <= target {
+                self.restore_state(state)?;
+                self.frame_count = frame;
+                return Ok(true);
             }
         }
-        any_px_erased
+        Ok(false)
     }
 
-    fn clear_screen(&mut self) {
-        for y in 0..HEIGHT {
-            for x in 0..WIDTH {
-                self.frame_buf[y][x] = false;
+    fn record_rewind_state(&mut self) {
+        if self.rewind_depth == 0 {
+            return;
+        }
+        let due = match self.rewind_buffer.back() {
+            Some((frame, _)) => self.frame_count >= frame + self.rewind_interval,
+            None => true,
+        };
+        if due {
+            let state = self.save_state();
+            self.rewind_buffer.push_back((self.frame_count, state));
+            self.trim_rewind_buffer();
+        }
+    }
+
+    fn trim_rewind_buffer(&mut self) {
+        while let Some((frame, _)) = self.rewind_buffer.front() {
+            if frame + self.rewind_depth > self.frame_count {
+                break;
             }
+            self.rewind_buffer.pop_front();
         }
     }
 
-    pub fn set_keys_pressed(&mut self, keys_pressed: HashSet<Key>) {
-        self.keys_pressed = keys_pressed;
+    pub fn save_state(&self) -> EmulatorState {
+        EmulatorState {
+            version: STATE_VERSION,
+            memory: self.memory.to_vec(),
+            stack: self.stack.clone(),
+            registers: self.registers,
+            i: self.i,
+            program_counter: self.program_counter,
+            delay_timer: self.delay_timer,
+            sound_timer: self.sound_timer,
+            frame_buf: self.frame_buf.to_vec(),
+            plane2: self.plane2.to_vec(),
+            selected_planes: self.selected_planes,
+            hires: self.hires,
+            audio_pattern: self.audio_pattern,
+            pitch: self.pitch,
+            rpl_flags: self.rpl_flags,
+        }
     }
 
-    pub fn should_redraw(&self) -> bool {
-        self.redraw
+    /// Restores a state from `save_state`, migrating it if it is from an older
+    /// version. Fails without changing anything if the state is from a newer
+    /// version or inconsistent.
+    pub fn load_state(&mut self, state: EmulatorState) -> Result<(), EmulatorError> {
+        let state = state.migrate()?;
+        validate_machine(
+            state.memory.len(),
+            state.program_counter,
+            state.selected_planes,
+            &state.stack,
+        )?;
+        self.restore_state(state)
     }
 
-    pub fn get_framebuffer(&self) -> &[[bool; WIDTH]; HEIGHT] {
-        &self.frame_buf
+    /// Restores a state without validating it, for states this emulator saved itself.
+    /// Fails without changing anything if the state does not fit.
+    fn restore_state(&mut self, state: EmulatorState) -> Result<(), EmulatorError> {
+        if state.memory.len() != self.memory.len() {
+            return Err(EmulatorError::InvalidState(format!(
+                "memory size {} is not {}",
+                state.memory.len(),
+                self.memory.len()
+            )));
+        }
+        let screen_rows = |rows: Vec<u128>| {
+            EmulatorError::InvalidState(format!(
+                "{} screen rows instead of {}",
+                rows.len(),
+                HIRES_HEIGHT
+            ))
+        };
+        let frame_buf = state.frame_buf.try_into().map_err(screen_rows)?;
+        let plane2 = match state.plane2 {
+            rows if rows.is_empty() => [0; HIRES_HEIGHT],
+            rows => rows.try_into().map_err(screen_rows)?,
+        };
+
+        self.memory = state.memory;
+        self.stack = state.stack;
+        self.registers = state.registers;
+        self.i = state.i;
+        self.program_counter = state.program_counter;
+        self.delay_timer = state.delay_timer;
+        self.sound_timer = state.sound_timer;
+        self.frame_buf = frame_buf;
+        self.plane2 = plane2;
+        self.selected_planes = state.selected_planes;
+        self.hires = state.hires;
+        self.audio_pattern = state.audio_pattern;
+        self.pitch = state.pitch;
+        self.rpl_flags = state.rpl_flags;
+
+        self.beeper.stop();
+        match self.audio_pattern {
+            Some(pattern) => self.beeper.play_pattern(pattern, self.pitch),
+            None => self.beeper.clear_pattern(),
+        }
+        if self.sound_timer > 0 {
+            self.beeper.start();
+        }
+        self.keys_awaiting_release.clear();
+        self.key_events.clear();
+        self.waiting_for_vblank = false;
+        self.busy_slots = 0;
+        self.instructions_without_progress = 0;
+        self.watchpoint_hit = None;
+        // the front-end may render before the next frame runs, e.g. while rewinding
+        self.redraw = true;
+        self.dirty_rows = u64::MAX >> (64 - self.resolution().1);
+        Ok(())
     }
 
-    pub fn run_frame(&mut self) -> Result<(), EmulatorError> {
-        let mut redraw = false;
-        for _ in 0..self.ticks_per_frame {
-            redraw = self.tick()? || redraw;
+    /// Checks the invariants that executing instructions relies on,
+    /// e.g. after restoring a state from an untrusted source.
+    pub fn validate(&self) -> Result<(), EmulatorError> {
+        validate_machine(
+            self.memory.len(),
+            self.program_counter,
+            self.selected_planes,
+            &self.stack,
+        )
+    }
 
-            self.inst_count += 1;
-            if self.inst_count == self.timers_update_interval {
-                self.update_timers();
-                self.inst_count = 0;
+    /// The address and bytes of the word at `address`, wrapped or rejected
+    /// past the end of memory according to the `PcOverflowPolicy`.
+    fn read_word(&self, address: usize) -> Result<(usize, (u8, u8)), EmulatorError> {
+        let size = self.memory.len();
+        let address = match self.pc_overflow_policy {
+            PcOverflowPolicy::Error if address >= size - 1 => {
+                return Err(EmulatorError::MemoryAccess);
             }
+            PcOverflowPolicy::Error => address,
+            PcOverflowPolicy::Wrap => address % size,
+        };
+        Ok((
+            address,
+            (self.memory[address], self.memory[(address + 1) % size]),
+        ))
+    }
+
+    /// The address and bytes of the instruction at the program counter.
+    fn read_instruction(&self) -> Result<(usize, (u8, u8)), EmulatorError> {
+        self.read_word(self.program_counter)
+    }
+
+    /// Reads the word at the program counter and advances it.
+    fn fetch(&mut self) -> Result<(usize, (u8, u8)), EmulatorError> {
+        let (address, instruction_bytes) = self.read_instruction()?;
+        self.program_counter = self.address_after(address, 2);
+        Ok((address, instruction_bytes))
+    }
+
+    /// `address` advanced by `offset` bytes, wrapped past the end of memory according
+    /// to the `PcOverflowPolicy`. Without wrapping the next fetch fails instead.
+    fn address_after(&self, address: usize, offset: usize) -> usize {
+        match self.pc_overflow_policy {
+            PcOverflowPolicy::Error => address + offset,
+            PcOverflowPolicy::Wrap => (address + offset) % self.memory.len(),
+        }
+    }
+
+    /// Rejects XO-CHIP instructions outside of XO-CHIP mode.
+    fn require_xo_chip(&self) -> Result<(), EmulatorError> {
+        if self.xo_chip {
+            Ok(())
+        } else {
+            Err(EmulatorError::Instruction())
+        }
+    }
+
+    /// True if `instruction` is the first word of `F000 NNNN` in XO-CHIP mode.
+    fn has_operand(&self, instruction: (u8, u8)) -> bool {
+        self.xo_chip && Instruction::has_operand(instruction)
+    }
+
+    /// Skips the next instruction, both words of it if it is `F000 NNNN`.
+    fn skip_next_instruction(&mut self) {
+        let long = self
+            .read_instruction()
+            .is_ok_and(|(_, instruction)| self.has_operand(instruction));
+        self.program_counter = self.address_after(self.program_counter, if long { 4 } else { 2 });
+    }
+
+    fn read_memory(&self, start_address: usize, len: usize) -> Result<&[u8], EmulatorError> {
+        self.memory
+            .get(start_address..start_address + len)
+            .ok_or(EmulatorError::MemoryAccess)
+    }
+
+    fn write_to_memory(&mut self, start_address: usize, buf: &[u8]) -> Result<(), EmulatorError> {
+        if start_address + buf.len() > self.memory.len() {
+            return Err(EmulatorError::MemoryAccess);
+        }
+        for (mem, data) in self.memory[start_address..].iter_mut().zip(buf.iter()) {
+            *mem = *data
         }
-        self.redraw = redraw;
         Ok(())
     }
 
-    /// returns true if a redraw is necessary
-    pub fn tick(&mut self) -> Result<bool, EmulatorError> {
-        let instruction_bytes = (
-            self.memory[self.program_counter],
-            self.memory[self.program_counter + 1],
-        );
-        self.program_counter += 2;
+    /// `write_to_memory` for instructions, recording the first write to a watched address.
+    fn write_watched(
+        &mut self,
+        instruction_address: usize,
+        start_address: usize,
+        buf: &[u8],
+    ) -> Result<(), EmulatorError> {
+        self.watchpoint_hit = (start_address..)
+            .zip(buf)
+            .find(|(address, _)| self.watchpoints.contains(address))
+            .and_then(|(address, &new)| {
+                Some(WatchpointHit {
+                    program_counter: instruction_address,
+                    address,
+                    old: *self.memory.get(address)?,
+                    new,
+                })
+            });
+        self.write_to_memory(start_address, buf)
+    }
 
-        let instruction = Instruction::parse(instruction_bytes)?;
+    fn plane_mut(&mut self, plane: usize) -> &mut [u128; HIRES_HEIGHT] {
+        match plane {
+            0 => &mut self.frame_buf,
+            _ => &mut self.plane2,
+        }
+    }
 
-        let mut redraw = false;
-        match instruction {
-            Instruction::ClearScreen => {
-                self.clear_screen();
-                redraw = true;
-            }
-            Instruction::Draw(x, y, n) => {
-                let x_coord = self.registers[x] as usize;
-                let y_coord = self.registers[y] as usize;
-                let any_px_erased = self.draw_to_fb(
-                    x_coord,
-                    y_coord,
-                    &self.memory[self.i..self.i + n].to_owned(),
-                );
-                if any_px_erased {
-                    self.registers[0xF] = 1;
-                } else {
-                    self.registers[0xF] = 0;
-                }
-                redraw = true;
-            }
-            Instruction::Jump(adr) => {
-                self.program_counter = adr;
-            }
-            Instruction::JumpWithOffset(adr) => {
-                self.program_counter = adr + self.registers[0] as usize;
-            }
-            Instruction::Call(adr) => {
-                self.stack.push(self.program_counter);
-                self.program_counter = adr;
+    /// Draws `sprite`, rows of `sprite_width` (8 or 16) pixels, into one plane, 0 or 1.
+    /// Returns the number of rows in which a pixel was erased.
+    fn draw_to_fb(
+        &mut self,
+        plane: usize,
+        x: usize,
+        y: usize,
+        sprite: &[u8],
+        sprite_width: usize,
+    ) -> usize {
+        let (width, height) = self.resolution();
+        let x = x & (width - 1);
+        let y = y & (height - 1);
+
+        let wraps = self.quirks.draw_wraps;
+        let mut collision_rows = 0;
+        for (row, bytes) in sprite.chunks(sprite_width / 8).enumerate() {
+            let fb_y = match y + row {
+                fb_y if fb_y < height => fb_y,
+                fb_y if wraps => fb_y - height,
+                _ => break,
+            };
+            let bits = bytes.iter().fold(0, |bits, &b| bits << 8 | b as u128);
+            let wide = bits << (width - sprite_width);
+            // columns shifted out on the right are clipped or wrap to the left edge
+            let mut sprite_row = wide >> x;
+            if wraps && x > width - sprite_width {
+                sprite_row |= (wide << (width - x)) & (u128::MAX >> (128 - width));
             }
-            Instruction::Return => {
-                let adr = self.stack.pop().ok_or(EmulatorError::StackUnderflow)?;
-                self.program_counter = adr;
+            let fb_row = &mut self.plane_mut(plane)[fb_y];
+            if *fb_row & sprite_row != 0 {
+                collision_rows += 1;
             }
-            Instruction::SkipIfRegisterEqualsConstant(x, c) => {
-                if self.registers[x] == c {
-                    self.program_counter += 2;
+            *fb_row ^= sprite_row;
+            if !self.toggles.is_empty() {
+                let mut bits = sprite_row;
+                while bits != 0 {
+                    let px = fb_y * HIRES_WIDTH + width - 1 - bits.trailing_zeros() as usize;
+                    self.toggles[px] = self.toggles[px].saturating_add(1);
+                    bits &= bits - 1;
                 }
             }
-            Instruction::SkipIfRegisterNotEqualsConstant(x, c) => {
-                if self.registers[x] != c {
-                    self.program_counter += 2;
-                }
+            if sprite_row != 0 {
+                self.dirty_rows |= 1 << fb_y;
             }
-            Instruction::SkipIfRegisterEqualsRegister(x, y) => {
-                if self.registers[x] == self.registers[y] {
-                    self.program_counter += 2;
-                }
+        }
+        collision_rows
+    }
+
+    /// Clears the planes whose bits are set in `planes`.
+    fn clear_planes(&mut self, planes: u8) {
+        for plane in planes_in(planes) {
+            let fb = self.plane_mut(plane);
+            let dirty = fb
+                .iter()
+                .enumerate()
+                .filter(|(_, &row)| row != 0)
+                .fold(0, |dirty, (y, _)| dirty | 1 << y);
+            *fb = [0; HIRES_HEIGHT];
+            self.dirty_rows |= dirty;
+        }
+    }
+
+    /// Clears the selected planes, only the first outside of XO-CHIP mode.
+    fn clear_screen(&mut self) {
+        self.clear_planes(self.selected_planes);
+    }
+
+    /// Replaces each visible row `y` of the selected planes with `f(rows, y)`,
+    /// computed from the rows before the change.
+    fn transform_rows(&mut self, f: impl Fn(&[u128], usize) -> u128) {
+        let height = self.resolution().1;
+        for plane in planes_in(self.selected_planes) {
+            let fb = self.plane_mut(plane);
+            let old = *fb;
+            let mut dirty = 0;
+            for y in 0..height {
+                fb[y] = f(&old[..height], y);
+                if fb[y] != old[y] {
+                    dirty |= 1 << y;
+                }
+            }
+            self.dirty_rows |= dirty;
+        }
+    }
+
+    /// Switches between 64x32 and 128x64, clearing all planes.
+    fn set_hires(&mut self, hires: bool) {
+        self.clear_planes(0b11);
+        self.hires = hires;
+        // the front-end has to redraw everything at the new size
+        self.dirty_rows = u64::MAX >> (64 - self.resolution().1);
+    }
+
+    pub fn set_keys_pressed(&mut self, keys_pressed: HashSet<Key>) {
+        self.keys_pressed = keys_pressed;
+    }
+
+    /// Queues a key press or release as it happened, an alternative to `set_keys_pressed`.
+    /// Each tick applies the next event that changes the pressed keys, so a key pressed
+    /// and released between two frames is still seen by EX9E, EXA1 and FX0A.
+    pub fn push_key_event(&mut self, key: Key, pressed: bool) {
+        self.key_events.push_back((key, pressed));
+    }
+
+    fn apply_key_event(&mut self) {
+        while let Some((key, pressed)) = self.key_events.pop_front() {
+            let changed = if pressed {
+                self.keys_pressed.insert(key)
+            } else {
+                self.keys_pressed.remove(&key)
+            };
+            if changed {
+                break;
+            }
+        }
+    }
+
+    /// Replaces the active quirks, taking effect with the next instruction.
+    ///
+    /// Switching quirks in the middle of a game can corrupt its state, since
+    /// earlier instructions ran under the old behavior. Prefer restarting the
+    /// ROM after changing them.
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    /// Replaces the beeper that plays while the sound timer is active.
+    pub fn set_beeper(&mut self, beeper: impl AudioBackend + 'static) {
+        self.set_audio_backend(Box::new(beeper));
+    }
+
+    /// Like `set_beeper` for a backend that is already boxed.
+    pub fn set_audio_backend(&mut self, beeper: Box<dyn AudioBackend>) {
+        self.beeper.stop();
+        self.beeper = beeper;
+        if let Some(pattern) = self.audio_pattern {
+            self.beeper.play_pattern(pattern, self.pitch);
+        }
+    }
+
+    /// Decodes the instruction that the next `tick` executes, without changing any state.
+    pub fn peek_next_instruction(&self) -> Result<Instruction, EmulatorError> {
+        let (address, instruction_bytes) = self.read_instruction()?;
+        if self.has_operand(instruction_bytes) {
+            let (_, operand) = self.read_word(address + 2)?;
+            Instruction::parse_long(instruction_bytes, operand)
+        } else {
+            Instruction::parse(instruction_bytes)
+        }
+    }
+
+    /// The newest variant whose opcodes have been executed so far.
+    /// Only code that was actually reached counts, unlike a static scan of the ROM.
+    pub fn detected_variant(&self) -> Mode {
+        self.detected_variant
+    }
+
+    /// Installs a hook that is called before each instruction is executed.
+    pub fn set_trace_hook(&mut self, trace_hook: Option<TraceHook>) {
+        self.trace_hook = trace_hook;
+    }
+
+    /// Reports a `Diagnostic` with the traced instruction where it applies.
+    /// Only takes effect while a trace hook is installed.
+    pub fn set_diagnostics(&mut self, diagnostics: bool) {
+        self.diagnostics = diagnostics;
+    }
+
+    pub fn set_machine_call_policy(&mut self, policy: MachineCallPolicy) {
+        self.machine_call_policy = policy;
+    }
+
+    pub fn set_pc_overflow_policy(&mut self, policy: PcOverflowPolicy) {
+        self.pc_overflow_policy = policy;
+    }
+
+    /// Makes `tick` fail with `EmulatorError::Watchdog` after more than `limit`
+    /// instructions in a row that neither draw, touch the timers nor read the keypad.
+    /// A jump to itself is treated as a deliberate halt and never trips it.
+    /// `None` (the default) disables the watchdog.
+    pub fn set_watchdog(&mut self, limit: Option<u64>) {
+        self.watchdog_limit = limit;
+        self.instructions_without_progress = 0;
+    }
+
+    /// Stops the delay and sound timers from counting down while instructions keep executing.
+    pub fn set_timers_frozen(&mut self, frozen: bool) {
+        self.timers_frozen = frozen;
+    }
+
+    pub fn timers_frozen(&self) -> bool {
+        self.timers_frozen
+    }
+
+    /// True if the screen changed since the last `clear_redraw`, including a
+    /// change of `resolution`.
+    pub fn should_redraw(&self) -> bool {
+        self.redraw
+    }
+
+    /// True while the SUPER-CHIP high-resolution mode is active.
+    pub fn hires(&self) -> bool {
+        self.hires
+    }
+
+    /// Current width and height of the screen in pixels.
+    pub fn resolution(&self) -> (usize, usize) {
+        if self.hires {
+            (HIRES_WIDTH, HIRES_HEIGHT)
+        } else {
+            (WIDTH, HEIGHT)
+        }
+    }
+
+    /// Rows that changed since the last `clear_redraw`, in ascending order.
+    pub fn dirty_rows(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..self.resolution().1).filter(|&y| self.dirty_rows & (1 << y) != 0)
+    }
+
+    /// Acknowledges that the front-end has drawn the current screen.
+    pub fn clear_redraw(&mut self) {
+        self.redraw = false;
+        self.dirty_rows = 0;
+    }
+
+    /// Number of sprites drawn during the last frame.
+    pub fn draws_last_frame(&self) -> u32 {
+        self.draws
+    }
+
+    /// Prints the pixels that were toggled at least `threshold` times in a frame
+    /// after each frame, the signature of flicker. `None` (the default) turns it off.
+    pub fn set_flicker_log(&mut self, threshold: Option<u8>) {
+        self.flicker_threshold = threshold;
+        self.toggles = match threshold {
+            Some(_) => vec![0; HIRES_WIDTH * HIRES_HEIGHT],
+            None => vec![],
+        };
+    }
+
+    /// Pixels toggled at least `threshold` times in the current frame as (x, y, toggles).
+    fn flickering_pixels(&self, threshold: u8) -> Vec<(usize, usize, u8)> {
+        self.toggles
+            .iter()
+            .enumerate()
+            .filter(|&(_, &toggles)| toggles >= threshold)
+            .map(|(px, &toggles)| (px % HIRES_WIDTH, px / HIRES_WIDTH, toggles))
+            .collect()
+    }
+
+    fn log_flicker(&self) {
+        let Some(threshold) = self.flicker_threshold else {
+            return;
+        };
+        let flickering = self.flickering_pixels(threshold);
+        if let Some(&(x, y, toggles)) = flickering.iter().max_by_key(|(_, _, toggles)| toggles) {
+            eprintln!(
+                "Frame {}: {} pixels toggled {} times or more in {} draws, ({}, {}) {} times.",
+                self.frame_count,
+                flickering.len(),
+                threshold,
+                self.draws,
+                x,
+                y,
+                toggles
+            );
+        }
+    }
+
+    /// Returns true if a sprite drawn during the last frame erased a pixel.
+    pub fn last_collision(&self) -> bool {
+        self.collision
+    }
+
+    /// Returns true if the pixel at (`x`, `y`) is on.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `x` or `y` is outside the current [`resolution`](Self::resolution).
+    pub fn get_pixel(&self, x: usize, y: usize) -> bool {
+        let (width, height) = self.resolution();
+        assert!(x < width, "x out of range: {}", x);
+        assert!(y < height, "y out of range: {}", y);
+        self.frame_buf[y] & (1 << (width - 1 - x)) != 0
+    }
+
+    /// The screen as one `u128` per row, bit `width - 1` being the leftmost pixel.
+    pub fn framebuffer_packed(&self) -> &[u128] {
+        &self.frame_buf[..self.resolution().1]
+    }
+
+    /// A hash of the screen that is the same on every platform and build, to check
+    /// a screen in tests without storing it. Covers both XO-CHIP planes.
+    pub fn framebuffer_hash(&self) -> u64 {
+        let rows = self.resolution().1;
+        self.frame_buf[..rows]
+            .iter()
+            .chain(&self.plane2[..rows])
+            .flat_map(|row| row.to_le_bytes())
+            .fold(FNV_OFFSET_BASIS, |hash, byte| {
+                (hash ^ byte as u64).wrapping_mul(FNV_PRIME)
+            })
+    }
+
+    /// The color of a pixel in XO-CHIP terms, bit 0 from the first plane and bit 1
+    /// from the second. Outside of XO-CHIP mode only 0 and 1 occur.
+    pub fn pixel_color(&self, x: usize, y: usize) -> u8 {
+        let bit = 1 << (self.resolution().0 - 1 - x);
+        self.get_pixel(x, y) as u8 | ((self.plane2[y] & bit != 0) as u8) << 1
+    }
+
+    /// The screen as rows of `pixel_color`, for front-ends that show four colors.
+    pub fn get_framebuffer_colors(&self) -> Vec<Vec<u8>> {
+        let (width, height) = self.resolution();
+        (0..height)
+            .map(|y| (0..width).map(|x| self.pixel_color(x, y)).collect())
+            .collect()
+    }
+
+    /// Positions (x, y) of the pixels that are on, row by row from the top left.
+    pub fn lit_pixels(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        let width = self.resolution().0;
+        self.framebuffer_packed()
+            .iter()
+            .enumerate()
+            .flat_map(move |(y, &row)| {
+                let mut bits = row;
+                std::iter::from_fn(move || {
+                    if bits == 0 {
+                        return None;
+                    }
+                    let bit = 127 - bits.leading_zeros() as usize;
+                    bits &= !(1 << bit);
+                    Some((width - 1 - bit, y))
+                })
+            })
+    }
+
+    /// Returns an unpacked copy of the screen at the current resolution, indexed by `[y][x]`.
+    pub fn get_framebuffer(&self) -> Vec<Vec<bool>> {
+        let (width, height) = self.resolution();
+        (0..height)
+            .map(|y| (0..width).map(|x| self.get_pixel(x, y)).collect())
+            .collect()
+    }
+
+    /// V0 to VF.
+    pub fn registers(&self) -> &[u8; 16] {
+        &self.registers
+    }
+
+    pub fn index_register(&self) -> usize {
+        self.i
+    }
+
+    /// Address of the instruction the next `tick` executes.
+    pub fn program_counter(&self) -> usize {
+        self.program_counter
+    }
+
+    pub fn delay_timer(&self) -> u8 {
+        self.delay_timer
+    }
+
+    pub fn sound_timer(&self) -> u8 {
+        self.sound_timer
+    }
+
+    /// `len` bytes of memory starting at `start`.
+    pub fn dump_memory(&self, start: usize, len: usize) -> Result<&[u8], EmulatorError> {
+        self.read_memory(start, len)
+    }
+
+    /// A readable snapshot of the registers, timers, stack and the 16 bytes at I.
+    pub fn dump_state(&self) -> String {
+        self.dump_state_with_memory(self.i..self.i + 16)
+    }
+
+    /// Like `dump_state`, with a hex view of `memory` instead, clipped to the memory size.
+    pub fn dump_state_with_memory(&self, memory: Range<usize>) -> String {
+        let mut dump = String::new();
+        writeln!(
+            dump,
+            "PC {:#05X}  I {:#05X}  DT {:3}  ST {:3}",
+            self.program_counter, self.i, self.delay_timer, self.sound_timer
+        )
+        .unwrap();
+        for (n, registers) in self.registers.chunks(8).enumerate() {
+            let line = registers
+                .iter()
+                .enumerate()
+                .map(|(x, v)| format!("V{:X} {:02X}", n * 8 + x, v))
+                .collect::<Vec<_>>()
+                .join("  ");
+            writeln!(dump, "{}", line).unwrap();
+        }
+        let stack = self
+            .stack
+            .iter()
+            .map(|adr| format!("{:#05X}", adr))
+            .collect::<Vec<_>>()
+            .join(" ");
+        writeln!(dump, "Stack [{}]", stack).unwrap();
+
+        let end = memory.end.min(self.memory.len());
+        let mut address = memory.start;
+        while address < end {
+            let bytes = &self.memory[address..end.min(address + 16)];
+            let hex = bytes
+                .iter()
+                .map(|b| format!("{:02X}", b))
+                .collect::<Vec<_>>()
+                .join(" ");
+            writeln!(dump, "{:#05X}: {}", address, hex).unwrap();
+            address += bytes.len();
+        }
+        dump
+    }
+
+    pub fn core_state(&self) -> CoreState<'_> {
+        let (width, height) = self.resolution();
+        CoreState {
+            framebuffer: self.framebuffer_packed(),
+            width,
+            height,
+            redraw: self.redraw,
+            collision: self.collision,
+            delay_timer: self.delay_timer,
+            sound_timer: self.sound_timer,
+            keys_pressed: &self.keys_pressed,
+        }
+    }
+
+    /// Number of `run_frame` calls so far, i.e. a clock running at `FPS`.
+    pub fn frame_count(&self) -> u64 {
+        self.frame_count
+    }
+
+    /// Number of instructions executed since the last `reset`, including ones
+    /// that failed.
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// Makes `run_frame_debug` stop when an instruction writes to `address`.
+    pub fn add_watchpoint(&mut self, address: usize) {
+        self.watchpoints.insert(address);
+    }
+
+    pub fn remove_watchpoint(&mut self, address: usize) {
+        self.watchpoints.remove(&address);
+    }
+
+    /// Makes `run_until_breakpoint` and `run_frame_debug` stop before executing the
+    /// instruction at `address`.
+    pub fn set_breakpoint(&mut self, address: usize) {
+        self.breakpoints.insert(address);
+    }
+
+    pub fn clear_breakpoint(&mut self, address: usize) {
+        self.breakpoints.remove(&address);
+    }
+
+    /// Executes a single instruction, e.g. to step through a program by hand.
+    /// `should_redraw` reports a changed screen as after `run_frame`, timers don't run.
+    pub fn step(&mut self) -> Result<(), EmulatorError> {
+        let redraw = self.tick()?;
+        self.redraw |= redraw || std::mem::take(&mut self.redraw_requested);
+        Ok(())
+    }
+
+    /// Ticks until the program counter reaches a breakpoint and returns it.
+    /// Always executes at least one instruction, so calling it again continues
+    /// from a breakpoint. Timers don't run and it only returns early on an error,
+    /// e.g. from the watchdog, so without a reachable breakpoint it never returns.
+    pub fn run_until_breakpoint(&mut self) -> Result<usize, EmulatorError> {
+        loop {
+            self.tick()?;
+            if self.breakpoints.contains(&self.program_counter) {
+                return Ok(self.program_counter);
+            }
+        }
+    }
+
+    pub fn run_frame(&mut self) -> Result<(), EmulatorError> {
+        self.run_frame_inner(false)?;
+        Ok(())
+    }
+
+    /// Runs `n` frames and returns the screen afterwards, stopping at the first error.
+    pub fn run_frames(&mut self, n: u32) -> Result<Vec<Vec<bool>>, EmulatorError> {
+        for _ in 0..n {
+            self.run_frame()?;
+        }
+        Ok(self.get_framebuffer())
+    }
+
+    /// Like `run_frame`, but ends the frame early when a watchpoint triggers or
+    /// a breakpoint is reached.
+    pub fn run_frame_debug(&mut self) -> Result<RunOutcome, EmulatorError> {
+        self.run_frame_inner(true)
+    }
+
+    fn run_frame_inner(&mut self, debug: bool) -> Result<RunOutcome, EmulatorError> {
+        self.record_rewind_state();
+        self.frame_count += 1;
+        let mut redraw = std::mem::take(&mut self.redraw_requested);
+        self.collision = false;
+        self.waiting_for_vblank = false;
+        self.draws = 0;
+        self.toggles.fill(0);
+        let mut outcome = RunOutcome::FrameComplete;
+        for _ in 0..self.ticks_per_frame {
+            // a draw under the display wait starts after the vertical blank
+            if self.waiting_for_vblank {
+                continue;
+            }
+            if self.busy_slots > 0 {
+                self.busy_slots -= 1;
+                continue;
+            }
+            redraw = self.tick()? || redraw;
+
+            if let Some(hit) = self.watchpoint_hit.take() {
+                if debug {
+                    outcome = RunOutcome::Watchpoint(hit);
+                    break;
+                }
+            }
+            if debug && self.breakpoints.contains(&self.program_counter) {
+                outcome = RunOutcome::Breakpoint(self.program_counter);
+                break;
+            }
+        }
+        // once per frame, so the timers count at 60 Hz whatever the clock speed
+        self.update_timers();
+        self.redraw |= redraw;
+        if let RunOutcome::FrameComplete = outcome {
+            self.log_flicker();
+        }
+        Ok(outcome)
+    }
+
+    /// returns true if a redraw is necessary
+    pub fn tick(&mut self) -> Result<bool, EmulatorError> {
+        self.apply_key_event();
+        let (instruction_address, instruction_bytes) = self.fetch()?;
+        self.watchpoint_hit = None;
+        let opcode = u16::from_be_bytes([instruction_bytes.0, instruction_bytes.1]);
+        // recorded before decoding, so opcodes this emulator does not support still count
+        self.detected_variant = self.detected_variant.max(Mode::of_opcode(opcode));
+
+        let instruction = if self.has_operand(instruction_bytes) {
+            let (_, operand) = self.fetch()?;
+            Instruction::parse_long(instruction_bytes, operand)?
+        } else {
+            Instruction::parse(instruction_bytes)?
+        };
+        self.cycles += 1;
+
+        let diagnostic = if self.diagnostics && self.trace_hook.is_some() {
+            self.diagnose(&instruction)
+        } else {
+            None
+        };
+        if let Some(trace_hook) = &mut self.trace_hook {
+            trace_hook(&Trace {
+                program_counter: instruction_address,
+                opcode,
+                instruction: &instruction,
+                registers: &self.registers,
+                i: self.i,
+                diagnostic,
+            });
+        }
+
+        let mut redraw = false;
+        match instruction {
+            Instruction::MachineCall(adr) => match self.machine_call_policy {
+                MachineCallPolicy::Error => return Err(EmulatorError::Instruction()),
+                MachineCallPolicy::Ignore => {}
+                MachineCallPolicy::Log => {
+                    if !crate::is_quiet() {
+                        eprintln!(
+                            "Ignoring machine code call to {:#05X} at {:#05X}.",
+                            adr, instruction_address
+                        );
+                    }
+                }
+            },
+            Instruction::ClearScreen => {
+                self.clear_screen();
+                redraw = true;
+            }
+            // scrolling moves by pixels of the current resolution, vacated pixels are cleared
+            Instruction::ScrollDown(n) => {
+                self.transform_rows(|rows, y| if y >= n { rows[y - n] } else { 0 });
+                redraw = true;
+            }
+            Instruction::ScrollRight => {
+                self.transform_rows(|rows, y| rows[y] >> 4);
+                redraw = true;
+            }
+            Instruction::ScrollLeft => {
+                let mask = u128::MAX >> (128 - self.resolution().0);
+                self.transform_rows(|rows, y| (rows[y] << 4) & mask);
+                redraw = true;
+            }
+            Instruction::SelectPlane(planes) => {
+                self.require_xo_chip()?;
+                self.selected_planes = planes & 0b11;
+            }
+            Instruction::LowRes => {
+                self.set_hires(false);
+                redraw = true;
+            }
+            Instruction::HighRes => {
+                self.set_hires(true);
+                redraw = true;
+            }
+            Instruction::Draw(x, y, n) => {
+                let x_coord = self.registers[x] as usize;
+                let y_coord = self.registers[y] as usize;
+                let (sprite_len, sprite_width) = self.sprite_shape(n);
+                // with both XO-CHIP planes selected the second plane's sprite follows the first
+                let planes: Vec<usize> = planes_in(self.selected_planes).collect();
+                let sprites = self
+                    .read_memory(self.i, sprite_len * planes.len())?
+                    .to_vec();
+                let mut collision_rows = 0;
+                for (plane, sprite) in planes.into_iter().zip(sprites.chunks(sprite_len.max(1))) {
+                    collision_rows +=
+                        self.draw_to_fb(plane, x_coord, y_coord, sprite, sprite_width);
+                }
+                if collision_rows > 0 {
+                    self.collision = true;
+                }
+                // SUPER-CHIP counts the rows with collisions in high resolution
+                self.registers[0xF] = if self.hires {
+                    collision_rows.min(u8::MAX as usize) as u8
+                } else {
+                    (collision_rows > 0) as u8
+                };
+                self.waiting_for_vblank = self.quirks.display_wait;
+                if self.draw_rows_per_slot > 0 {
+                    let rows = (sprite_len * 8 / sprite_width) as u16;
+                    self.busy_slots = rows.div_ceil(self.draw_rows_per_slot as u16);
+                }
+                self.draws += 1;
+                redraw = true;
+            }
+            Instruction::Jump(adr) => {
+                self.program_counter = adr;
+            }
+            Instruction::JumpWithOffset(adr) => {
+                let offset_register = if self.quirks.jump_offset_uses_vx {
+                    (adr >> 8) & 0xF
+                } else {
+                    0
+                };
+                self.program_counter = adr + self.registers[offset_register] as usize;
+            }
+            Instruction::Call(adr) => {
+                if self.stack.len() == STACK_SIZE {
+                    return Err(EmulatorError::StackOverflow);
+                }
+                self.stack.push(self.program_counter);
+                self.program_counter = adr;
+            }
+            Instruction::Return => {
+                let adr = self.stack.pop().ok_or(EmulatorError::StackUnderflow)?;
+                self.program_counter = adr;
+            }
+            Instruction::SkipIfRegisterEqualsConstant(x, c) => {
+                if self.registers[x] == c {
+                    self.skip_next_instruction();
+                }
+            }
+            Instruction::SkipIfRegisterNotEqualsConstant(x, c) => {
+                if self.registers[x] != c {
+                    self.skip_next_instruction();
+                }
+            }
+            Instruction::SkipIfRegisterEqualsRegister(x, y) => {
+                if self.registers[x] == self.registers[y] {
+                    self.skip_next_instruction();
+                }
             }
             Instruction::SkipIfRegisterNotEqualsRegister(x, y) => {
                 if self.registers[x] != self.registers[y] {
-                    self.program_counter += 2;
+                    self.skip_next_instruction();
                 }
             }
             Instruction::SetRegisterToValue(x, value) => {
@@ -311,58 +1907,78 @@ impl Emulator {
             }
             Instruction::BinaryOR(x, y) => {
                 self.registers[x] |= self.registers[y];
+                self.reset_vf_after_logic();
             }
             Instruction::BinaryAND(x, y) => {
                 self.registers[x] &= self.registers[y];
+                self.reset_vf_after_logic();
             }
             Instruction::BinaryXOR(x, y) => {
                 self.registers[x] ^= self.registers[y];
+                self.reset_vf_after_logic();
             }
             Instruction::AddValueToRegister(x, value) => {
                 self.registers[x] = self.registers[x].wrapping_add(value);
             }
+            // VF is written after VX, so with X = F the flag wins over the result.
+            // Both operands are read before either register is written.
             Instruction::AddRegisterToRegister(x, y) => {
-                let sum = self.registers[x].wrapping_add(self.registers[y]);
-                self.registers[0xF] = (sum < self.registers[x]) as u8;
+                // VF = 1 on carry
+                let (sum, carry) = self.registers[x].overflowing_add(self.registers[y]);
                 self.registers[x] = sum;
+                self.registers[0xF] = carry as u8;
             }
             Instruction::SubstractXMinusY(x, y) => {
-                let flag = (self.registers[x] > self.registers[y]) as u8;
+                // VF = 1 if there is no borrow, i.e. VX >= VY
+                let flag = (self.registers[x] >= self.registers[y]) as u8;
                 self.registers[x] = self.registers[x].wrapping_sub(self.registers[y]);
                 self.registers[0xF] = flag;
             }
             Instruction::SubstractYMinusX(x, y) => {
-                let flag = (self.registers[y] > self.registers[x]) as u8;
+                // VF = 1 if there is no borrow, i.e. VY >= VX
+                let flag = (self.registers[y] >= self.registers[x]) as u8;
                 self.registers[x] = self.registers[y].wrapping_sub(self.registers[x]);
                 self.registers[0xF] = flag;
             }
             Instruction::ShiftRight(x, y) => {
-                let flag = self.registers[y] & 1; // shifted out bit
-                self.registers[x] = self.registers[y] >> 1;
+                let value = self.shift_operand(x, y);
+                let flag = value & 1; // shifted out bit
+                self.registers[x] = value >> 1;
                 self.registers[0xF] = flag;
             }
             Instruction::ShiftLeft(x, y) => {
-                let flag = (self.registers[y] & 128 != 0) as u8; // shifted out bit
-                self.registers[x] = self.registers[y] << 1;
+                let value = self.shift_operand(x, y);
+                let flag = (value & 128 != 0) as u8; // shifted out bit
+                self.registers[x] = value << 1;
                 self.registers[0xF] = flag;
             }
+            // key numbers above 0xF don't exist and are never pressed
             Instruction::SkipIfKeyIsPressed(x) => {
-                let key = Key::from_num(self.registers[x]);
-                if self.keys_pressed.contains(&key) {
-                    self.program_counter += 2;
+                if Key::is_pressed(self.registers[x], &self.keys_pressed) {
+                    self.skip_next_instruction();
                 }
             }
             Instruction::SkipIfKeyIsNotPressed(x) => {
-                let key = Key::from_num(self.registers[x]);
-                if !self.keys_pressed.contains(&key) {
-                    self.program_counter += 2;
+                if !Key::is_pressed(self.registers[x], &self.keys_pressed) {
+                    self.skip_next_instruction();
                 }
             }
+            // waits until a key is pressed and released again
             Instruction::GetKey(x) => {
-                if self.keys_pressed.len() == 1 {
-                    self.registers[x] = self.keys_pressed.iter().next().unwrap().to_num();
-                } else {
-                    self.program_counter -= 2;
+                let released = self
+                    .keys_awaiting_release
+                    .difference(&self.keys_pressed)
+                    .map(Key::to_num)
+                    .min();
+                match released {
+                    Some(key) => {
+                        self.registers[x] = key;
+                        self.keys_awaiting_release.clear();
+                    }
+                    None => {
+                        self.keys_awaiting_release = self.keys_pressed.clone();
+                        self.program_counter = instruction_address;
+                    }
                 }
             }
             Instruction::GetDelayTimerValue(x) => {
@@ -378,50 +1994,1900 @@ impl Emulator {
                 }
             }
             Instruction::StoreRegistersToMemory(end_index) => {
-                self.write_to_memory(self.i.clone(), &self.registers[0..=end_index].to_owned())?;
+                let registers = self.registers;
+                self.write_watched(instruction_address, self.i, &registers[0..=end_index])?;
+                self.increment_index_after_load_store(end_index);
             }
             Instruction::LoadRegistersFromMemory(end_index) => {
-                for (mem, data) in self.memory[self.i..]
-                    .iter()
-                    .zip(self.registers[0..=end_index].iter_mut())
-                {
-                    *data = *mem;
-                }
+                let data = self.read_memory(self.i, end_index + 1)?.to_vec();
+                self.registers[0..=end_index].copy_from_slice(&data);
+                self.increment_index_after_load_store(end_index);
             }
             Instruction::SetIndexRegister(value) => {
                 self.i = value;
             }
             Instruction::AddRegisterToIndexRegister(x) => {
-                self.i += self.registers[x] as usize;
+                let sum = self.i + self.registers[x] as usize;
+                if self.quirks.index_overflow_flag {
+                    self.i = sum & 0xFFF;
+                    self.registers[0xF] = (sum > 0xFFF) as u8;
+                } else {
+                    self.i = sum;
+                }
+            }
+            Instruction::StoreAudioPattern => {
+                self.require_xo_chip()?;
+                let mut pattern = [0; 16];
+                pattern.copy_from_slice(self.read_memory(self.i, 16)?);
+                self.audio_pattern = Some(pattern);
+                self.beeper.play_pattern(pattern, self.pitch);
+            }
+            Instruction::SetPitch(x) => {
+                self.require_xo_chip()?;
+                self.pitch = self.registers[x];
+                if let Some(pattern) = self.audio_pattern {
+                    self.beeper.play_pattern(pattern, self.pitch);
+                }
+            }
+            Instruction::SetIndexRegisterLong(adr) => {
+                self.i = adr as usize;
             }
             Instruction::LoadSprite(x) => {
                 self.i = FONT_START_ADDRESS + self.registers[x] as usize * 5;
             }
+            Instruction::LoadBigSprite(x) => {
+                self.i = BIG_FONT_START_ADDRESS + self.registers[x] as usize * 10;
+            }
+            Instruction::StoreRplFlags(x) => {
+                let flags = self
+                    .rpl_flags
+                    .get_mut(0..=x)
+                    .ok_or(EmulatorError::MemoryAccess)?;
+                flags.copy_from_slice(&self.registers[0..=x]);
+            }
+            Instruction::LoadRplFlags(x) => {
+                let flags = self
+                    .rpl_flags
+                    .get(0..=x)
+                    .ok_or(EmulatorError::MemoryAccess)?;
+                self.registers[0..=x].copy_from_slice(flags);
+            }
             Instruction::BCD(x) => {
                 let n = [
                     self.registers[x] / 100,
                     (self.registers[x] % 100) / 10,
                     self.registers[x] % 10,
                 ];
-                self.write_to_memory(self.i, &n)?;
+                self.write_watched(instruction_address, self.i, &n)?;
             }
             Instruction::Random(x, c) => {
                 self.registers[x] = self.rand_num_gen.gen::<u8>() & c;
             }
         }
 
+        if let Some(limit) = self.watchdog_limit {
+            if Self::makes_progress(&instruction, instruction_address) {
+                self.instructions_without_progress = 0;
+            } else {
+                self.instructions_without_progress += 1;
+                if self.instructions_without_progress > limit {
+                    return Err(EmulatorError::Watchdog);
+                }
+            }
+        }
+
         Ok(redraw)
     }
 
-    fn update_timers(&mut self) {
-        if self.sound_timer > 0 {
-            self.sound_timer -= 1;
-        } else {
-            self.beeper.stop();
+    /// Whether the instruction has an observable effect or is a self-jump halt.
+    fn makes_progress(instruction: &Instruction, address: usize) -> bool {
+        match *instruction {
+            Instruction::Jump(adr) => adr == address,
+            Instruction::ClearScreen
+            | Instruction::LowRes
+            | Instruction::HighRes
+            | Instruction::ScrollDown(_)
+            | Instruction::ScrollRight
+            | Instruction::ScrollLeft
+            | Instruction::Draw(..)
+            | Instruction::SkipIfKeyIsPressed(_)
+            | Instruction::SkipIfKeyIsNotPressed(_)
+            | Instruction::GetKey(_)
+            | Instruction::GetDelayTimerValue(_)
+            | Instruction::SetDelayTimer(_)
+            | Instruction::SetSoundTimer(_) => true,
+            _ => false,
         }
+    }
 
-        if self.delay_timer > 0 {
-            self.delay_timer -= 1;
+    fn diagnose(&self, instruction: &Instruction) -> Option<Diagnostic> {
+        match *instruction {
+            Instruction::Draw(_, _, n) => {
+                let (start, end) = (self.i, self.i + self.sprite_shape(n).0);
+                [FONT_START_ADDRESS, FONT_END_ADDRESS, self.program_start]
+                    .iter()
+                    .any(|&boundary| start < boundary && boundary < end)
+                    .then_some(Diagnostic::SpriteCrossesRegion { start, end })
+            }
+            _ => None,
+        }
+    }
+
+    /// The length in bytes and the width of the sprite drawn by DXYN. DXY0 is 16x16
+    /// in high resolution and in XO-CHIP mode, otherwise it draws nothing as on CHIP-8.
+    fn sprite_shape(&self, n: usize) -> (usize, usize) {
+        match n {
+            0 if self.hires || self.xo_chip => (32, 16),
+            n => (n, 8),
         }
     }
+
+    fn shift_operand(&self, x: usize, y: usize) -> u8 {
+        if self.quirks.shift_uses_vy {
+            self.registers[y]
+        } else {
+            self.registers[x]
+        }
+    }
+
+    fn reset_vf_after_logic(&mut self) {
+        if self.quirks.reset_vf_on_logic {
+            self.registers[0xF] = 0;
+        }
+    }
+
+    fn increment_index_after_load_store(&mut self, end_index: usize) {
+        match self.quirks.index_increment_on_load_store {
+            IndexIncrement::None => {}
+            IndexIncrement::X => self.i += end_index,
+            IndexIncrement::XPlusOne => self.i += end_index + 1,
+        }
+    }
+
+    fn update_timers(&mut self) {
+        if self.timers_frozen {
+            return;
+        }
+
+        if self.sound_timer > 0 {
+            self.sound_timer -= 1;
+        } else {
+            self.beeper.stop();
+        }
+
+        if self.delay_timer > 0 {
+            self.delay_timer -= 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seed() {
+        // V0..VF = random & 0xFF, then start over
+        let mut program: Vec<u8> = (0..16).flat_map(|x| [0xC0 | x, 0xFF]).collect();
+        program.extend([0x12, 0x00]);
+        let run = |e: &mut Emulator| {
+            (0..17 * 4)
+                .map(|_| {
+                    e.tick().unwrap();
+                    e.registers
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let mut a = Emulator::new_with_seed(400, program.clone(), 42).unwrap();
+        let mut b = Emulator::new_with_seed(400, program.clone(), 42).unwrap();
+        let mut c = Emulator::new_with_seed(400, program, 43).unwrap();
+        let registers = run(&mut a);
+        assert_eq!(registers, run(&mut b));
+        assert_ne!(registers, run(&mut c));
+
+        a.reset();
+        assert_eq!(registers, run(&mut a));
+    }
+
+    #[test]
+    fn test_memory_init() {
+        let program = vec![0x12, 0x00];
+        let tests = [
+            (MemoryInit::Zeros, 0x00),
+            (MemoryInit::Ones, 0xFF),
+            (MemoryInit::Pattern(0xAA), 0xAA),
+        ];
+
+        for (memory_init, expected) in tests {
+            let e = Emulator::new_with_memory_init(400, program.clone(), memory_init).unwrap();
+            assert_eq!(e.memory[PROGRAM_START_ADDRESS..][..2], program[..]);
+            assert!(e.memory[PROGRAM_START_ADDRESS + 2..]
+                .iter()
+                .all(|&b| b == expected));
+            assert_eq!(e.memory[FONT_START_ADDRESS..][..5], FONT[0]);
+        }
+    }
+
+    #[test]
+    fn test_new_with_quirks() {
+        let e = Emulator::new(400, vec![]).unwrap();
+        assert_eq!(e.quirks(), Quirks::default());
+
+        let quirks = Quirks {
+            jump_offset_uses_vx: true,
+            draw_wraps: true,
+            ..Quirks::default()
+        };
+        let e = Emulator::new_with_quirks(400, vec![], quirks).unwrap();
+        assert_eq!(e.quirks(), quirks);
+    }
+
+    #[test]
+    fn test_quirk_profiles() {
+        let chip8 = Quirks::chip8();
+        assert!(chip8.shift_uses_vy && chip8.reset_vf_on_logic && chip8.display_wait);
+        assert!(!chip8.jump_offset_uses_vx && !chip8.draw_wraps);
+        assert_eq!(
+            chip8.index_increment_on_load_store,
+            IndexIncrement::XPlusOne
+        );
+
+        let schip = Quirks::schip();
+        assert!(!schip.shift_uses_vy && !schip.reset_vf_on_logic && !schip.display_wait);
+        assert!(schip.jump_offset_uses_vx && !schip.draw_wraps);
+        assert_eq!(schip.index_increment_on_load_store, IndexIncrement::None);
+
+        let xochip = Quirks::xochip();
+        assert!(xochip.shift_uses_vy && !xochip.reset_vf_on_logic && !xochip.display_wait);
+        assert!(!xochip.jump_offset_uses_vx && xochip.draw_wraps);
+        assert_eq!(
+            xochip.index_increment_on_load_store,
+            IndexIncrement::XPlusOne
+        );
+
+        for name in QUIRK_PROFILES {
+            assert!(Quirks::profile(name).is_some(), "{}", name);
+        }
+        assert_eq!(Quirks::profile("xochip"), Some(xochip));
+        assert_eq!(Quirks::profile("CHIP-8"), None);
+        assert_eq!(Target::Cosmac.quirks(), chip8);
+        assert_eq!(Target::SuperChip.quirks(), schip);
+    }
+
+    #[test]
+    fn test_set_quirks_takes_effect_mid_run() {
+        // V0 = 0x10, V1 = 0x01, V0 >>= V1 (twice)
+        let program = vec![0x60, 0x10, 0x61, 0x01, 0x80, 0x16, 0x80, 0x16];
+        let mut e = Emulator::new(400, program).unwrap();
+        for _ in 0..3 {
+            e.tick().unwrap();
+        }
+        assert_eq!(e.registers[0], 0x00);
+
+        e.set_quirks(Quirks {
+            shift_uses_vy: false,
+            ..Quirks::default()
+        });
+        e.registers[0] = 0x10;
+        e.tick().unwrap();
+        assert_eq!(e.registers[0], 0x08);
+        assert!(!e.quirks().shift_uses_vy);
+    }
+
+    #[test]
+    fn test_display_wait() {
+        // two draws followed by a self-jump
+        let program = vec![0xD0, 0x01, 0xD0, 0x01, 0x12, 0x04];
+
+        let mut e = Emulator::new_with_target(program.clone(), Target::Cosmac).unwrap();
+        e.run_frame().unwrap();
+        assert_eq!(e.program_counter, PROGRAM_START_ADDRESS + 2);
+        e.run_frame().unwrap();
+        assert_eq!(e.program_counter, PROGRAM_START_ADDRESS + 4);
+
+        let mut e = Emulator::new_with_target(program, Target::SuperChip).unwrap();
+        e.run_frame().unwrap();
+        assert_eq!(e.program_counter, PROGRAM_START_ADDRESS + 4);
+    }
+
+    #[test]
+    fn test_display_wait_one_draw_per_frame() {
+        // draw and jump back, forever
+        let program = vec![0xD0, 0x01, 0x12, 0x00];
+        for (display_wait, draws) in [(true, 1), (false, 5)] {
+            let quirks = Quirks {
+                display_wait,
+                ..Quirks::default()
+            };
+            let mut e = Emulator::new_with_quirks(600, program.clone(), quirks).unwrap();
+            for _ in 0..3 {
+                e.run_frame().unwrap();
+                assert_eq!(e.draws_last_frame(), draws, "display wait {}", display_wait);
+            }
+        }
+    }
+
+    #[test]
+    fn test_detected_variant() {
+        // V0 = 1, CLS, scroll down (SCHIP), self-jump
+        let program = vec![0x60, 0x01, 0x00, 0xE0, 0x00, 0xC1, 0x12, 0x06];
+        let mut e = Emulator::new(400, program).unwrap();
+        e.set_machine_call_policy(MachineCallPolicy::Ignore);
+        e.tick().unwrap();
+        e.tick().unwrap();
+        assert_eq!(e.detected_variant(), Mode::Chip8);
+        e.tick().unwrap();
+        e.tick().unwrap();
+        assert_eq!(e.detected_variant(), Mode::SuperChip);
+
+        assert_eq!(Mode::of_opcode(0xD120), Mode::SuperChip);
+        assert_eq!(Mode::of_opcode(0xD121), Mode::Chip8);
+        assert_eq!(Mode::of_opcode(0xF385), Mode::SuperChip);
+        assert_eq!(Mode::of_opcode(0x5122), Mode::XoChip);
+        assert_eq!(Mode::of_opcode(0xF201), Mode::XoChip);
+        assert_eq!(Mode::of_opcode(0xF000), Mode::XoChip);
+        assert_eq!(Mode::of_opcode(0x00EE), Mode::Chip8);
+    }
+
+    #[test]
+    fn test_validate() {
+        let e = Emulator::new(400, vec![]).unwrap();
+        assert_eq!(e.validate(), Ok(()));
+
+        let mut e = Emulator::new(400, vec![]).unwrap();
+        e.program_counter = MEMORY_SIZE;
+        assert!(matches!(e.validate(), Err(EmulatorError::InvalidState(_))));
+
+        // I = 0xFFF, V0 = 0xFF, I += V0
+        let mut e = Emulator::new(400, vec![0xAF, 0xFF, 0x60, 0xFF, 0xF0, 0x1E]).unwrap();
+        for _ in 0..3 {
+            e.tick().unwrap();
+        }
+        assert_eq!(e.i, 0x10FE);
+        assert_eq!(e.validate(), Ok(()));
+
+        let mut e = Emulator::new(400, vec![]).unwrap();
+        e.stack = vec![PROGRAM_START_ADDRESS; STACK_SIZE + 1];
+        assert!(matches!(e.validate(), Err(EmulatorError::InvalidState(_))));
+
+        let mut e = Emulator::new(400, vec![]).unwrap();
+        e.stack = vec![PROGRAM_START_ADDRESS, 0xFFFF];
+        assert!(matches!(e.validate(), Err(EmulatorError::InvalidState(_))));
+    }
+
+    #[test]
+    fn test_stack_limits() {
+        // a subroutine calling itself
+        let program = vec![0x22, 0x00];
+        let mut e = Emulator::new(400, program).unwrap();
+        for _ in 0..STACK_SIZE {
+            e.tick().unwrap();
+        }
+        assert_eq!(e.tick(), Err(EmulatorError::StackOverflow));
+        assert_eq!(e.stack.len(), STACK_SIZE);
+
+        let program = vec![0x00, 0xEE];
+        let mut e = Emulator::new(400, program).unwrap();
+        assert_eq!(e.tick(), Err(EmulatorError::StackUnderflow));
+    }
+
+    /// Runs a single ALU instruction 8XYN with the given registers preset.
+    fn run_alu(opcode: u16, presets: &[(usize, u8)]) -> [u8; 16] {
+        let mut e = Emulator::new(400, opcode.to_be_bytes().to_vec()).unwrap();
+        for &(r, value) in presets {
+            e.registers[r] = value;
+        }
+        e.tick().unwrap();
+        e.registers
+    }
+
+    #[test]
+    fn test_add_flag_ordering() {
+        let r = run_alu(0x8014, &[(0, 0xFF), (1, 0x02)]);
+        assert_eq!((r[0], r[0xF]), (0x01, 1));
+        let r = run_alu(0x8014, &[(0, 0x10), (1, 0x02), (0xF, 1)]);
+        assert_eq!((r[0], r[0xF]), (0x12, 0));
+        // x == y
+        let r = run_alu(0x8004, &[(0, 0x80)]);
+        assert_eq!((r[0], r[0xF]), (0x00, 1));
+        // x == F: the flag overwrites the sum
+        let r = run_alu(0x8F04, &[(0xF, 0x01), (0, 0x02)]);
+        assert_eq!(r[0xF], 0);
+        let r = run_alu(0x8F04, &[(0xF, 0xFF), (0, 0x02)]);
+        assert_eq!(r[0xF], 1);
+        // y == F: VF is read as an operand before it is overwritten
+        let r = run_alu(0x80F4, &[(0, 0xFF), (0xF, 0x01)]);
+        assert_eq!((r[0], r[0xF]), (0x00, 1));
+    }
+
+    #[test]
+    fn test_sub_flag_ordering() {
+        let r = run_alu(0x8015, &[(0, 0x05), (1, 0x03)]);
+        assert_eq!((r[0], r[0xF]), (0x02, 1));
+        let r = run_alu(0x8015, &[(0, 0x03), (1, 0x05)]);
+        assert_eq!((r[0], r[0xF]), (0xFE, 0));
+        // equal operands do not borrow
+        let r = run_alu(0x8015, &[(0, 0x05), (1, 0x05)]);
+        assert_eq!((r[0], r[0xF]), (0x00, 1));
+        // x == y
+        let r = run_alu(0x8005, &[(0, 0x42)]);
+        assert_eq!((r[0], r[0xF]), (0x00, 1));
+        // x == F
+        let r = run_alu(0x8F05, &[(0xF, 0x01), (0, 0x02)]);
+        assert_eq!(r[0xF], 0);
+        // y == F
+        let r = run_alu(0x80F5, &[(0, 0x01), (0xF, 0x02)]);
+        assert_eq!((r[0], r[0xF]), (0xFF, 0));
+
+        let r = run_alu(0x8017, &[(0, 0x03), (1, 0x05)]);
+        assert_eq!((r[0], r[0xF]), (0x02, 1));
+        let r = run_alu(0x8017, &[(0, 0x05), (1, 0x03)]);
+        assert_eq!((r[0], r[0xF]), (0xFE, 0));
+        let r = run_alu(0x8017, &[(0, 0x05), (1, 0x05)]);
+        assert_eq!((r[0], r[0xF]), (0x00, 1));
+        // x == F
+        let r = run_alu(0x8F07, &[(0xF, 0x01), (0, 0x02)]);
+        assert_eq!(r[0xF], 1);
+        // y == F
+        let r = run_alu(0x80F7, &[(0, 0x02), (0xF, 0x01)]);
+        assert_eq!((r[0], r[0xF]), (0xFF, 0));
+    }
+
+    #[test]
+    fn test_shift_flag_ordering() {
+        let r = run_alu(0x8016, &[(1, 0x03)]);
+        assert_eq!((r[0], r[0xF]), (0x01, 1));
+        let r = run_alu(0x801E, &[(1, 0x81)]);
+        assert_eq!((r[0], r[0xF]), (0x02, 1));
+        // x == F: the shifted out bit overwrites the result
+        let r = run_alu(0x8F06, &[(0, 0x02)]);
+        assert_eq!(r[0xF], 0);
+        let r = run_alu(0x8F0E, &[(0, 0x81)]);
+        assert_eq!(r[0xF], 1);
+
+        // shifting VF in place
+        let quirks = Quirks {
+            shift_uses_vy: false,
+            ..Quirks::default()
+        };
+        for (opcode, vf, flag) in [(0x8F06_u16, 0x02, 0), (0x8F0E, 0x81, 1)] {
+            let mut e =
+                Emulator::new_with_quirks(400, opcode.to_be_bytes().to_vec(), quirks).unwrap();
+            e.registers[0xF] = vf;
+            e.tick().unwrap();
+            assert_eq!(e.registers[0xF], flag, "{:04X}", opcode);
+        }
+    }
+
+    #[test]
+    fn test_index_overflow_flag() {
+        // I = 0xFFE, I += V0
+        let program = vec![0xAF, 0xFE, 0xF0, 0x1E];
+        let quirks = Quirks {
+            index_overflow_flag: true,
+            ..Quirks::default()
+        };
+        for (v0, i, flag) in [(0x01, 0xFFF, 0), (0x02, 0x000, 1), (0x05, 0x003, 1)] {
+            let mut e = Emulator::new_with_quirks(400, program.clone(), quirks).unwrap();
+            e.registers[0] = v0;
+            e.registers[0xF] = 0xAA;
+            e.tick().unwrap();
+            e.tick().unwrap();
+            assert_eq!((e.i, e.registers[0xF]), (i, flag), "V0 = {}", v0);
+        }
+
+        // without the quirk VF is untouched and I leaves the 12-bit space
+        let mut e = Emulator::new(400, program).unwrap();
+        e.registers[0] = 0x05;
+        e.tick().unwrap();
+        e.tick().unwrap();
+        assert_eq!((e.i, e.registers[0xF]), (0x1003, 0));
+    }
+
+    #[test]
+    fn test_machine_call_policy() {
+        // SYS 0x123, then V0 = 1
+        let program = vec![0x01, 0x23, 0x60, 0x01];
+
+        let mut e = Emulator::new(400, program.clone()).unwrap();
+        assert_eq!(e.tick(), Err(EmulatorError::Instruction()));
+
+        for policy in [MachineCallPolicy::Ignore, MachineCallPolicy::Log] {
+            let mut e = Emulator::new(400, program.clone()).unwrap();
+            e.set_machine_call_policy(policy);
+            e.tick().unwrap();
+            e.tick().unwrap();
+            assert_eq!(e.program_counter, PROGRAM_START_ADDRESS + 4);
+            assert_eq!(e.registers[0], 1);
+        }
+    }
+
+    #[test]
+    fn test_pc_overflow_policy() {
+        let program = vec![0x12, 0x00];
+
+        let mut e = Emulator::new(400, program.clone()).unwrap();
+        e.program_counter = MEMORY_SIZE - 1;
+        assert_eq!(e.tick(), Err(EmulatorError::MemoryAccess));
+
+        // 0xFFF: 0x60, 0x000: 0x2A => V0 = 0x2A
+        let mut e = Emulator::new(400, program).unwrap();
+        e.set_pc_overflow_policy(PcOverflowPolicy::Wrap);
+        e.memory[MEMORY_SIZE - 1] = 0x60;
+        e.memory[0] = 0x2A;
+        e.program_counter = MEMORY_SIZE - 1;
+        e.tick().unwrap();
+        assert_eq!(e.registers[0], 0x2A);
+        assert_eq!(e.program_counter, 1);
+
+        // FX0A at the top of memory waits there, PC has already wrapped to 0
+        let mut e = Emulator::new(400, vec![]).unwrap();
+        e.set_pc_overflow_policy(PcOverflowPolicy::Wrap);
+        e.memory[MEMORY_SIZE - 2..].copy_from_slice(&[0xF0, 0x0A]);
+        e.program_counter = MEMORY_SIZE - 2;
+        e.tick().unwrap();
+        assert_eq!(e.program_counter, MEMORY_SIZE - 2);
+
+        // 3000 skips the instruction at 0x000 and continues at 0x002
+        e.memory[MEMORY_SIZE - 2..].copy_from_slice(&[0x30, 0x00]);
+        e.tick().unwrap();
+        assert_eq!(e.program_counter, 2);
+
+        // without wrapping the skip runs past memory and the next fetch fails
+        e.set_pc_overflow_policy(PcOverflowPolicy::Error);
+        e.program_counter = MEMORY_SIZE - 2;
+        e.tick().unwrap();
+        assert_eq!(e.program_counter, MEMORY_SIZE + 2);
+        assert_eq!(e.tick(), Err(EmulatorError::MemoryAccess));
+    }
+
+    #[test]
+    fn test_memory_bounds() {
+        // jump to 0xFFF, where only one byte of an instruction fits
+        let mut e = Emulator::new(400, vec![0x1F, 0xFF]).unwrap();
+        e.tick().unwrap();
+        assert_eq!(e.tick(), Err(EmulatorError::MemoryAccess));
+
+        // I = 0xFFE, then draw 3 rows, BCD, store V0..V2 and load V0..V2, all past the end
+        for instruction in [[0xD0, 0x13], [0xF0, 0x33], [0xF2, 0x55], [0xF2, 0x65]] {
+            let program = vec![0xAF, 0xFE, instruction[0], instruction[1]];
+            let mut e = Emulator::new(400, program).unwrap();
+            e.tick().unwrap();
+            assert_eq!(
+                e.tick(),
+                Err(EmulatorError::MemoryAccess),
+                "{:X?}",
+                instruction
+            );
+        }
+    }
+
+    #[test]
+    fn test_load_registers_bounds() {
+        // I = 0xFF8, load V0..VF, 8 bytes past the end
+        let mut e = Emulator::new(400, vec![0xAF, 0xF8, 0xFF, 0x65]).unwrap();
+        e.memory[0xFF8..].fill(0xAB);
+        e.tick().unwrap();
+        assert_eq!(e.tick(), Err(EmulatorError::MemoryAccess));
+        assert_eq!(e.registers, [0; 16]);
+
+        // V0..V7 still fit
+        let mut e = Emulator::new(400, vec![0xAF, 0xF8, 0xF7, 0x65]).unwrap();
+        e.memory[0xFF8..].fill(0xAB);
+        e.tick().unwrap();
+        e.tick().unwrap();
+        assert_eq!(e.registers[..8], [0xAB; 8]);
+    }
+
+    #[test]
+    fn test_big_font() {
+        // V1 = 7, I = big "7", draw it at (0, 0)
+        let mut e = Emulator::new(400, vec![0x61, 0x07, 0xF1, 0x30, 0xD0, 0x0A]).unwrap();
+        e.tick().unwrap();
+        e.tick().unwrap();
+        assert_eq!(e.i, BIG_FONT_START_ADDRESS + 70);
+        assert_eq!(e.memory[e.i..][..10], BIG_FONT[7]);
+        // the big font fits between the small font and the program
+        assert!(e.i + 10 <= PROGRAM_START_ADDRESS);
+        assert_eq!(e.memory[FONT_START_ADDRESS..][..5], FONT[0]);
+        e.tick().unwrap();
+        assert_eq!(e.pixel_color(0, 0), 1);
+        assert_eq!(e.pixel_color(7, 9), 0);
+        assert_eq!(e.pixel_color(3, 9), 1);
+    }
+
+    #[test]
+    fn test_rpl_flags() {
+        // V0 = 1, V1 = 2, V2 = 3, store V0..V2, clear them, load V0..V1
+        let program = vec![
+            0x60, 0x01, 0x61, 0x02, 0x62, 0x03, 0xF2, 0x75, 0x60, 0x00, 0x61, 0x00, 0x62, 0x00,
+            0xF1, 0x85,
+        ];
+        let mut e = Emulator::new(400, program).unwrap();
+        for _ in 0..8 {
+            e.tick().unwrap();
+        }
+        assert_eq!(e.registers[..3], [1, 2, 0]);
+        assert_eq!(e.rpl_flags[..4], [1, 2, 3, 0]);
+
+        // the flags survive save and load but not a reset
+        let state = e.save_state();
+        e.reset();
+        assert_eq!(e.rpl_flags, [0; RPL_FLAGS]);
+        e.load_state(state).unwrap();
+        assert_eq!(e.rpl_flags[..3], [1, 2, 3]);
+
+        // there are only 8 flags
+        for opcode in [(0xF8, 0x75), (0xF8, 0x85), (0xFF, 0x75)] {
+            let mut e = Emulator::new(400, vec![opcode.0, opcode.1]).unwrap();
+            assert_eq!(e.tick(), Err(EmulatorError::MemoryAccess));
+        }
+        let mut e = Emulator::new(400, vec![0xF7, 0x75, 0xF7, 0x85]).unwrap();
+        e.tick().unwrap();
+        e.tick().unwrap();
+    }
+
+    #[test]
+    fn test_dump_state() {
+        // V0 = 0x2A, VF = 7, I = font "1", call 0x208, delay timer = V0
+        let program = vec![0x60, 0x2A, 0x6F, 0x07, 0xA0, 0x55, 0x22, 0x08, 0xF0, 0x15];
+        let mut e = Emulator::new(400, program).unwrap();
+        for _ in 0..5 {
+            e.tick().unwrap();
+        }
+
+        let dump = e.dump_state();
+        let lines: Vec<&str> = dump.lines().collect();
+        assert_eq!(lines[0], "PC 0x20A  I 0x055  DT  42  ST   0");
+        assert!(lines[1].starts_with("V0 2A  V1 00  "));
+        assert!(lines[2].ends_with("  VF 07"));
+        assert_eq!(lines[3], "Stack [0x208]");
+        assert_eq!(
+            lines[4],
+            "0x055: 20 60 20 20 70 F0 10 F0 80 F0 F0 10 F0 10 F0 90"
+        );
+        assert_eq!(lines.len(), 5);
+
+        // the memory view is clipped and split into rows of 16 bytes
+        let dump = e.dump_state_with_memory(0xFEE..0x1010);
+        let lines: Vec<&str> = dump.lines().skip(4).collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("0xFEE: "));
+        assert!(lines[1].starts_with("0xFFE: "));
+
+        assert_eq!(e.dump_memory(0x200, 2), Ok(&[0x60, 0x2A][..]));
+        assert_eq!(e.dump_memory(0xFFF, 2), Err(EmulatorError::MemoryAccess));
+    }
+
+    #[test]
+    fn test_new_with_start() {
+        // V0 = 5, jump to 0x600
+        let mut e = Emulator::new_with_start(400, vec![0x60, 0x05, 0x16, 0x00], 0x600).unwrap();
+        assert_eq!(e.program_counter(), 0x600);
+        assert_eq!(e.memory[0x600..][..4], [0x60, 0x05, 0x16, 0x00]);
+        assert_eq!(e.memory[PROGRAM_START_ADDRESS..][..2], [0, 0]);
+        e.tick().unwrap();
+        assert_eq!(e.registers[0], 5);
+        e.tick().unwrap();
+        assert_eq!(e.program_counter(), 0x600);
+
+        // a reset starts over at 0x600
+        e.tick().unwrap();
+        e.reset();
+        assert_eq!(e.program_counter(), 0x600);
+        assert_eq!(e.max_program_size(), MEMORY_SIZE - 0x600);
+
+        assert!(Emulator::new_with_start(400, vec![0; MEMORY_SIZE - 0x600], 0x600).is_ok());
+        for (len, start) in [(0, MEMORY_SIZE), (2, 0x50)] {
+            assert_eq!(
+                Emulator::new_with_start(400, vec![0; len], start).err(),
+                Some(EmulatorError::MemoryAccess)
+            );
+        }
+        assert_eq!(
+            Emulator::new_with_start(400, vec![0; MEMORY_SIZE - 0x600 + 1], 0x600).err(),
+            Some(EmulatorError::RomTooLarge {
+                size: MEMORY_SIZE - 0x600 + 1,
+                max: MEMORY_SIZE - 0x600
+            })
+        );
+    }
+
+    #[test]
+    fn test_from_bytes() {
+        let e = Emulator::from_bytes(400, &[0x60, 0x2A]).unwrap();
+        assert_eq!(e.memory[PROGRAM_START_ADDRESS..][..2], [0x60, 0x2A]);
+
+        let max = e.max_program_size();
+        assert_eq!(max, MEMORY_SIZE - PROGRAM_START_ADDRESS);
+        assert!(Emulator::from_bytes(400, &vec![0; max]).is_ok());
+        assert_eq!(
+            Emulator::from_bytes(400, &vec![0; max + 1]).err(),
+            Some(EmulatorError::RomTooLarge { size: max + 1, max })
+        );
+    }
+
+    #[test]
+    fn test_xo_chip_memory() {
+        // I = 0xABCD, store V0 there, skip the next long instruction, V1 = 1
+        let program = vec![
+            0xF0, 0x00, 0xAB, 0xCD, 0xF0, 0x55, 0x40, 0x00, 0xF0, 0x00, 0x12, 0x34, 0x61, 0x01,
+        ];
+        let mut e = Emulator::new(400, program.clone()).unwrap();
+        assert_eq!(e.tick(), Err(EmulatorError::Instruction()));
+
+        e.set_xo_chip(true).unwrap();
+        assert_eq!(e.memory.len(), XO_MEMORY_SIZE);
+        assert_eq!(
+            e.peek_next_instruction(),
+            Ok(Instruction::SetIndexRegisterLong(0xABCD))
+        );
+        e.registers[0] = 0x2A;
+        e.tick().unwrap();
+        assert_eq!(e.i, 0xABCD);
+        assert_eq!(e.program_counter, PROGRAM_START_ADDRESS + 4);
+        e.tick().unwrap();
+        assert_eq!(e.memory[0xABCD], 0x2A);
+        e.tick().unwrap();
+        assert_eq!(e.program_counter, PROGRAM_START_ADDRESS + 12);
+        e.tick().unwrap();
+        assert_eq!(e.registers[1], 1);
+
+        // a ROM larger than 4K only fits in XO-CHIP mode
+        let large = vec![0; 8192];
+        assert_eq!(e.load_program(&large), Ok(()));
+        let too_large = || EmulatorError::RomTooLarge {
+            size: 8192,
+            max: MEMORY_SIZE - PROGRAM_START_ADDRESS,
+        };
+        assert_eq!(e.set_xo_chip(false), Err(too_large()));
+        e.load_program(&program).unwrap();
+        e.set_xo_chip(false).unwrap();
+        assert_eq!(e.memory.len(), MEMORY_SIZE);
+        assert_eq!(e.load_program(&large), Err(too_large()));
+    }
+
+    #[test]
+    fn test_bcd_all_values() {
+        // I = 0x300, store BCD of V0
+        let program = vec![0xA3, 0x00, 0xF0, 0x33];
+        let mut e = Emulator::new(400, program).unwrap();
+
+        for value in 0..=u8::MAX {
+            e.program_counter = PROGRAM_START_ADDRESS;
+            e.registers[0] = value;
+            e.tick().unwrap();
+            e.tick().unwrap();
+            assert_eq!(
+                e.memory[0x300..0x303],
+                [value / 100, value / 10 % 10, value % 10],
+                "BCD of {}",
+                value
+            );
+        }
+    }
+
+    #[test]
+    fn test_watchdog() {
+        let tests = [
+            // V0 += 1, jump back
+            (vec![0x70, 0x01, 0x12, 0x00], true),
+            // jump to itself
+            (vec![0x12, 0x00], false),
+            // V0 = DT, jump back
+            (vec![0xF0, 0x07, 0x12, 0x00], false),
+        ];
+
+        for (program, trips) in tests {
+            let mut e = Emulator::new(400, program).unwrap();
+            e.set_watchdog(Some(100));
+            let result = (0..1000).try_for_each(|_| e.tick().map(|_| ()));
+            assert_eq!(result.is_err(), trips);
+            if trips {
+                assert_eq!(result, Err(EmulatorError::Watchdog));
+            }
+        }
+    }
+
+    #[test]
+    fn test_step() {
+        // V0 = 10, DT = V0, I = font "0", draw it
+        let program = vec![0x60, 0x0A, 0xF0, 0x15, 0xA0, 0x50, 0xD0, 0x05];
+        let mut e = Emulator::new(400, program).unwrap();
+        // loading the program asks for a redraw
+        e.step().unwrap();
+        assert!(e.should_redraw());
+        e.clear_redraw();
+
+        for _ in 0..2 {
+            e.step().unwrap();
+            assert!(!e.should_redraw());
+        }
+        assert_eq!(e.delay_timer(), 10);
+        e.step().unwrap();
+        assert!(e.should_redraw());
+        assert!(e.get_pixel(10, 10));
+        assert_eq!(e.delay_timer(), 10);
+    }
+
+    #[test]
+    fn test_timers_frozen() {
+        // V0 = 10, DT = V0, then loop forever
+        let program = vec![0x60, 0x0A, 0xF0, 0x15, 0x12, 0x04];
+        let mut e = Emulator::new(FPS as u16 * 3, program).unwrap();
+        e.run_frame().unwrap();
+        assert_eq!(e.delay_timer, 9);
+
+        e.set_timers_frozen(true);
+        assert!(e.timers_frozen());
+        e.run_frame().unwrap();
+        e.run_frame().unwrap();
+        assert_eq!(e.delay_timer, 9);
+
+        e.set_timers_frozen(false);
+        e.run_frame().unwrap();
+        assert_eq!(e.delay_timer, 8);
+    }
+
+    #[test]
+    fn test_frame_count() {
+        let program = vec![0x12, 0x00];
+        let mut e = Emulator::new(400, program).unwrap();
+        assert_eq!(e.frame_count(), 0);
+        for _ in 0..3 {
+            e.run_frame().unwrap();
+        }
+        e.tick().unwrap();
+        assert_eq!(e.frame_count(), 3);
+    }
+
+    #[test]
+    fn test_cycles() {
+        // V0 = 0, then V0 += 1 until it is 10
+        let program = vec![0x60, 0x00, 0x70, 0x01, 0x30, 0x0A, 0x12, 0x02, 0x12, 0x08];
+        let mut e = Emulator::new(400, program).unwrap();
+        e.set_breakpoint(0x208);
+        assert_eq!(e.run_until_breakpoint(), Ok(0x208));
+        // 1 + 10 * 2 for the increment and skip, 9 jumps back
+        assert_eq!(e.cycles(), 30);
+
+        e.run_frame().unwrap();
+        assert_eq!(e.cycles(), 30 + e.ticks_per_frame as u64);
+        e.reset();
+        assert_eq!(e.cycles(), 0);
+    }
+
+    #[test]
+    fn test_rewind() {
+        // V0 += 1 and repeat, once per frame
+        let program = vec![0x70, 0x01, 0x12, 0x00];
+        let mut e = Emulator::new(FPS as u16 * 2, program).unwrap();
+        assert_eq!(e.rewind(1), Ok(false));
+        e.set_rewind_depth(10);
+        e.set_rewind_interval(2);
+        let mut registers = vec![];
+        for _ in 0..20 {
+            registers.push(e.registers);
+            e.run_frame().unwrap();
+        }
+        assert_eq!(e.frame_count(), 20);
+        assert_eq!(e.rewind_buffer.len(), 5);
+
+        e.clear_redraw();
+        assert_eq!(e.rewind(4), Ok(true));
+        assert_eq!(e.frame_count(), 16);
+        assert_eq!(e.registers, registers[16]);
+        // shown right away, without running a frame
+        assert!(e.should_redraw());
+        assert_eq!(e.dirty_rows().count(), HEIGHT);
+        // only every other frame is saved
+        assert_eq!(e.rewind(1), Ok(true));
+        assert_eq!(e.frame_count(), 14);
+        assert_eq!(e.registers, registers[14]);
+        // no further back than the depth
+        assert_eq!(e.rewind(100), Ok(true));
+        assert_eq!(e.frame_count(), 10);
+        assert_eq!(e.registers, registers[10]);
+        assert_eq!(e.rewind(1), Ok(false));
+
+        e.run_frame().unwrap();
+        assert_eq!(e.registers, registers[11]);
+        e.reset();
+        assert_eq!(e.rewind(1), Ok(false));
+
+        // I = 0xFFF, V0 = 0xFF, I += V0 once per frame, I ends up past memory
+        let program = vec![0xAF, 0xFF, 0x60, 0xFF, 0xF0, 0x1E, 0x12, 0x04];
+        let mut e = Emulator::new(FPS as u16 * 2, program).unwrap();
+        e.set_rewind_depth(10);
+        e.run_frames(5).unwrap();
+        assert!(e.i >= 0x1000);
+        assert_eq!(e.rewind(2), Ok(true));
+        assert_eq!(e.frame_count(), 3);
+        assert_eq!(e.i, 0xFFF + 2 * 0xFF);
+    }
+
+    #[test]
+    fn test_framebuffer_hash() {
+        // I = font "0", draw it at (V0, V0), V0 += 8, repeat
+        let program = vec![0xA0, 0x50, 0xD0, 0x05, 0x70, 0x08, 0x12, 0x00];
+        let mut e = Emulator::new(FPS as u16 * 4, program).unwrap();
+        let empty = e.framebuffer_hash();
+        e.run_frames(3).unwrap();
+        // zeros at (0, 0), (8, 8) and (16, 16), must never change
+        assert_eq!(e.framebuffer_hash(), 1997327930254572309);
+        assert_ne!(e.framebuffer_hash(), empty);
+        e.reset();
+        assert_eq!(e.framebuffer_hash(), empty);
+    }
+
+    #[test]
+    fn test_draw_16x16() {
+        // high resolution, I = 0x20E, draw 16x16 at (0, 0), then again at (8, 10)
+        let mut program = vec![
+            0x00, 0xFF, 0xA2, 0x0E, 0xD0, 0x10, 0x60, 0x08, 0x61, 0x0A, 0xD0, 0x10, 0x12, 0x0C,
+        ];
+        program.extend([0xFF; 32]);
+        let mut e = Emulator::new(400, program.clone()).unwrap();
+        for _ in 0..3 {
+            e.tick().unwrap();
+        }
+        assert_eq!(e.registers[0xF], 0);
+        assert!(e.get_pixel(0, 0) && e.get_pixel(15, 15));
+        assert!(!e.get_pixel(16, 0) && !e.get_pixel(0, 16));
+
+        // rows 10 to 15 overlap
+        for _ in 0..3 {
+            e.tick().unwrap();
+        }
+        assert_eq!(e.registers[0xF], 6);
+        assert!(e.get_pixel(7, 10) && !e.get_pixel(8, 10) && !e.get_pixel(15, 15));
+        assert!(e.get_pixel(16, 10) && e.get_pixel(23, 25) && !e.get_pixel(24, 25));
+
+        // a boolean in XO-CHIP low resolution
+        program[..2].copy_from_slice(&[0x00, 0xE0]);
+        let mut e = Emulator::new(400, program).unwrap();
+        e.set_xo_chip(true).unwrap();
+        for _ in 0..6 {
+            e.tick().unwrap();
+        }
+        assert_eq!(e.registers[0xF], 1);
+        assert!(e.get_pixel(23, 25) && !e.get_pixel(8, 10));
+
+        // 16 pixel rows are clipped at the right edge
+        let mut e = Emulator::new(400, vec![]).unwrap();
+        assert_eq!(e.draw_to_fb(0, WIDTH - 4, 0, &[0xFF, 0x01], 16), 0);
+        assert_eq!(e.frame_buf[0], 0b1111);
+    }
+
+    #[test]
+    fn test_draw_zero_rows() {
+        // I = 0xFF0, D000 draws nothing in CHIP-8 low resolution
+        let mut e = Emulator::new(400, vec![0xAF, 0xF0, 0xD0, 0x00]).unwrap();
+        e.registers[0xF] = 1;
+        e.tick().unwrap();
+        assert_eq!(e.tick(), Ok(true));
+        assert_eq!(e.registers[0xF], 0);
+        assert_eq!(e.lit_pixels().count(), 0);
+    }
+
+    #[test]
+    fn test_draw_to_fb() {
+        let mut e = Emulator::new(400, vec![]).unwrap();
+        assert_eq!(
+            e.draw_to_fb(0, 60, 30, &[0b1100_0011, 0b1000_0001, 0xFF], 8),
+            0
+        );
+        // right columns and bottom row are clipped
+        assert_eq!(e.frame_buf[30], 0b1100);
+        assert_eq!(e.frame_buf[31], 0b1000);
+        assert!(e.get_pixel(60, 30) && e.get_pixel(61, 30) && !e.get_pixel(62, 30));
+
+        // start position wraps around
+        assert_eq!(e.draw_to_fb(0, WIDTH + 60, HEIGHT + 31, &[0x80], 8), 1);
+        assert!(!e.get_pixel(60, 31));
+        assert!(e.get_framebuffer()[30][61]);
+
+        e.clear_screen();
+        assert_eq!(e.frame_buf, [0; HIRES_HEIGHT]);
+    }
+
+    #[test]
+    fn test_planes() {
+        let program = vec![
+            0xF2, 0x01, // select plane 2
+            0xA2, 0x0E, // I = sprite data
+            0xD0, 0x01, // draw one row at (0, 0)
+            0xF3, 0x01, // select both planes
+            0xD0, 0x01, // draw one row per plane at (0, 0)
+            0xF2, 0x01, // select plane 2
+            0x00, 0xE0, // clear it
+            0xF0, 0xC0, // sprite rows
+        ];
+        let mut e = Emulator::new(400, program).unwrap();
+        assert_eq!(e.tick(), Err(EmulatorError::Instruction()));
+
+        e.set_xo_chip(true).unwrap();
+        for _ in 0..3 {
+            e.tick().unwrap();
+        }
+        assert_eq!(e.pixel_color(0, 0), 2);
+        assert!(!e.get_pixel(0, 0));
+
+        e.tick().unwrap();
+        e.tick().unwrap();
+        // only the second plane had pixels to erase
+        assert_eq!(e.registers[0xF], 1);
+        assert_eq!(e.get_framebuffer_colors()[0][..5], [1, 1, 3, 3, 0]);
+
+        e.tick().unwrap();
+        e.tick().unwrap();
+        assert_eq!(e.get_framebuffer_colors()[0][..5], [1, 1, 1, 1, 0]);
+    }
+
+    #[test]
+    fn test_audio_backend() {
+        use std::cell::RefCell;
+        use std::rc::Rc;
+
+        /// Records which `AudioBackend` methods the emulator calls.
+        struct MockAudio(Rc<RefCell<Vec<&'static str>>>);
+
+        impl AudioBackend for MockAudio {
+            fn start(&mut self) {
+                self.0.borrow_mut().push("start");
+            }
+
+            fn stop(&mut self) {
+                self.0.borrow_mut().push("stop");
+            }
+        }
+
+        // ST = 5, then loop forever
+        let program = vec![0x60, 0x05, 0xF0, 0x18, 0x12, 0x04];
+        let calls: Rc<RefCell<Vec<&str>>> = Rc::default();
+        let audio = Box::new(MockAudio(Rc::clone(&calls)));
+        let mut e = Emulator::new_with_audio(FPS as u16 * 2, program, audio).unwrap();
+        calls.borrow_mut().clear();
+
+        e.run_frame().unwrap();
+        assert_eq!(*calls.borrow(), ["start"]);
+        for _ in 0..4 {
+            e.run_frame().unwrap();
+        }
+        assert_eq!(e.sound_timer(), 0);
+        assert_eq!(*calls.borrow(), ["start"]);
+        e.run_frame().unwrap();
+        assert_eq!(*calls.borrow(), ["start", "stop"]);
+
+        e.set_sound_paused(true);
+        assert_eq!(calls.borrow().last(), Some(&"stop"));
+    }
+
+    #[test]
+    fn test_audio_pattern() {
+        let program = vec![
+            0xA2, 0x0A, // I = pattern
+            0xF0, 0x02, // load the audio pattern
+            0x60, 0x70, // V0 = 112
+            0xF0, 0x3A, // pitch = V0
+            0xF0, 0x18, // sound timer = V0
+            0xAA, 0x55, 0xAA, 0x55, 0xAA, 0x55, 0xAA, 0x55, // pattern
+            0xAA, 0x55, 0xAA, 0x55, 0xAA, 0x55, 0xAA, 0x55,
+        ];
+        let mut e = Emulator::new(400, program).unwrap();
+        e.set_beeper(Beeper::silent());
+        e.tick().unwrap();
+        assert_eq!(e.tick(), Err(EmulatorError::Instruction()));
+
+        e.set_xo_chip(true).unwrap();
+        assert_eq!((e.audio_pattern, e.pitch), (None, DEFAULT_PITCH));
+        for _ in 0..5 {
+            e.tick().unwrap();
+        }
+        assert_eq!(
+            e.audio_pattern,
+            Some([0xAA, 0x55].repeat(8).try_into().unwrap())
+        );
+        assert_eq!(e.pitch, 112);
+        assert_eq!(e.sound_timer, 112);
+
+        let state = e.save_state();
+        e.reset();
+        assert_eq!((e.audio_pattern, e.pitch), (None, DEFAULT_PITCH));
+        e.load_state(state).unwrap();
+        assert_eq!(e.pitch, 112);
+        assert!(e.audio_pattern.is_some());
+    }
+
+    #[test]
+    fn test_scroll() {
+        // scroll down 2, right, left, left
+        let program = vec![0x00, 0xC2, 0x00, 0xFB, 0x00, 0xFC, 0x00, 0xFC];
+        let mut e = Emulator::new(400, program).unwrap();
+        e.draw_to_fb(0, 0, 0, &[0x80], 8);
+        e.draw_to_fb(0, WIDTH - 1, HEIGHT - 1, &[0x80], 8);
+        e.clear_redraw();
+
+        assert!(e.tick().unwrap());
+        assert!(e.get_pixel(0, 2) && !e.get_pixel(0, 0));
+        // pushed off the bottom
+        assert!(!e.get_pixel(WIDTH - 1, HEIGHT - 1));
+        assert_eq!(e.dirty_rows().collect::<Vec<_>>(), [0, 2, HEIGHT - 1]);
+
+        e.tick().unwrap();
+        assert!(e.get_pixel(4, 2) && !e.get_pixel(0, 2));
+        e.tick().unwrap();
+        assert!(e.get_pixel(0, 2) && !e.get_pixel(4, 2));
+        e.tick().unwrap();
+        assert_eq!(e.get_framebuffer(), vec![vec![false; WIDTH]; HEIGHT]);
+    }
+
+    #[test]
+    fn test_hires() {
+        // I = font "0", high resolution, draw at (120, 60), low resolution, loop forever
+        let program = vec![
+            0xA0, 0x50, 0x00, 0xFF, 0x60, 0x78, 0x61, 0x3C, 0xD0, 0x15, 0x00, 0xFE, 0x12, 0x0C,
+        ];
+        let mut e = Emulator::new(400, program).unwrap();
+        assert_eq!(e.resolution(), (WIDTH, HEIGHT));
+        for _ in 0..5 {
+            e.tick().unwrap();
+        }
+        assert!(e.hires());
+        assert_eq!(e.resolution(), (HIRES_WIDTH, HIRES_HEIGHT));
+        // drawn inside the larger screen, the bottom rows are clipped
+        assert!(e.get_pixel(120, 60) && e.get_pixel(123, 63) && !e.get_pixel(124, 60));
+        assert_eq!(e.get_framebuffer().len(), HIRES_HEIGHT);
+        assert_eq!(e.get_framebuffer()[0].len(), HIRES_WIDTH);
+        assert_eq!(e.dirty_rows().count(), HIRES_HEIGHT);
+
+        e.clear_redraw();
+        assert!(e.tick().unwrap());
+        assert!(!e.hires());
+        assert_eq!(e.get_framebuffer(), vec![vec![false; WIDTH]; HEIGHT]);
+        assert_eq!(e.framebuffer_packed().len(), HEIGHT);
+        assert_eq!(e.dirty_rows().count(), HEIGHT);
+    }
+
+    #[test]
+    fn test_draw_wraps_at_x62() {
+        // V0 = 62, V1 = 31, I = sprite, draw 2 rows of 0xFF at (62, 31)
+        let program = vec![0x60, 62, 0x61, 31, 0xA2, 0x08, 0xD0, 0x12, 0xFF, 0xFF];
+        let run = |draw_wraps| {
+            let quirks = Quirks {
+                draw_wraps,
+                ..Quirks::default()
+            };
+            let mut e = Emulator::new_with_quirks(400, program.clone(), quirks).unwrap();
+            for _ in 0..4 {
+                e.tick().unwrap();
+            }
+            e.lit_pixels().collect::<Vec<_>>()
+        };
+
+        assert_eq!(run(false), [(62, 31), (63, 31)]);
+        let wrapped_row = |y| (0..6).chain(62..64).map(move |x| (x, y));
+        assert_eq!(
+            run(true),
+            wrapped_row(0).chain(wrapped_row(31)).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_draw_at_edges() {
+        fn lit_columns(e: &Emulator, y: usize) -> Vec<usize> {
+            (0..WIDTH).filter(|&x| e.get_pixel(x, y)).collect()
+        }
+
+        // (x, lit columns when clipped, lit columns when wrapped)
+        let tests = [
+            (
+                0,
+                vec![0, 1, 2, 3, 4, 5, 6, 7],
+                vec![0, 1, 2, 3, 4, 5, 6, 7],
+            ),
+            (63, vec![63], vec![0, 1, 2, 3, 4, 5, 6, 63]),
+            (
+                64,
+                vec![0, 1, 2, 3, 4, 5, 6, 7],
+                vec![0, 1, 2, 3, 4, 5, 6, 7],
+            ),
+        ];
+        for (x, clipped, wrapped) in tests {
+            for (draw_wraps, expected) in [(false, clipped), (true, wrapped)] {
+                let quirks = Quirks {
+                    draw_wraps,
+                    ..Quirks::default()
+                };
+                let mut e = Emulator::new_with_quirks(400, vec![], quirks).unwrap();
+                e.draw_to_fb(0, x, 0, &[0xFF], 8);
+                assert_eq!(
+                    lit_columns(&e, 0),
+                    expected,
+                    "x = {}, wraps = {}",
+                    x,
+                    draw_wraps
+                );
+            }
+        }
+
+        // rows at the bottom edge
+        for (draw_wraps, top_row_lit) in [(false, false), (true, true)] {
+            let quirks = Quirks {
+                draw_wraps,
+                ..Quirks::default()
+            };
+            let mut e = Emulator::new_with_quirks(400, vec![], quirks).unwrap();
+            e.draw_to_fb(0, 0, HEIGHT - 1, &[0x80, 0x80], 8);
+            assert!(e.get_pixel(0, HEIGHT - 1));
+            assert_eq!(e.get_pixel(0, 0), top_row_lit);
+        }
+    }
+
+    /// Draws like `draw_to_fb` with one bool per pixel, as the emulator used to.
+    fn draw_unpacked(fb: &mut [[bool; WIDTH]; HEIGHT], x: usize, y: usize, sprite: &[u8]) -> bool {
+        let (x, y) = (x % WIDTH, y % HEIGHT);
+        let mut any_px_erased = false;
+        for (row, &sprite_row) in sprite.iter().enumerate().take(HEIGHT - y) {
+            for col in 0..8.min(WIDTH - x) {
+                if sprite_row & (0x80 >> col) != 0 {
+                    let px = &mut fb[y + row][x + col];
+                    any_px_erased |= *px;
+                    *px = !*px;
+                }
+            }
+        }
+        any_px_erased
+    }
+
+    fn random_draws(n: usize) -> Vec<(usize, usize, Vec<u8>)> {
+        let mut rng = thread_rng();
+        (0..n)
+            .map(|_| {
+                let rows = rng.gen_range(1..16);
+                (rng.gen(), rng.gen(), (0..rows).map(|_| rng.gen()).collect())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_draw_matches_unpacked() {
+        let mut e = Emulator::new(400, vec![]).unwrap();
+        let mut fb = [[false; WIDTH]; HEIGHT];
+        for (x, y, sprite) in random_draws(1000) {
+            assert_eq!(
+                e.draw_to_fb(0, x, y, &sprite, 8) > 0,
+                draw_unpacked(&mut fb, x, y, &sprite)
+            );
+        }
+        assert_eq!(e.get_framebuffer(), fb.map(Vec::from).to_vec());
+        let lit: Vec<_> = (0..HEIGHT)
+            .flat_map(|y| (0..WIDTH).map(move |x| (x, y)))
+            .filter(|&(x, y)| fb[y][x])
+            .collect();
+        assert_eq!(e.lit_pixels().collect::<Vec<_>>(), lit);
+    }
+
+    /// Run with `cargo test --release -- --ignored --nocapture bench_draw`.
+    #[test]
+    #[ignore]
+    fn bench_draw() {
+        let draws = random_draws(1_000_000);
+        let mut e = Emulator::new(400, vec![]).unwrap();
+        let start = std::time::Instant::now();
+        for (x, y, sprite) in &draws {
+            e.draw_to_fb(0, *x, *y, sprite, 8);
+        }
+        let packed = start.elapsed();
+
+        let mut fb = [[false; WIDTH]; HEIGHT];
+        let start = std::time::Instant::now();
+        for (x, y, sprite) in &draws {
+            draw_unpacked(&mut fb, *x, *y, sprite);
+        }
+        let unpacked = start.elapsed();
+        println!(
+            "{} draws: packed {:?}, unpacked {:?}",
+            draws.len(),
+            packed,
+            unpacked
+        );
+    }
+
+    #[test]
+    fn test_dirty_rows() {
+        let mut e = Emulator::new(400, vec![]).unwrap();
+        e.draw_to_fb(0, 0, 4, &[0x80, 0x00, 0x80], 8);
+        e.draw_to_fb(0, 0, 10, &[0x80], 8);
+        assert_eq!(e.dirty_rows().collect::<Vec<_>>(), [4, 6, 10]);
+
+        e.clear_redraw();
+        assert_eq!(e.dirty_rows().count(), 0);
+
+        e.clear_screen();
+        assert_eq!(e.dirty_rows().collect::<Vec<_>>(), [4, 6, 10]);
+    }
+
+    #[test]
+    fn test_redraw_until_cleared() {
+        // I = font "0", draw it, then loop forever, one instruction per frame
+        let program = vec![0xA0, 0x50, 0xD0, 0x05, 0x12, 0x04];
+        let mut e = Emulator::new(FPS as u16, program).unwrap();
+        e.run_frame().unwrap();
+        e.clear_redraw();
+
+        // a frame that draws, then one that doesn't
+        e.run_frame().unwrap();
+        e.run_frame().unwrap();
+        assert!(e.should_redraw());
+        assert_eq!(e.dirty_rows().collect::<Vec<_>>(), [0, 1, 2, 3, 4]);
+
+        e.clear_redraw();
+        e.run_frame().unwrap();
+        assert!(!e.should_redraw());
+    }
+
+    #[test]
+    fn test_trace_hook() {
+        use std::cell::RefCell;
+        use std::rc::Rc;
+
+        let program = vec![0x60, 0x05, 0xA3, 0x15];
+        let mut e = Emulator::new(400, program).unwrap();
+        let traced = Rc::new(RefCell::new(vec![]));
+        let traced_in_hook = Rc::clone(&traced);
+        e.set_trace_hook(Some(Box::new(move |t: &Trace| {
+            traced_in_hook.borrow_mut().push((
+                t.program_counter,
+                t.opcode,
+                t.instruction.to_string(),
+                t.registers[0],
+            ));
+        })));
+        e.tick().unwrap();
+        e.tick().unwrap();
+
+        assert_eq!(
+            *traced.borrow(),
+            [
+                (0x200, 0x6005, String::from("LD V0, 0x05"), 0),
+                (0x202, 0xA315, String::from("LD I, 0x315"), 5),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_sprite_diagnostic() {
+        use std::cell::RefCell;
+        use std::rc::Rc;
+
+        // I = 0x1FE, draw 4 rows, I = font "F", draw 5 rows
+        let program = vec![0xA1, 0xFE, 0xD0, 0x04, 0xA0, 0x9B, 0xD0, 0x05];
+        let run = |diagnostics| {
+            let mut e = Emulator::new(400, program.clone()).unwrap();
+            e.set_diagnostics(diagnostics);
+            let diagnostics = Rc::new(RefCell::new(vec![]));
+            let diagnostics_in_hook = Rc::clone(&diagnostics);
+            e.set_trace_hook(Some(Box::new(move |t: &Trace| {
+                diagnostics_in_hook.borrow_mut().extend(t.diagnostic);
+            })));
+            for _ in 0..4 {
+                e.tick().unwrap();
+            }
+            let diagnostics = diagnostics.borrow().clone();
+            diagnostics
+        };
+
+        assert_eq!(
+            run(true),
+            [Diagnostic::SpriteCrossesRegion {
+                start: 0x1FE,
+                end: 0x202
+            }]
+        );
+        assert!(run(false).is_empty());
+    }
+
+    #[test]
+    fn test_peek_next_instruction() {
+        let program = vec![0x60, 0x05, 0xFF, 0xFF];
+        let mut e = Emulator::new(400, program).unwrap();
+        assert_eq!(
+            e.peek_next_instruction(),
+            Ok(Instruction::SetRegisterToValue(0, 5))
+        );
+        assert_eq!(e.program_counter, PROGRAM_START_ADDRESS);
+        assert_eq!(e.registers[0], 0);
+
+        e.tick().unwrap();
+        assert_eq!(e.peek_next_instruction(), Err(EmulatorError::Instruction()));
+        assert_eq!(e.program_counter, PROGRAM_START_ADDRESS + 2);
+
+        e.program_counter = MEMORY_SIZE - 1;
+        assert_eq!(e.peek_next_instruction(), Err(EmulatorError::MemoryAccess));
+    }
+
+    #[test]
+    fn test_watchpoint() {
+        // I = 0x300, V0 = 123, V1 = 7, store V0..V1, BCD of V0, then loop forever
+        let program = vec![
+            0xA3, 0x00, 0x60, 0x7B, 0x61, 0x07, 0xF1, 0x55, 0xF0, 0x33, 0x12, 0x0A,
+        ];
+        let mut e = Emulator::new(400, program).unwrap();
+        e.add_watchpoint(0x301);
+        assert_eq!(
+            e.run_frame_debug(),
+            Ok(RunOutcome::Watchpoint(WatchpointHit {
+                program_counter: 0x206,
+                address: 0x301,
+                old: 0,
+                new: 7,
+            }))
+        );
+        assert_eq!(e.program_counter, 0x208);
+
+        assert_eq!(
+            e.run_frame_debug(),
+            Ok(RunOutcome::Watchpoint(WatchpointHit {
+                program_counter: 0x208,
+                address: 0x301,
+                old: 7,
+                new: 2,
+            }))
+        );
+
+        e.remove_watchpoint(0x301);
+        assert_eq!(e.run_frame_debug(), Ok(RunOutcome::FrameComplete));
+    }
+
+    #[test]
+    fn test_reset() {
+        // V0 += 1, store V0 at I = 0x210 (overwriting this program), jump back
+        let program = vec![0x70, 0x01, 0xA2, 0x10, 0xF0, 0x55, 0x12, 0x00];
+        let mut e = Emulator::new(400, program.clone()).unwrap();
+        e.set_breakpoint(0x200);
+        e.run_until_breakpoint().unwrap();
+        e.draw_to_fb(0, 0, 0, &[0x80], 8);
+        e.run_frame().unwrap();
+        assert_eq!(e.memory[0x210], e.registers[0]);
+
+        e.reset();
+        assert_eq!(e.registers, [0; 16]);
+        assert_eq!((e.program_counter, e.i), (PROGRAM_START_ADDRESS, 0));
+        assert_eq!(e.frame_count(), 0);
+        assert_eq!(e.lit_pixels().count(), 0);
+        assert_eq!(e.memory[PROGRAM_START_ADDRESS..][..program.len()], program);
+        assert_eq!(e.memory[0x210], 0);
+        // settings survive
+        assert_eq!(e.run_until_breakpoint(), Ok(0x200));
+    }
+
+    #[test]
+    fn test_breakpoints() {
+        // V0 += 1, V1 += 2, jump back
+        let program = vec![0x70, 0x01, 0x71, 0x02, 0x12, 0x00];
+        let mut e = Emulator::new(400, program).unwrap();
+        e.set_breakpoint(0x202);
+        assert_eq!(e.run_until_breakpoint(), Ok(0x202));
+        assert_eq!(e.program_counter, 0x202);
+        assert_eq!(e.registers[..2], [1, 0]);
+
+        // continues from the breakpoint
+        assert_eq!(e.run_until_breakpoint(), Ok(0x202));
+        assert_eq!(e.registers[..2], [2, 2]);
+
+        assert_eq!(e.run_frame_debug(), Ok(RunOutcome::Breakpoint(0x202)));
+        assert_eq!(e.registers[..2], [3, 4]);
+
+        e.clear_breakpoint(0x202);
+        assert_eq!(e.run_frame_debug(), Ok(RunOutcome::FrameComplete));
+    }
+
+    #[test]
+    fn test_load_program() {
+        // I = font "0", draw it, V0 = 1, then loop forever
+        let program = vec![0xA0, 0x50, 0xD0, 0x05, 0x60, 0x01, 0x12, 0x06];
+        let mut e = Emulator::new(400, program).unwrap();
+        e.set_quirks(Target::Chip48.quirks());
+        e.run_frame().unwrap();
+        e.clear_redraw();
+        assert!(e.get_pixel(0, 0));
+
+        e.load_program(&[0x12, 0x00]).unwrap();
+        assert_eq!(e.program_counter, PROGRAM_START_ADDRESS);
+        assert_eq!(e.registers, [0; 16]);
+        assert_eq!(e.i, 0);
+        assert_eq!(e.frame_count(), 0);
+        assert!(!e.get_pixel(0, 0));
+        assert_eq!(e.memory[PROGRAM_START_ADDRESS + 6], 0);
+        assert_eq!(e.quirks(), Target::Chip48.quirks());
+        // the cleared screen is drawn even though the new program never draws
+        e.run_frame().unwrap();
+        assert!(e.should_redraw());
+
+        let too_large = vec![0; MEMORY_SIZE];
+        assert_eq!(
+            e.load_program(&too_large),
+            Err(EmulatorError::RomTooLarge {
+                size: MEMORY_SIZE,
+                max: MEMORY_SIZE - PROGRAM_START_ADDRESS
+            })
+        );
+        assert_eq!(e.memory[PROGRAM_START_ADDRESS], 0x12);
+    }
+
+    #[test]
+    fn test_set_clock_speed() {
+        let mut e = Emulator::new(600, vec![0x12, 0x00]).unwrap();
+        assert_eq!((e.clock_speed(), e.ticks_per_frame), (600, 10));
+        e.run_frame().unwrap();
+        e.set_clock_speed(1200);
+        assert_eq!(e.ticks_per_frame, 20);
+
+        // never stalls
+        e.set_clock_speed(0);
+        assert_eq!((e.clock_speed(), e.ticks_per_frame), (FPS as u16, 1));
+        e.delay_timer = 2;
+        e.run_frame().unwrap();
+        e.run_frame().unwrap();
+        assert_eq!(e.delay_timer, 0);
+
+        // every clock speed is reached, even past 255 instructions per frame
+        e.set_clock_speed(u16::MAX);
+        assert_eq!((e.clock_speed(), e.ticks_per_frame), (u16::MAX, 1092));
+        let cycles = e.cycles();
+        e.run_frame().unwrap();
+        assert_eq!(e.cycles() - cycles, 1092);
+    }
+
+    #[test]
+    fn test_draw_cost() {
+        // draw a 5 row sprite, repeat
+        let program = vec![0xD0, 0x05, 0x12, 0x00];
+        let mut e = Emulator::new(FPS as u16 * 6, program).unwrap();
+        e.run_frame().unwrap();
+        assert_eq!(e.cycles(), 6);
+
+        // 3 slots drawing after each DXYN, the second one takes them from the next frame
+        e.reset();
+        e.set_draw_rows_per_slot(2);
+        e.run_frame().unwrap();
+        assert_eq!(e.cycles(), 3);
+        e.run_frame().unwrap();
+        assert_eq!(e.cycles(), 5);
+
+        let e = Emulator::new_with_target(vec![], Target::Cosmac).unwrap();
+        assert_eq!(e.draw_rows_per_slot(), 2);
+    }
+
+    #[test]
+    fn test_timers_once_per_frame() {
+        for clock_speed in [60, 400, 500, 700, 1000, 5000] {
+            let mut e = Emulator::new(clock_speed, vec![0x12, 0x00]).unwrap();
+            e.delay_timer = 50;
+            e.sound_timer = 50;
+            for frame in 1..=10 {
+                e.run_frame().unwrap();
+                assert_eq!(e.delay_timer, 50 - frame, "clock speed {}", clock_speed);
+                assert_eq!(e.sound_timer, 50 - frame, "clock speed {}", clock_speed);
+            }
+        }
+    }
+
+    #[test]
+    fn test_run_frame_executes_ticks_per_frame() {
+        use std::cell::Cell;
+        use std::rc::Rc;
+
+        for (clock_speed, expected) in [(60, 1), (400, 7), (500, 8), (700, 12), (1000, 17)] {
+            let mut e = Emulator::new(clock_speed, vec![0x12, 0x00]).unwrap();
+            let executed = Rc::new(Cell::new(0));
+            let executed_in_hook = Rc::clone(&executed);
+            e.set_trace_hook(Some(Box::new(move |_: &Trace| {
+                executed_in_hook.set(executed_in_hook.get() + 1);
+            })));
+            e.run_frame().unwrap();
+            assert_eq!(executed.get(), expected, "clock speed {}", clock_speed);
+            assert_eq!(executed.get(), e.ticks_per_frame);
+        }
+    }
+
+    #[test]
+    fn test_run_frames() {
+        // I = font "0", draw it, then loop forever
+        let program = vec![0xA0, 0x50, 0xD0, 0x05, 0x12, 0x04];
+        let mut e = Emulator::new(400, program).unwrap();
+        let fb = e.run_frames(3).unwrap();
+        assert_eq!(e.frame_count(), 3);
+        assert!(fb[0][0] && fb[0][3] && !fb[1][1]);
+
+        // an unknown instruction in the second frame
+        let program = vec![0x00, 0x00];
+        let mut e = Emulator::new(60, program).unwrap();
+        e.set_machine_call_policy(MachineCallPolicy::Ignore);
+        e.memory[PROGRAM_START_ADDRESS + 2] = 0xFF;
+        e.memory[PROGRAM_START_ADDRESS + 3] = 0xFF;
+        assert_eq!(e.run_frames(5), Err(EmulatorError::Instruction()));
+        assert_eq!(e.frame_count(), 2);
+    }
+
+    #[test]
+    fn test_last_collision() {
+        // I = font "0", draw it at (0, 0) twice, then loop forever
+        let program = vec![0xA0, 0x50, 0xD0, 0x05, 0xD0, 0x05, 0x12, 0x06];
+        // one instruction per frame
+        let mut e = Emulator::new(60, program).unwrap();
+        let expected = [false, false, true, false];
+        for collision in expected {
+            e.run_frame().unwrap();
+            assert_eq!(e.last_collision(), collision);
+        }
+    }
+
+    #[test]
+    fn test_skip_if_key_out_of_range() {
+        // skip if key V0 is pressed, skip if key V0 is not pressed
+        let program = vec![0xE0, 0x9E, 0xE0, 0xA1];
+        let mut e = Emulator::new(400, program).unwrap();
+        e.set_keys_pressed((0..=0xF).filter_map(Key::from_num).collect());
+        e.registers[0] = 0xFF;
+        e.tick().unwrap();
+        assert_eq!(e.program_counter, PROGRAM_START_ADDRESS + 2);
+        e.tick().unwrap();
+        assert_eq!(e.program_counter, PROGRAM_START_ADDRESS + 6);
+    }
+
+    #[test]
+    fn test_get_key_waits_for_release() {
+        // V0 = K, loop forever
+        let program = vec![0xF0, 0x0A, 0x12, 0x02];
+        let mut e = Emulator::new(400, program).unwrap();
+        e.registers[0] = 0xFF;
+        let steps = [
+            (vec![], PROGRAM_START_ADDRESS),
+            (vec![Key::Key7], PROGRAM_START_ADDRESS),
+            (vec![Key::Key7, Key::KeyA], PROGRAM_START_ADDRESS),
+            (vec![Key::Key7, Key::KeyA], PROGRAM_START_ADDRESS),
+        ];
+        for (keys, program_counter) in steps {
+            e.set_keys_pressed(keys.into_iter().collect());
+            e.tick().unwrap();
+            assert_eq!(e.program_counter, program_counter);
+            assert_eq!(e.registers[0], 0xFF);
+        }
+
+        // releasing A completes the wait, although 7 is still down
+        e.set_keys_pressed(HashSet::from([Key::Key7]));
+        e.tick().unwrap();
+        assert_eq!(e.program_counter, PROGRAM_START_ADDRESS + 2);
+        assert_eq!(e.registers[0], 0xA);
+    }
+
+    #[test]
+    fn test_key_events() {
+        // V0 = K, loop forever
+        let program = vec![0xF0, 0x0A, 0x12, 0x02];
+
+        // a tap between two frames is lost with snapshots
+        let mut e = Emulator::new(400, program.clone()).unwrap();
+        e.set_keys_pressed(HashSet::from([Key::Key5]));
+        e.set_keys_pressed(HashSet::new());
+        e.tick().unwrap();
+        e.tick().unwrap();
+        assert_eq!(e.program_counter, PROGRAM_START_ADDRESS);
+
+        // but not with events, the press and the release each take a tick
+        let mut e = Emulator::new(400, program.clone()).unwrap();
+        e.push_key_event(Key::Key5, true);
+        e.push_key_event(Key::Key5, false);
+        e.tick().unwrap();
+        assert_eq!(e.program_counter, PROGRAM_START_ADDRESS);
+        assert!(e.keys_pressed.contains(&Key::Key5));
+        e.tick().unwrap();
+        assert_eq!(e.program_counter, PROGRAM_START_ADDRESS + 2);
+        assert_eq!(e.registers[0], 5);
+        assert!(e.keys_pressed.is_empty());
+
+        // events that change nothing don't use up a tick
+        let mut e = Emulator::new(400, program).unwrap();
+        e.push_key_event(Key::Key7, true);
+        e.tick().unwrap();
+        e.push_key_event(Key::Key7, true);
+        e.push_key_event(Key::KeyA, false);
+        e.push_key_event(Key::Key7, false);
+        e.push_key_event(Key::KeyB, true);
+        e.tick().unwrap();
+        assert_eq!(e.registers[0], 7);
+        assert_eq!(e.keys_pressed, HashSet::new());
+        e.tick().unwrap();
+        assert_eq!(e.keys_pressed, HashSet::from([Key::KeyB]));
+
+        // a reset drops queued events
+        e.push_key_event(Key::Key1, true);
+        e.reset();
+        e.tick().unwrap();
+        assert!(!e.keys_pressed.contains(&Key::Key1));
+    }
+
+    #[test]
+    fn test_headless_accessors() {
+        let mut e = Emulator::new(400, vec![]).unwrap();
+        // V3 = 0x2A, I = 0x300, delay timer = V3, sound timer = V3
+        e.load_program(&[0x63, 0x2A, 0xA3, 0x00, 0xF3, 0x15, 0xF3, 0x18])
+            .unwrap();
+        for _ in 0..4 {
+            e.tick().unwrap();
+        }
+        assert_eq!(e.registers()[3], 0x2A);
+        assert_eq!(e.index_register(), 0x300);
+        assert_eq!(e.program_counter(), PROGRAM_START_ADDRESS + 8);
+        assert_eq!((e.delay_timer(), e.sound_timer()), (0x2A, 0x2A));
+    }
+
+    #[test]
+    fn test_save_and_load_state() {
+        // V0 += 1, I = font "0", draw it at (V0, V0), jump back
+        let program = vec![0x70, 0x01, 0xA0, 0x50, 0xD0, 0x05, 0x12, 0x00];
+        let mut e = Emulator::new(400, program).unwrap();
+        for _ in 0..5 {
+            e.tick().unwrap();
+        }
+        let saved = e.save_state();
+        for _ in 0..7 {
+            e.tick().unwrap();
+        }
+        assert_ne!(e.save_state(), saved);
+
+        let json = serde_json::to_string(&saved).unwrap();
+        e.load_state(serde_json::from_str(&json).unwrap()).unwrap();
+        assert_eq!(e.save_state(), saved);
+        assert_eq!(e.registers[0], 2);
+        // drawn at (V0, V0) with V0 = 1
+        assert!(e.get_pixel(1, 1));
+    }
+
+    #[test]
+    fn test_load_v1_state() {
+        // V0 += 1, jump back
+        let mut memory = vec![0; MEMORY_SIZE];
+        memory[PROGRAM_START_ADDRESS..PROGRAM_START_ADDRESS + 4]
+            .copy_from_slice(&[0x70, 0x01, 0x12, 0x00]);
+        let mut frame_buf = vec![0_u64; HIRES_HEIGHT];
+        frame_buf[3] = 0xF0;
+        // saved before planes, audio patterns and RPL flags existed
+        let v1 = serde_json::json!({
+            "version": 1,
+            "memory": memory,
+            "stack": [0x300],
+            "registers": [7, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+            "i": 0x50,
+            "program_counter": PROGRAM_START_ADDRESS,
+            "delay_timer": 3,
+            "sound_timer": 0,
+            "frame_buf": frame_buf,
+            "hires": false,
+        });
+
+        let mut e = Emulator::new(400, vec![]).unwrap();
+        e.load_state(serde_json::from_value(v1).unwrap()).unwrap();
+        let state = e.save_state();
+        assert_eq!(state.version, STATE_VERSION);
+        assert_eq!(
+            (state.registers[0], state.i, state.delay_timer),
+            (7, 0x50, 3)
+        );
+        assert_eq!(state.frame_buf[3], 0xF0);
+        assert_eq!(state.plane2, vec![0; HIRES_HEIGHT]);
+        assert_eq!(state.selected_planes, 1);
+        assert_eq!((state.audio_pattern, state.pitch), (None, DEFAULT_PITCH));
+        assert_eq!(state.rpl_flags, [0; RPL_FLAGS]);
+        e.tick().unwrap();
+        assert_eq!(e.registers[0], 8);
+
+        // fields a version 1 state cannot have set are reset
+        let migrated = EmulatorState {
+            version: 1,
+            selected_planes: 3,
+            pitch: 0,
+            rpl_flags: [1; RPL_FLAGS],
+            ..e.save_state()
+        }
+        .migrate()
+        .unwrap();
+        assert_eq!(
+            (migrated.selected_planes, migrated.pitch, migrated.rpl_flags),
+            (1, DEFAULT_PITCH, [0; RPL_FLAGS])
+        );
+    }
+
+    #[test]
+    fn test_load_invalid_state() {
+        let mut e = Emulator::new(400, vec![0x12, 0x00]).unwrap();
+        let saved = e.save_state();
+        let invalid = [
+            EmulatorState {
+                version: STATE_VERSION + 1,
+                ..saved.clone()
+            },
+            EmulatorState {
+                version: 0,
+                ..saved.clone()
+            },
+            EmulatorState {
+                memory: vec![0; 10],
+                ..saved.clone()
+            },
+            EmulatorState {
+                frame_buf: vec![0; HEIGHT],
+                ..saved.clone()
+            },
+            EmulatorState {
+                program_counter: MEMORY_SIZE,
+                ..saved.clone()
+            },
+        ];
+        for state in invalid {
+            assert!(matches!(
+                e.load_state(state),
+                Err(EmulatorError::InvalidState(_))
+            ));
+            assert_eq!(e.save_state(), saved);
+        }
+
+        // rejected without touching the emulator, whose own I is past memory
+        let program = vec![0xAF, 0xFF, 0x60, 0xFF, 0xF0, 0x1E, 0x12, 0x06];
+        let mut e = Emulator::new(400, program).unwrap();
+        for _ in 0..3 {
+            e.tick().unwrap();
+        }
+        assert!(e.i >= 0x1000);
+        let saved = e.save_state();
+        let invalid = EmulatorState {
+            program_counter: MEMORY_SIZE,
+            ..saved.clone()
+        };
+        assert!(matches!(
+            e.load_state(invalid),
+            Err(EmulatorError::InvalidState(_))
+        ));
+        assert_eq!(e.save_state(), saved);
+    }
+
+    #[test]
+    fn test_core_state() {
+        // I = font "0", draw it, sound timer = V0 = 0, loop forever
+        let program = vec![0xA0, 0x50, 0xD0, 0x05, 0xF0, 0x18, 0x12, 0x06];
+        let mut e = Emulator::new(400, program).unwrap();
+        e.set_keys_pressed(HashSet::from([Key::Key5]));
+        e.run_frame().unwrap();
+
+        let state = e.core_state();
+        assert!(state.redraw && !state.collision);
+        assert_eq!((state.width, state.height), (WIDTH, HEIGHT));
+        assert_eq!(state.framebuffer.len(), HEIGHT);
+        assert_eq!(state.framebuffer[0], 0xF0 << (WIDTH - 8));
+        assert_eq!((state.delay_timer, state.sound_timer), (0, 0));
+        assert!(state.keys_pressed.contains(&Key::Key5));
+    }
+
+    #[test]
+    fn test_flicker_log() {
+        // I = font "0", draw it at (0, 0) twice, then loop forever
+        let program = vec![0xA0, 0x50, 0xD0, 0x05, 0xD0, 0x05, 0x12, 0x06];
+        let mut e = Emulator::new(400, program).unwrap();
+        e.set_flicker_log(Some(2));
+        e.run_frame().unwrap();
+        assert_eq!(e.draws_last_frame(), 2);
+        // every pixel of the "0" was switched on and off again
+        let flickering = e.flickering_pixels(2);
+        assert_eq!(flickering.len(), 14);
+        assert_eq!(flickering[0], (0, 0, 2));
+        assert!(e.flickering_pixels(3).is_empty());
+
+        // the counts start over with each frame
+        e.run_frame().unwrap();
+        assert_eq!(e.draws_last_frame(), 0);
+        assert!(e.flickering_pixels(1).is_empty());
+    }
 }
@@ -1,4 +1,10 @@
-use crate::emulator::EmulatorError;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::fmt::Write;
+
+use thiserror::Error;
+
+use crate::emulator::{EmulatorError, PROGRAM_START_ADDRESS};
 
 fn extract_address(instruction: (u8, u8)) -> usize {
     let first = ((instruction.0 & 0xF) as u16) << 8;
@@ -16,11 +22,20 @@ fn extract_second_nibble(byte: u8) -> u8 {
     byte & 0x0F
 }
 
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, PartialEq, Eq)]
 pub enum Instruction {
+    // machine code
+    MachineCall(usize), // 0NNN
     // screen
     ClearScreen,               // 00E0
     Draw(usize, usize, usize), // DXYN
+    LowRes,                    // 00FE
+    HighRes,                   // 00FF
+    ScrollDown(usize),         // 00CN
+    ScrollRight,               // 00FB
+    ScrollLeft,                // 00FC
+    SelectPlane(u8),           // FN01, XO-CHIP
     // control flow
     Jump(usize),                                   // 1NNN
     JumpWithOffset(usize),                         // BNNN
@@ -51,33 +66,109 @@ pub enum Instruction {
     GetDelayTimerValue(usize), // FX07
     SetDelayTimer(usize),      // FX15
     SetSoundTimer(usize),      // FX18
+    StoreAudioPattern,         // F002, XO-CHIP
+    SetPitch(usize),           // FX3A, XO-CHIP
     // memory
     StoreRegistersToMemory(usize),     // FX55
     LoadRegistersFromMemory(usize),    // FX65
     SetIndexRegister(usize),           // ANNN
     AddRegisterToIndexRegister(usize), // FX1E
     LoadSprite(usize),                 // FX29
+    LoadBigSprite(usize),              // FX30, SUPER-CHIP
+    SetIndexRegisterLong(u16),         // F000 NNNN, XO-CHIP
     // misc
-    BCD(usize),        // FX33
-    Random(usize, u8), // CXNN
+    BCD(usize),           // FX33
+    StoreRplFlags(usize), // FX75, SUPER-CHIP
+    LoadRplFlags(usize),  // FX85, SUPER-CHIP
+    Random(usize, u8),    // CXNN
 }
 
+/// Opcode patterns and mnemonics of all supported instructions.
+/// X and Y are register nibbles, N an address, byte or nibble operand.
+pub const OPCODES: &[(&str, &str)] = &[
+    ("0NNN", "SYS addr"),
+    ("00CN", "SCD nibble"),
+    ("00E0", "CLS"),
+    ("00EE", "RET"),
+    ("00FB", "SCR"),
+    ("00FC", "SCL"),
+    ("00FE", "LOW"),
+    ("00FF", "HIGH"),
+    ("FN01", "PLANE n"),
+    ("1NNN", "JP addr"),
+    ("2NNN", "CALL addr"),
+    ("3XNN", "SE Vx, byte"),
+    ("4XNN", "SNE Vx, byte"),
+    ("5XY0", "SE Vx, Vy"),
+    ("6XNN", "LD Vx, byte"),
+    ("7XNN", "ADD Vx, byte"),
+    ("8XY0", "LD Vx, Vy"),
+    ("8XY1", "OR Vx, Vy"),
+    ("8XY2", "AND Vx, Vy"),
+    ("8XY3", "XOR Vx, Vy"),
+    ("8XY4", "ADD Vx, Vy"),
+    ("8XY5", "SUB Vx, Vy"),
+    ("8XY6", "SHR Vx, Vy"),
+    ("8XY7", "SUBN Vx, Vy"),
+    ("8XYE", "SHL Vx, Vy"),
+    ("9XY0", "SNE Vx, Vy"),
+    ("ANNN", "LD I, addr"),
+    ("BNNN", "JP V0, addr"),
+    ("CXNN", "RND Vx, byte"),
+    ("DXYN", "DRW Vx, Vy, nibble"),
+    ("EX9E", "SKP Vx"),
+    ("EXA1", "SKNP Vx"),
+    ("FX07", "LD Vx, DT"),
+    ("FX0A", "LD Vx, K"),
+    ("FX15", "LD DT, Vx"),
+    ("FX18", "LD ST, Vx"),
+    ("FX1E", "ADD I, Vx"),
+    ("FX29", "LD F, Vx"),
+    ("FX30", "LD HF, Vx"),
+    ("FX33", "LD B, Vx"),
+    ("F002", "AUDIO"),
+    ("FX3A", "PITCH Vx"),
+    ("FX55", "LD [I], Vx"),
+    ("FX65", "LD Vx, [I]"),
+    ("FX75", "LD R, Vx"),
+    ("FX85", "LD Vx, R"),
+    ("F000 NNNN", "LD I, long addr"),
+];
+
 impl Instruction {
+    /// True for the first word of a four byte instruction, whose operand is the next word.
+    pub fn has_operand(instruction: (u8, u8)) -> bool {
+        instruction == (0xF0, 0x00)
+    }
+
+    /// Decodes a four byte instruction from its two words, see `has_operand`.
+    pub fn parse_long(instruction: (u8, u8), operand: (u8, u8)) -> Result<Self, EmulatorError> {
+        if Self::has_operand(instruction) {
+            Ok(Self::SetIndexRegisterLong(u16::from_be_bytes([
+                operand.0, operand.1,
+            ])))
+        } else {
+            Err(EmulatorError::Instruction())
+        }
+    }
+
     pub fn parse(instruction: (u8, u8)) -> Result<Self, EmulatorError> {
         //let instruction_bytes = instruction.to_be_bytes();
         //println!("inst {:#02x}{:#02x}", instruction.0, instruction.1);
         let first_nibble = instruction.0 >> 4;
         let i = match first_nibble {
-            0x0 => {
-                if instruction.0 != 0 {
-                    return Err(EmulatorError::Instruction());
+            0x0 => match instruction {
+                (0x00, 0xE0) => Self::ClearScreen,
+                (0x00, 0xEE) => Self::Return,
+                (0x00, 0xC0..=0xCF) => {
+                    Self::ScrollDown(extract_second_nibble(instruction.1) as usize)
                 }
-                match instruction.1 {
-                    0xE0 => Self::ClearScreen,
-                    0xEE => Self::Return,
-                    _ => return Err(EmulatorError::Instruction()),
-                }
-            }
+                (0x00, 0xFB) => Self::ScrollRight,
+                (0x00, 0xFC) => Self::ScrollLeft,
+                (0x00, 0xFE) => Self::LowRes,
+                (0x00, 0xFF) => Self::HighRes,
+                _ => Self::MachineCall(extract_address(instruction)),
+            },
             0x1 => Self::Jump(extract_address(instruction)),
             0x2 => Self::Call(extract_address(instruction)),
             0x3 => Self::SkipIfRegisterEqualsConstant(
@@ -146,15 +237,21 @@ impl Instruction {
             0xF => {
                 let x = extract_second_nibble(instruction.0) as usize;
                 match instruction.1 {
+                    0x01 => Self::SelectPlane(x as u8),
                     0x07 => Self::GetDelayTimerValue(x),
                     0x0A => Self::GetKey(x),
                     0x15 => Self::SetDelayTimer(x),
                     0x18 => Self::SetSoundTimer(x),
                     0x1E => Self::AddRegisterToIndexRegister(x),
                     0x29 => Self::LoadSprite(x),
+                    0x30 => Self::LoadBigSprite(x),
+                    0x02 if x == 0 => Self::StoreAudioPattern,
+                    0x3A => Self::SetPitch(x),
                     0x33 => Self::BCD(x),
                     0x55 => Self::StoreRegistersToMemory(x),
                     0x65 => Self::LoadRegistersFromMemory(x),
+                    0x75 => Self::StoreRplFlags(x),
+                    0x85 => Self::LoadRplFlags(x),
                     _ => return Err(EmulatorError::Instruction()),
                 }
             }
@@ -162,17 +259,479 @@ impl Instruction {
         };
         Ok(i)
     }
+
+    /// The two bytes that `parse` decodes to this instruction. For
+    /// `SetIndexRegisterLong` it is the first word, the address follows in the second.
+    /// Operands are cut to the bits their opcode has room for.
+    pub fn encode(&self) -> (u8, u8) {
+        let nnn = |prefix: u16, adr: usize| prefix << 12 | (adr as u16 & 0xFFF);
+        let xnn = |prefix: u16, x: usize, nn: u8| prefix << 12 | (x as u16 & 0xF) << 8 | nn as u16;
+        let xyn = |prefix: u16, x: usize, y: usize, n: u16| {
+            prefix << 12 | (x as u16 & 0xF) << 8 | (y as u16 & 0xF) << 4 | (n & 0xF)
+        };
+        let fx = |x: usize, nn: u8| xnn(0xF, x, nn);
+        let opcode = match *self {
+            Self::MachineCall(adr) => nnn(0x0, adr),
+            Self::ClearScreen => 0x00E0,
+            Self::Draw(x, y, n) => xyn(0xD, x, y, n as u16),
+            Self::LowRes => 0x00FE,
+            Self::HighRes => 0x00FF,
+            Self::ScrollDown(n) => 0x00C0 | (n as u16 & 0xF),
+            Self::ScrollRight => 0x00FB,
+            Self::ScrollLeft => 0x00FC,
+            Self::SelectPlane(n) => fx(n as usize, 0x01),
+            Self::Jump(adr) => nnn(0x1, adr),
+            Self::JumpWithOffset(adr) => nnn(0xB, adr),
+            Self::Call(adr) => nnn(0x2, adr),
+            Self::Return => 0x00EE,
+            Self::SkipIfRegisterEqualsConstant(x, nn) => xnn(0x3, x, nn),
+            Self::SkipIfRegisterNotEqualsConstant(x, nn) => xnn(0x4, x, nn),
+            Self::SkipIfRegisterEqualsRegister(x, y) => xyn(0x5, x, y, 0x0),
+            Self::SkipIfRegisterNotEqualsRegister(x, y) => xyn(0x9, x, y, 0x0),
+            Self::SetRegisterToValue(x, nn) => xnn(0x6, x, nn),
+            Self::SetRegisterToValueOfRegister(x, y) => xyn(0x8, x, y, 0x0),
+            Self::BinaryOR(x, y) => xyn(0x8, x, y, 0x1),
+            Self::BinaryAND(x, y) => xyn(0x8, x, y, 0x2),
+            Self::BinaryXOR(x, y) => xyn(0x8, x, y, 0x3),
+            Self::AddValueToRegister(x, nn) => xnn(0x7, x, nn),
+            Self::AddRegisterToRegister(x, y) => xyn(0x8, x, y, 0x4),
+            Self::SubstractXMinusY(x, y) => xyn(0x8, x, y, 0x5),
+            Self::SubstractYMinusX(x, y) => xyn(0x8, x, y, 0x7),
+            Self::ShiftRight(x, y) => xyn(0x8, x, y, 0x6),
+            Self::ShiftLeft(x, y) => xyn(0x8, x, y, 0xE),
+            Self::SkipIfKeyIsPressed(x) => xnn(0xE, x, 0x9E),
+            Self::SkipIfKeyIsNotPressed(x) => xnn(0xE, x, 0xA1),
+            Self::GetKey(x) => fx(x, 0x0A),
+            Self::GetDelayTimerValue(x) => fx(x, 0x07),
+            Self::SetDelayTimer(x) => fx(x, 0x15),
+            Self::SetSoundTimer(x) => fx(x, 0x18),
+            Self::StoreAudioPattern => 0xF002,
+            Self::SetPitch(x) => fx(x, 0x3A),
+            Self::StoreRegistersToMemory(x) => fx(x, 0x55),
+            Self::LoadRegistersFromMemory(x) => fx(x, 0x65),
+            Self::SetIndexRegister(adr) => nnn(0xA, adr),
+            Self::AddRegisterToIndexRegister(x) => fx(x, 0x1E),
+            Self::LoadSprite(x) => fx(x, 0x29),
+            Self::LoadBigSprite(x) => fx(x, 0x30),
+            Self::SetIndexRegisterLong(_) => 0xF000,
+            Self::BCD(x) => fx(x, 0x33),
+            Self::StoreRplFlags(x) => fx(x, 0x75),
+            Self::LoadRplFlags(x) => fx(x, 0x85),
+            Self::Random(x, nn) => xnn(0xC, x, nn),
+        };
+        let [high, low] = opcode.to_be_bytes();
+        (high, low)
+    }
+}
+
+/// Formats the instruction as a conventional CHIP-8 mnemonic, e.g. `DRW V2, V9, 1`.
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::MachineCall(adr) => write!(f, "SYS {:#05X}", adr),
+            Self::ClearScreen => write!(f, "CLS"),
+            Self::Draw(x, y, n) => write!(f, "DRW V{:X}, V{:X}, {}", x, y, n),
+            Self::LowRes => write!(f, "LOW"),
+            Self::HighRes => write!(f, "HIGH"),
+            Self::ScrollDown(n) => write!(f, "SCD {}", n),
+            Self::ScrollRight => write!(f, "SCR"),
+            Self::ScrollLeft => write!(f, "SCL"),
+            Self::SelectPlane(n) => write!(f, "PLANE {}", n),
+            Self::Jump(adr) => write!(f, "JP {:#05X}", adr),
+            Self::JumpWithOffset(adr) => write!(f, "JP V0, {:#05X}", adr),
+            Self::Call(adr) => write!(f, "CALL {:#05X}", adr),
+            Self::Return => write!(f, "RET"),
+            Self::SkipIfRegisterEqualsConstant(x, c) => write!(f, "SE V{:X}, {:#04X}", x, c),
+            Self::SkipIfRegisterNotEqualsConstant(x, c) => write!(f, "SNE V{:X}, {:#04X}", x, c),
+            Self::SkipIfRegisterEqualsRegister(x, y) => write!(f, "SE V{:X}, V{:X}", x, y),
+            Self::SkipIfRegisterNotEqualsRegister(x, y) => write!(f, "SNE V{:X}, V{:X}", x, y),
+            Self::SetRegisterToValue(x, c) => write!(f, "LD V{:X}, {:#04X}", x, c),
+            Self::SetRegisterToValueOfRegister(x, y) => write!(f, "LD V{:X}, V{:X}", x, y),
+            Self::BinaryOR(x, y) => write!(f, "OR V{:X}, V{:X}", x, y),
+            Self::BinaryAND(x, y) => write!(f, "AND V{:X}, V{:X}", x, y),
+            Self::BinaryXOR(x, y) => write!(f, "XOR V{:X}, V{:X}", x, y),
+            Self::AddValueToRegister(x, c) => write!(f, "ADD V{:X}, {:#04X}", x, c),
+            Self::AddRegisterToRegister(x, y) => write!(f, "ADD V{:X}, V{:X}", x, y),
+            Self::SubstractXMinusY(x, y) => write!(f, "SUB V{:X}, V{:X}", x, y),
+            Self::SubstractYMinusX(x, y) => write!(f, "SUBN V{:X}, V{:X}", x, y),
+            Self::ShiftRight(x, y) => write!(f, "SHR V{:X}, V{:X}", x, y),
+            Self::ShiftLeft(x, y) => write!(f, "SHL V{:X}, V{:X}", x, y),
+            Self::SkipIfKeyIsPressed(x) => write!(f, "SKP V{:X}", x),
+            Self::SkipIfKeyIsNotPressed(x) => write!(f, "SKNP V{:X}", x),
+            Self::GetKey(x) => write!(f, "LD V{:X}, K", x),
+            Self::GetDelayTimerValue(x) => write!(f, "LD V{:X}, DT", x),
+            Self::SetDelayTimer(x) => write!(f, "LD DT, V{:X}", x),
+            Self::SetSoundTimer(x) => write!(f, "LD ST, V{:X}", x),
+            Self::StoreRegistersToMemory(x) => write!(f, "LD [I], V{:X}", x),
+            Self::LoadRegistersFromMemory(x) => write!(f, "LD V{:X}, [I]", x),
+            Self::SetIndexRegister(adr) => write!(f, "LD I, {:#05X}", adr),
+            Self::AddRegisterToIndexRegister(x) => write!(f, "ADD I, V{:X}", x),
+            Self::LoadSprite(x) => write!(f, "LD F, V{:X}", x),
+            Self::LoadBigSprite(x) => write!(f, "LD HF, V{:X}", x),
+            Self::SetIndexRegisterLong(adr) => write!(f, "LD I, LONG {:#06X}", adr),
+            Self::BCD(x) => write!(f, "LD B, V{:X}", x),
+            Self::StoreRplFlags(x) => write!(f, "LD R, V{:X}", x),
+            Self::LoadRplFlags(x) => write!(f, "LD V{:X}, R", x),
+            Self::StoreAudioPattern => write!(f, "AUDIO"),
+            Self::SetPitch(x) => write!(f, "PITCH V{:X}", x),
+            Self::Random(x, c) => write!(f, "RND V{:X}, {:#04X}", x, c),
+        }
+    }
+}
+
+impl TryFrom<(u8, u8)> for Instruction {
+    type Error = EmulatorError;
+
+    fn try_from(instruction: (u8, u8)) -> Result<Self, Self::Error> {
+        Self::parse(instruction)
+    }
+}
+
+impl TryFrom<u16> for Instruction {
+    type Error = EmulatorError;
+
+    fn try_from(instruction: u16) -> Result<Self, Self::Error> {
+        let [first, second] = instruction.to_be_bytes();
+        Self::parse((first, second))
+    }
+}
+
+/// Disassembles a ROM loaded at `PROGRAM_START_ADDRESS` into (address, mnemonic) pairs.
+/// Words that don't decode are emitted as `DB 0xNNNN`, a trailing odd byte as `DB 0xNN`.
+pub fn disassemble(program: &[u8]) -> Vec<(usize, String)> {
+    program
+        .chunks(2)
+        .enumerate()
+        .map(|(n, bytes)| {
+            let address = PROGRAM_START_ADDRESS + n * 2;
+            let text = match *bytes {
+                [first, second] => match Instruction::parse((first, second)) {
+                    Ok(i) => i.to_string(),
+                    Err(_) => format!("DB {:#06X}", u16::from_be_bytes([first, second])),
+                },
+                _ => format!("DB {:#04X}", bytes[0]),
+            };
+            (address, text)
+        })
+        .collect()
+}
+
+/// Disassembles a ROM into an assembly listing with labels for jump, call and
+/// `LD I` targets. Every line is commented with its address and raw bytes, and
+/// words that don't decode (usually data) are emitted as `DB`.
+pub fn disassemble_annotated(program: &[u8]) -> String {
+    let label = |adr: usize| format!("L{:03X}", adr);
+    let end_address = PROGRAM_START_ADDRESS + program.len();
+    let words = program.chunks(2).enumerate().map(|(n, bytes)| {
+        let address = PROGRAM_START_ADDRESS + n * 2;
+        let instruction = match *bytes {
+            [first, second] => Instruction::parse((first, second)).ok(),
+            _ => None,
+        };
+        (address, bytes, instruction)
+    });
+
+    // first pass: collect targets that fall on an instruction in the program
+    let labels: BTreeSet<usize> = words
+        .clone()
+        .filter_map(|(_, _, instruction)| match instruction? {
+            Instruction::Jump(adr)
+            | Instruction::Call(adr)
+            | Instruction::SetIndexRegister(adr) => Some(adr),
+            _ => None,
+        })
+        .filter(|&adr| {
+            (PROGRAM_START_ADDRESS..end_address).contains(&adr)
+                && (adr - PROGRAM_START_ADDRESS) & 1 == 0
+        })
+        .collect();
+
+    // second pass: emit the listing
+    let mut listing = String::new();
+    for (address, bytes, instruction) in words {
+        if labels.contains(&address) {
+            writeln!(listing, "{}:", label(address)).unwrap();
+        }
+        let raw = bytes
+            .iter()
+            .map(|b| format!("{:02X}", b))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let (text, note) = match instruction {
+            Some(i) => {
+                let text = match i {
+                    Instruction::Jump(adr) if labels.contains(&adr) => format!("JP {}", label(adr)),
+                    Instruction::Call(adr) if labels.contains(&adr) => {
+                        format!("CALL {}", label(adr))
+                    }
+                    Instruction::SetIndexRegister(adr) if labels.contains(&adr) => {
+                        format!("LD I, {}", label(adr))
+                    }
+                    i => i.to_string(),
+                };
+                (text, "")
+            }
+            None => {
+                let data = bytes
+                    .iter()
+                    .map(|b| format!("{:#04X}", b))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                (format!("DB {}", data), " (undecodable)")
+            }
+        };
+        writeln!(
+            listing,
+            "    {:<20}; {:#05X}: {}{}",
+            text, address, raw, note
+        )
+        .unwrap();
+    }
+    listing
+}
+
+/// An error in assembly source, reported with its 1-based line number.
+#[derive(Error, Debug, PartialEq, Eq)]
+#[error("line {line}: {message}")]
+pub struct AssembleError {
+    pub line: usize,
+    pub message: String,
+}
+
+#[derive(Clone, Copy)]
+enum Operand<'a> {
+    Register(u16),
+    Index,
+    IndexMemory,
+    DelayTimer,
+    SoundTimer,
+    Key,
+    Font,
+    BigFont,
+    Rpl,
+    Bcd,
+    Long(&'a str),
+    Value(&'a str),
+}
+
+impl<'a> Operand<'a> {
+    fn parse(text: &'a str) -> Self {
+        match text.to_ascii_uppercase().as_str() {
+            "I" => return Self::Index,
+            "[I]" => return Self::IndexMemory,
+            "DT" => return Self::DelayTimer,
+            "ST" => return Self::SoundTimer,
+            "K" => return Self::Key,
+            "F" => return Self::Font,
+            "HF" => return Self::BigFont,
+            "R" => return Self::Rpl,
+            "B" => return Self::Bcd,
+            upper => {
+                if let Some(register) = upper
+                    .strip_prefix('V')
+                    .filter(|r| r.len() == 1)
+                    .and_then(|r| u16::from_str_radix(r, 16).ok())
+                {
+                    return Self::Register(register);
+                }
+            }
+        }
+        match text.get(..4).zip(text.get(4..)) {
+            Some((long, rest))
+                if long.eq_ignore_ascii_case("LONG") && rest.starts_with(char::is_whitespace) =>
+            {
+                Self::Long(rest.trim())
+            }
+            _ => Self::Value(text),
+        }
+    }
+}
+
+fn is_label(text: &str) -> bool {
+    text.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && text.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Splits a line into its optional `label:` and the statement after it.
+fn split_label(text: &str) -> Result<(Option<&str>, Option<&str>), String> {
+    let (label, statement) = match text.split_once(':') {
+        Some((label, statement)) => {
+            let label = label.trim();
+            if !is_label(label) {
+                return Err(format!("invalid label {:?}", label));
+            }
+            (Some(label), statement.trim())
+        }
+        None => (None, text),
+    };
+    Ok((label, Some(statement).filter(|s| !s.is_empty())))
+}
+
+/// Parses a number (`0x` hex, `0b` binary or decimal) or a label into a value up to `max`.
+fn parse_value(
+    text: &str,
+    max: u16,
+    resolve: &impl Fn(&str) -> Option<usize>,
+) -> Result<u16, String> {
+    let lower = text.to_ascii_lowercase();
+    let value = if let Some(hex) = lower.strip_prefix("0x") {
+        usize::from_str_radix(hex, 16).ok()
+    } else if let Some(bin) = lower.strip_prefix("0b") {
+        usize::from_str_radix(bin, 2).ok()
+    } else if is_label(text) {
+        Some(resolve(text).ok_or_else(|| format!("unknown label {}", text))?)
+    } else {
+        text.parse().ok()
+    };
+    match value {
+        Some(value) if value <= max as usize => Ok(value as u16),
+        Some(_) => Err(format!(
+            "{} is out of range, the maximum is {:#X}",
+            text, max
+        )),
+        None => Err(format!("invalid operand {:?}", text)),
+    }
+}
+
+/// Assembles a single statement, labels are looked up with `resolve`.
+fn assemble_statement(
+    statement: &str,
+    resolve: impl Fn(&str) -> Option<usize>,
+) -> Result<Vec<u8>, String> {
+    use Operand::*;
+
+    let (mnemonic, rest) = statement
+        .split_once(char::is_whitespace)
+        .unwrap_or((statement, ""));
+    let operands: Vec<&str> = match rest.trim() {
+        "" => vec![],
+        rest => rest.split(',').map(str::trim).collect(),
+    };
+    let value = |text: &str, max: u16| parse_value(text, max, &resolve);
+    let mnemonic = mnemonic.to_ascii_uppercase();
+    if mnemonic == "DB" {
+        return operands
+            .iter()
+            .map(|text| value(text, 0xFF).map(|b| b as u8))
+            .collect();
+    }
+
+    let operands: Vec<Operand> = operands.into_iter().map(Operand::parse).collect();
+    let word: u16 = match (mnemonic.as_str(), operands.as_slice()) {
+        ("SYS", [Value(adr)]) => value(adr, 0xFFF)?,
+        ("CLS", []) => 0x00E0,
+        ("RET", []) => 0x00EE,
+        ("SCD", [Value(n)]) => 0x00C0 | value(n, 0xF)?,
+        ("SCR", []) => 0x00FB,
+        ("SCL", []) => 0x00FC,
+        ("LOW", []) => 0x00FE,
+        ("HIGH", []) => 0x00FF,
+        ("JP", [Value(adr)]) => 0x1000 | value(adr, 0xFFF)?,
+        ("CALL", [Value(adr)]) => 0x2000 | value(adr, 0xFFF)?,
+        ("SE", [Register(x), Value(c)]) => 0x3000 | x << 8 | value(c, 0xFF)?,
+        ("SNE", [Register(x), Value(c)]) => 0x4000 | x << 8 | value(c, 0xFF)?,
+        ("SE", [Register(x), Register(y)]) => 0x5000 | x << 8 | y << 4,
+        ("LD", [Register(x), Value(c)]) => 0x6000 | x << 8 | value(c, 0xFF)?,
+        ("ADD", [Register(x), Value(c)]) => 0x7000 | x << 8 | value(c, 0xFF)?,
+        ("LD", [Register(x), Register(y)]) => 0x8000 | x << 8 | y << 4,
+        ("OR", [Register(x), Register(y)]) => 0x8001 | x << 8 | y << 4,
+        ("AND", [Register(x), Register(y)]) => 0x8002 | x << 8 | y << 4,
+        ("XOR", [Register(x), Register(y)]) => 0x8003 | x << 8 | y << 4,
+        ("ADD", [Register(x), Register(y)]) => 0x8004 | x << 8 | y << 4,
+        ("SUB", [Register(x), Register(y)]) => 0x8005 | x << 8 | y << 4,
+        ("SHR", [Register(x), Register(y)]) => 0x8006 | x << 8 | y << 4,
+        ("SUBN", [Register(x), Register(y)]) => 0x8007 | x << 8 | y << 4,
+        ("SHL", [Register(x), Register(y)]) => 0x800E | x << 8 | y << 4,
+        ("SNE", [Register(x), Register(y)]) => 0x9000 | x << 8 | y << 4,
+        ("LD", [Index, Value(adr)]) => 0xA000 | value(adr, 0xFFF)?,
+        ("JP", [Register(0), Value(adr)]) => 0xB000 | value(adr, 0xFFF)?,
+        ("RND", [Register(x), Value(c)]) => 0xC000 | x << 8 | value(c, 0xFF)?,
+        ("DRW", [Register(x), Register(y), Value(n)]) => 0xD000 | x << 8 | y << 4 | value(n, 0xF)?,
+        ("SKP", [Register(x)]) => 0xE09E | x << 8,
+        ("SKNP", [Register(x)]) => 0xE0A1 | x << 8,
+        ("PLANE", [Value(n)]) => 0xF001 | value(n, 0xF)? << 8,
+        ("AUDIO", []) => 0xF002,
+        ("LD", [Register(x), DelayTimer]) => 0xF007 | x << 8,
+        ("LD", [Register(x), Key]) => 0xF00A | x << 8,
+        ("LD", [DelayTimer, Register(x)]) => 0xF015 | x << 8,
+        ("LD", [SoundTimer, Register(x)]) => 0xF018 | x << 8,
+        ("ADD", [Index, Register(x)]) => 0xF01E | x << 8,
+        ("LD", [Font, Register(x)]) => 0xF029 | x << 8,
+        ("LD", [BigFont, Register(x)]) => 0xF030 | x << 8,
+        ("LD", [Bcd, Register(x)]) => 0xF033 | x << 8,
+        ("PITCH", [Register(x)]) => 0xF03A | x << 8,
+        ("LD", [IndexMemory, Register(x)]) => 0xF055 | x << 8,
+        ("LD", [Register(x), IndexMemory]) => 0xF065 | x << 8,
+        ("LD", [Rpl, Register(x)]) => 0xF075 | x << 8,
+        ("LD", [Register(x), Rpl]) => 0xF085 | x << 8,
+        ("LD", [Index, Long(adr)]) => {
+            let [first, second] = value(adr, 0xFFFF)?.to_be_bytes();
+            return Ok(vec![0xF0, 0x00, first, second]);
+        }
+        _ => return Err(format!("invalid instruction {:?}", statement)),
+    };
+    Ok(word.to_be_bytes().to_vec())
+}
+
+/// Assembles mnemonics as printed by `Instruction`'s `Display` into a ROM loaded at
+/// `PROGRAM_START_ADDRESS`, the inverse of `disassemble_annotated`. A line may start
+/// with `label:`, which can be used in place of an address, `;` starts a comment and
+/// `DB` emits raw bytes.
+pub fn assemble(source: &str) -> Result<Vec<u8>, AssembleError> {
+    let lines: Vec<(usize, &str)> = source
+        .lines()
+        .enumerate()
+        .map(|(n, text)| (n + 1, text.split(';').next().unwrap_or("").trim()))
+        .collect();
+    let error = |line| move |message| AssembleError { line, message };
+
+    // first pass: assign addresses to labels, any label will do for sizing statements
+    let mut labels = HashMap::new();
+    let mut address = PROGRAM_START_ADDRESS;
+    for &(line, text) in &lines {
+        let (label, statement) = split_label(text).map_err(error(line))?;
+        if let Some(label) = label {
+            if labels.insert(label, address).is_some() {
+                return Err(error(line)(format!("duplicate label {}", label)));
+            }
+        }
+        if let Some(statement) = statement {
+            address += assemble_statement(statement, |_| Some(0))
+                .map_err(error(line))?
+                .len();
+        }
+    }
+
+    // second pass: emit the program
+    let mut program = Vec::new();
+    for (line, text) in lines {
+        if let (_, Some(statement)) = split_label(text).map_err(error(line))? {
+            let bytes = assemble_statement(statement, |label| labels.get(label).copied())
+                .map_err(error(line))?;
+            program.extend(bytes);
+        }
+    }
+    Ok(program)
 }
 
 #[cfg(test)]
 mod tests {
+    use std::collections::HashSet;
+    use std::mem;
+
     use super::*;
 
-    #[test]
-    fn test_valid() {
-        let tests = [
+    fn valid_instructions() -> Vec<((u8, u8), Instruction)> {
+        vec![
             ((0x00, 0xE0), Instruction::ClearScreen),
             ((0x00, 0xEE), Instruction::Return),
+            ((0x00, 0xFE), Instruction::LowRes),
+            ((0x00, 0xFF), Instruction::HighRes),
+            ((0x00, 0xC3), Instruction::ScrollDown(3)),
+            ((0x00, 0xFB), Instruction::ScrollRight),
+            ((0x00, 0xFC), Instruction::ScrollLeft),
+            ((0x00, 0xE1), Instruction::MachineCall(0x0E1)),
+            ((0x01, 0x23), Instruction::MachineCall(0x123)),
             ((0x12, 0x34), Instruction::Jump(0x234)),
             ((0x29, 0x32), Instruction::Call(0x932)),
             (
@@ -211,27 +770,229 @@ mod tests {
             ((0xD2, 0x91), Instruction::Draw(0x2, 0x9, 0x1)),
             ((0xE1, 0x9E), Instruction::SkipIfKeyIsPressed(0x1)),
             ((0xE2, 0xA1), Instruction::SkipIfKeyIsNotPressed(0x2)),
+            ((0xF3, 0x01), Instruction::SelectPlane(0x3)),
             ((0xFF, 0x07), Instruction::GetDelayTimerValue(0xF)),
             ((0xFA, 0x0A), Instruction::GetKey(0xA)),
             ((0xF7, 0x15), Instruction::SetDelayTimer(0x7)),
             ((0xF6, 0x18), Instruction::SetSoundTimer(0x6)),
             ((0xF5, 0x1E), Instruction::AddRegisterToIndexRegister(0x5)),
             ((0xF4, 0x29), Instruction::LoadSprite(0x4)),
+            ((0xF4, 0x30), Instruction::LoadBigSprite(0x4)),
+            ((0xF7, 0x75), Instruction::StoreRplFlags(0x7)),
+            ((0xF3, 0x85), Instruction::LoadRplFlags(0x3)),
             ((0xF3, 0x33), Instruction::BCD(0x3)),
+            ((0xF0, 0x02), Instruction::StoreAudioPattern),
+            ((0xF4, 0x3A), Instruction::SetPitch(0x4)),
             ((0xF2, 0x55), Instruction::StoreRegistersToMemory(0x2)),
             ((0xF1, 0x65), Instruction::LoadRegistersFromMemory(0x1)),
-        ];
+        ]
+    }
 
-        for (i, expected) in tests {
+    #[test]
+    fn test_valid() {
+        for (i, expected) in valid_instructions() {
             let actual = Instruction::parse(i).unwrap();
             assert_eq!(actual, expected);
         }
     }
 
+    #[test]
+    fn test_encode() {
+        for (i, instruction) in valid_instructions() {
+            assert_eq!(instruction.encode(), i, "{}", instruction);
+            assert_eq!(Instruction::parse(instruction.encode()), Ok(instruction));
+        }
+        assert_eq!(
+            Instruction::SetIndexRegisterLong(0x1234).encode(),
+            (0xF0, 0x00)
+        );
+        // operands too large for the opcode are cut off instead of changing it
+        assert_eq!(Instruction::Jump(0x1234).encode(), (0x12, 0x34));
+        assert_eq!(Instruction::Draw(0x12, 3, 0x15).encode(), (0xD2, 0x35));
+    }
+
+    #[test]
+    fn test_parse_long() {
+        assert!(Instruction::has_operand((0xF0, 0x00)));
+        assert_eq!(
+            Instruction::parse_long((0xF0, 0x00), (0x12, 0x34)),
+            Ok(Instruction::SetIndexRegisterLong(0x1234))
+        );
+        assert_eq!(
+            Instruction::parse_long((0xF1, 0x00), (0x12, 0x34)),
+            Err(EmulatorError::Instruction())
+        );
+        // the first word alone can't be decoded
+        assert_eq!(
+            Instruction::parse((0xF0, 0x00)),
+            Err(EmulatorError::Instruction())
+        );
+    }
+
+    #[test]
+    fn test_display() {
+        let tests = [
+            (Instruction::ClearScreen, "CLS"),
+            (Instruction::Draw(0x2, 0x9, 0x1), "DRW V2, V9, 1"),
+            (Instruction::SetIndexRegister(0x315), "LD I, 0x315"),
+            (Instruction::SetRegisterToValue(0xA, 0x10), "LD VA, 0x10"),
+            (Instruction::JumpWithOffset(0x017), "JP V0, 0x017"),
+            (Instruction::LoadRegistersFromMemory(0x1), "LD V1, [I]"),
+        ];
+
+        for (i, expected) in tests {
+            assert_eq!(i.to_string(), expected);
+        }
+    }
+
+    #[test]
+    fn test_opcodes_cover_parse() {
+        fn matches(pattern: &str, opcode: u16) -> bool {
+            pattern
+                .chars()
+                .zip(format!("{:04X}", opcode).chars())
+                .all(|(p, o)| matches!(p, 'X' | 'Y' | 'N') || p == o)
+        }
+
+        let mut variants = HashSet::new();
+        for (pattern, _) in OPCODES {
+            let opcode =
+                u16::from_str_radix(&pattern[..4].replace(['X', 'Y', 'N'], "0"), 16).unwrap();
+            let [first, second] = opcode.to_be_bytes();
+            let i = if Instruction::has_operand((first, second)) {
+                Instruction::parse_long((first, second), (0, 0)).unwrap()
+            } else {
+                Instruction::try_from(opcode).unwrap()
+            };
+            assert!(variants.insert(mem::discriminant(&i)), "{}", pattern);
+        }
+
+        for opcode in 0..=u16::MAX {
+            if let Ok(i) = Instruction::try_from(opcode) {
+                assert!(
+                    OPCODES.iter().any(|(p, _)| matches(p, opcode)),
+                    "{:04X}",
+                    opcode
+                );
+                assert!(variants.contains(&mem::discriminant(&i)), "{:?}", i);
+            }
+        }
+    }
+
+    #[test]
+    fn test_disassemble() {
+        let tests = valid_instructions();
+        let program: Vec<u8> = tests.iter().flat_map(|((a, b), _)| [*a, *b]).collect();
+        let listing = disassemble(&program);
+        assert_eq!(listing.len(), tests.len());
+        for (n, ((address, text), (_, expected))) in listing.iter().zip(&tests).enumerate() {
+            assert_eq!(*address, PROGRAM_START_ADDRESS + n * 2);
+            assert_eq!(*text, expected.to_string());
+        }
+        assert!(listing.contains(&(0x200, String::from("CLS"))));
+        assert!(listing.iter().any(|(_, text)| text == "DRW V2, V9, 1"));
+        assert!(listing.iter().any(|(_, text)| text == "LD I, 0x315"));
+
+        assert_eq!(
+            disassemble(&[0x5A, 0xA2, 0x80]),
+            [
+                (0x200, String::from("DB 0x5AA2")),
+                (0x202, String::from("DB 0x80"))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_disassemble_annotated() {
+        let program = [
+            0xA2, 0x06, 0x22, 0x08, 0x12, 0x02, 0xFF, 0xFF, 0x00, 0xEE, 0x80,
+        ];
+        let expected = [
+            "    LD I, L206          ; 0x200: A2 06",
+            "L202:",
+            "    CALL L208           ; 0x202: 22 08",
+            "    JP L202             ; 0x204: 12 02",
+            "L206:",
+            "    DB 0xFF, 0xFF       ; 0x206: FF FF (undecodable)",
+            "L208:",
+            "    RET                 ; 0x208: 00 EE",
+            "    DB 0x80             ; 0x20A: 80 (undecodable)",
+        ];
+        assert_eq!(disassemble_annotated(&program), expected.join("\n") + "\n");
+    }
+
+    #[test]
+    fn test_assemble() {
+        for ((first, second), instruction) in valid_instructions() {
+            let source = instruction.to_string();
+            assert_eq!(assemble(&source), Ok(vec![first, second]), "{}", source);
+            assert_eq!(disassemble(&[first, second])[0].1, source);
+        }
+        assert_eq!(
+            assemble("LD I, LONG 0x1234"),
+            Ok(vec![0xF0, 0x00, 0x12, 0x34])
+        );
+
+        let program = [
+            0xA2, 0x06, 0x22, 0x08, 0x12, 0x02, 0xFF, 0xFF, 0x00, 0xEE, 0x80,
+        ];
+        assert_eq!(
+            assemble(&disassemble_annotated(&program)),
+            Ok(program.to_vec())
+        );
+
+        let source = "
+            ; count V0 up to 10
+            start:  ld v0, 0
+            loop:   add V0, 1   ; increment
+                    SE V0, 10
+                    JP loop
+                    LD I, data
+            done:   JP done
+            data:   DB 0b11110000, 0x90
+        ";
+        assert_eq!(
+            assemble(source),
+            Ok(vec![
+                0x60, 0x00, 0x70, 0x01, 0x30, 0x0A, 0x12, 0x02, 0xA2, 0x0C, 0x12, 0x0A, 0xF0, 0x90
+            ])
+        );
+    }
+
+    #[test]
+    fn test_assemble_errors() {
+        let error = |source| assemble(source).unwrap_err().to_string();
+        assert_eq!(
+            error("CLS\nFOO V1"),
+            "line 2: invalid instruction \"FOO V1\""
+        );
+        assert_eq!(error("JP nowhere"), "line 1: unknown label nowhere");
+        assert_eq!(
+            error("LD V1, 0x100"),
+            "line 1: 0x100 is out of range, the maximum is 0xFF"
+        );
+        assert_eq!(error("a: CLS\na: RET"), "line 2: duplicate label a");
+        assert_eq!(error("1a: CLS"), "line 1: invalid label \"1a\"");
+        assert_eq!(error("LD V1, 1x"), "line 1: invalid operand \"1x\"");
+    }
+
+    #[test]
+    fn test_try_from() {
+        let from_tuple: Instruction = (0xD2, 0x91).try_into().unwrap();
+        assert_eq!(from_tuple, Instruction::Draw(0x2, 0x9, 0x1));
+
+        let from_u16: Instruction = 0xA315.try_into().unwrap();
+        assert_eq!(from_u16, Instruction::SetIndexRegister(0x315));
+
+        assert_eq!(
+            Instruction::try_from(0x5AA2),
+            Err(EmulatorError::Instruction())
+        );
+    }
+
     #[test]
     fn test_invalid() {
         let tests = [
-            (0x00, 0xE1),
             (0x5A, 0xA2),
             (0x81, 0xFF),
             (0x94, 0x5F),
@@ -1,4 +1,20 @@
-mod beeper;
-mod instruction;
+use std::sync::atomic::{AtomicBool, Ordering};
 
+pub mod beeper;
 pub mod emulator;
+pub mod instruction;
+pub mod rom;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+/// Stops the library from printing warnings to stderr, e.g. about a beep sound
+/// that could not be loaded. Errors are still returned to the caller.
+pub fn set_quiet(quiet: bool) {
+    QUIET.store(quiet, Ordering::Relaxed);
+}
+
+pub fn is_quiet() -> bool {
+    QUIET.load(Ordering::Relaxed)
+}
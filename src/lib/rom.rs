@@ -0,0 +1,55 @@
+const MIN_TITLE_LEN: usize = 6;
+const MIN_TITLE_LETTERS: usize = 3;
+const MAX_TITLE_LEN: usize = 64;
+
+/// Best-effort extraction of a title embedded at the end of a ROM.
+///
+/// Some ROMs append their title or author as plain text after the code.
+/// This looks only at the last bytes of the ROM, ignoring trailing `0x00`
+/// padding: they must form a run of at least 6 printable ASCII characters
+/// with at least 3 letters, preceded by a non-printable byte. Anything else
+/// (including a ROM that is printable from the very first byte) returns
+/// `None`, since instruction bytes often happen to be printable as well.
+pub fn rom_title(bytes: &[u8]) -> Option<String> {
+    let end = bytes.iter().rposition(|&b| b != 0)? + 1;
+    let bytes = &bytes[..end];
+    let start = bytes.iter().rposition(|&b| !is_printable(b))? + 1;
+    let title = &bytes[start..];
+
+    let letters = title.iter().filter(|b| b.is_ascii_alphabetic()).count();
+    if title.len() < MIN_TITLE_LEN || letters < MIN_TITLE_LETTERS {
+        return None;
+    }
+
+    let title = String::from_utf8_lossy(title);
+    let title = title.trim();
+    Some(title.chars().take(MAX_TITLE_LEN).collect())
+}
+
+fn is_printable(b: u8) -> bool {
+    (0x20..=0x7E).contains(&b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rom_title() {
+        let tests: [(&[u8], Option<&str>); 6] = [
+            (
+                b"\x12\x00 Space Race by Joe\x00\x00",
+                Some("Space Race by Joe"),
+            ),
+            (b"\x00\xE0\xA2\x0FPONG 2\x00", Some("PONG 2")),
+            (b"\x12\x00", None),
+            (b"\x12\x00HI!!", None),
+            (b"\x12\x00\x31\x32\x33\x34\x35\x36", None),
+            (b"All printable", None),
+        ];
+
+        for (rom, expected) in tests {
+            assert_eq!(rom_title(rom).as_deref(), expected);
+        }
+    }
+}
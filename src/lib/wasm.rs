@@ -0,0 +1,70 @@
+//! JavaScript bindings for running the emulator in a browser, see `examples/web`.
+
+use wasm_bindgen::prelude::*;
+
+use crate::emulator::{Emulator, Key};
+
+/// The emulator as seen from JavaScript. The page calls `run_frame` 60 times per
+/// second, e.g. from `requestAnimationFrame`, draws `framebuffer` and beeps while
+/// `sound_timer` is non-zero.
+#[wasm_bindgen]
+pub struct WasmEmulator {
+    emulator: Emulator,
+}
+
+#[wasm_bindgen]
+impl WasmEmulator {
+    #[wasm_bindgen(constructor)]
+    pub fn new(clock_speed: u16) -> Result<WasmEmulator, JsError> {
+        Ok(Self {
+            emulator: Emulator::new(clock_speed, vec![])?,
+        })
+    }
+
+    /// Replaces the program and restarts the machine.
+    pub fn load_rom(&mut self, rom: &[u8]) -> Result<(), JsError> {
+        Ok(self.emulator.load_program(rom)?)
+    }
+
+    pub fn reset(&mut self) {
+        self.emulator.reset();
+    }
+
+    pub fn run_frame(&mut self) -> Result<(), JsError> {
+        Ok(self.emulator.run_frame()?)
+    }
+
+    /// True if the screen changed since the last `clear_redraw`.
+    pub fn should_redraw(&self) -> bool {
+        self.emulator.should_redraw()
+    }
+
+    pub fn clear_redraw(&mut self) {
+        self.emulator.clear_redraw();
+    }
+
+    pub fn width(&self) -> usize {
+        self.emulator.resolution().0
+    }
+
+    pub fn height(&self) -> usize {
+        self.emulator.resolution().1
+    }
+
+    /// One byte per pixel, row by row at the current resolution, 0 for off and 1 for on,
+    /// or an XO-CHIP color from 0 to 3. Arrives in JavaScript as a `Uint8Array`.
+    pub fn framebuffer(&self) -> Vec<u8> {
+        self.emulator.get_framebuffer_colors().concat()
+    }
+
+    /// Presses or releases CHIP-8 key `key`, 0 to 15, as it happens.
+    pub fn set_key(&mut self, key: u8, pressed: bool) -> Result<(), JsError> {
+        let key = Key::from_num(key).ok_or_else(|| JsError::new("no such key"))?;
+        self.emulator.push_key_event(key, pressed);
+        Ok(())
+    }
+
+    pub fn sound_timer(&self) -> u8 {
+        self.emulator.sound_timer()
+    }
+}
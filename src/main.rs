@@ -1,56 +1,424 @@
+mod archive;
+mod autofire;
 mod config;
+mod headless;
+mod pacing;
 mod pixelbuffer;
+mod scancode;
+mod script;
+#[cfg(feature = "stream")]
+mod stream;
+mod trace;
 
+use std::cell::{Cell, RefCell};
 use std::collections::HashSet;
 use std::fs;
-use std::path::PathBuf;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::Instant;
 
 use anyhow::Context;
-use clap::{Parser, ValueHint};
+use clap::{Parser, ValueEnum, ValueHint};
 use game_loop::game_loop;
+use winit::dpi::PhysicalSize;
 use winit::event_loop::EventLoop;
-use winit::window::WindowBuilder;
+use winit::window::{Fullscreen, Icon, WindowBuilder};
 use winit_input_helper::WinitInputHelper;
 
-use chip8_emulator_lib::emulator;
+use chip8_emulator_lib::beeper::Beeper;
+use chip8_emulator_lib::emulator::{self, IndexIncrement, Mode, Quirks, Target, QUIRK_PROFILES};
+use chip8_emulator_lib::instruction;
+use chip8_emulator_lib::rom;
 
-use pixelbuffer::{PixelBuffer, PixelBufferSize};
+use autofire::Autofire;
+use config::KeyMapping;
+use pacing::PacingStats;
+use pixelbuffer::{Flicker, PixelBuffer, PixelBufferSize, PresentMode};
+use scancode::{scancode, ScancodeInput};
+use script::InputScript;
+use trace::JsonTraceWriter;
+
+const ICON: &[u8] = include_bytes!("icon.png");
+/// shown when no ROM is given
+const SPLASH: &[u8] = include_bytes!("splash.ch8");
+/// change per press of the speed keys, one more or less instruction per frame
+const CLOCK_SPEED_STEP: u16 = emulator::FPS as u16;
+/// how far the rewind key goes back, 10 seconds
+const REWIND_FRAMES: u64 = 10 * emulator::FPS as u64;
+/// frames emulated per frame while the fast-forward key is held
+const FAST_FORWARD_FACTOR: u32 = 4;
 
 #[derive(Parser, Debug)]
-#[clap(name = "chip8-emulator")]
+#[clap(name = "chip8-emulator", version)]
 struct Args {
+    /// ROM to run, - reads it from stdin. Shows a splash screen to drop a ROM file on if omitted
     #[arg(value_hint = ValueHint::FilePath)]
-    rom_path: PathBuf,
+    rom_path: Option<PathBuf>,
     #[arg(short, long, default_value_t = 400)]
     clock_speed: u16,
+    /// Write every executed instruction to FILE as JSON lines
+    #[arg(long, value_name = "FILE", value_hint = ValueHint::FilePath)]
+    dump_trace_json: Option<PathBuf>,
+    /// Drive the keypad from a TOML script of per-frame key presses
+    #[arg(long, value_name = "FILE", value_hint = ValueHint::FilePath)]
+    input_script: Option<PathBuf>,
+    /// Write an annotated disassembly of the ROM to FILE and exit
+    #[arg(long, value_name = "FILE", value_hint = ValueHint::FilePath, requires = "rom_path")]
+    disassemble_to: Option<PathBuf>,
+    /// Approximate the quirks and speed of a hardware target
+    #[arg(long, value_enum, conflicts_with = "clock_speed")]
+    target: Option<TargetArg>,
+    /// Start from the quirks of a profile instead of the defaults or those of --target
+    #[arg(long, value_name = "PROFILE", value_parser = QUIRK_PROFILES)]
+    quirks: Option<String>,
+    /// How FX55/FX65 change I after the transfer
+    #[arg(long, value_enum, value_name = "MODE")]
+    quirk_load_store: Option<LoadStoreQuirk>,
+    /// DXYN waits for the vertical blank, at most one sprite is drawn per frame
+    #[arg(long)]
+    quirk_display_wait: bool,
+    /// Sprites crossing the screen edge continue on the opposite edge instead of being clipped
+    #[arg(long)]
+    quirk_draw_wraps: bool,
+    /// FX1E sets VF when I passes 0xFFF and wraps I, like the Amiga interpreter
+    #[arg(long)]
+    quirk_index_overflow: bool,
+    /// When frames are shown: fifo waits for vsync, immediate has the least latency but may tear
+    #[arg(long, value_enum, value_name = "MODE", default_value_t = PresentMode::default())]
+    present_mode: PresentMode,
+    /// Send every redrawn screen to a viewer listening on ADDR
+    #[cfg(feature = "stream")]
+    #[arg(long, value_name = "ADDR")]
+    stream: Option<String>,
+    /// Print every executed instruction to stderr
+    #[arg(long)]
+    debug: bool,
+    /// Run one instruction per press of the step key and print what it changed
+    #[arg(long)]
+    step: bool,
+    /// Warn about suspicious behavior, like sprites read across the font/program boundary
+    #[arg(long)]
+    diagnostics: bool,
+    /// Print how evenly frames were emulated and rendered on exit
+    #[arg(long)]
+    pacing_stats: bool,
+    /// Only print errors and requested output
+    #[arg(short, long)]
+    quiet: bool,
+    /// How switched off pixels are shown: as they are, blended with the last frame or fading out
+    #[arg(long, value_enum, value_name = "MODE", default_value_t = Flicker::default())]
+    flicker: Flicker,
+    /// Print frames in which pixels were toggled N or more times, the cause of flicker
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u8).range(1..))]
+    flicker_log: Option<u8>,
+    /// Enable XO-CHIP: 64K memory, two bit planes and audio patterns
+    #[arg(long)]
+    xo_chip: bool,
+    /// Render without a GPU, slower but works on VMs and old hardware
+    #[arg(long)]
+    force_software: bool,
+    /// Seed for CXNN, so runs with the same input are identical
+    #[arg(long, value_name = "N")]
+    seed: Option<u64>,
+    /// Run without a window, writing every redrawn frame to DIR as a PNG
+    #[arg(long, requires_all = ["frames", "out"])]
+    headless: bool,
+    /// Number of frames to run in headless mode
+    #[arg(long, value_name = "N", requires = "headless")]
+    frames: Option<u64>,
+    /// Directory for the frames written in headless mode
+    #[arg(long, value_name = "DIR", value_hint = ValueHint::DirPath, requires = "headless")]
+    out: Option<PathBuf>,
+    /// Print version and supported variants and quirks
+    #[arg(long)]
+    about: bool,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum TargetArg {
+    Cosmac,
+    Chip48,
+    Superchip,
+}
+
+impl From<TargetArg> for Target {
+    fn from(target: TargetArg) -> Self {
+        match target {
+            TargetArg::Cosmac => Target::Cosmac,
+            TargetArg::Chip48 => Target::Chip48,
+            TargetArg::Superchip => Target::SuperChip,
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum LoadStoreQuirk {
+    None,
+    X,
+    #[value(name = "x+1")]
+    XPlusOne,
+}
+
+impl From<LoadStoreQuirk> for IndexIncrement {
+    fn from(quirk: LoadStoreQuirk) -> Self {
+        match quirk {
+            LoadStoreQuirk::None => IndexIncrement::None,
+            LoadStoreQuirk::X => IndexIncrement::X,
+            LoadStoreQuirk::XPlusOne => IndexIncrement::XPlusOne,
+        }
+    }
+}
+
+fn clock_speed(args: &Args) -> u16 {
+    match args.target {
+        Some(target) => Target::from(target).clock_speed(),
+        None => args.clock_speed,
+    }
+}
+
+/// The quirks of `--quirks`, `--target` or the configured `profile` in that order,
+/// changed by the individual `--quirk-*` flags.
+fn quirks(args: &Args, profile: Option<Quirks>) -> anyhow::Result<Quirks> {
+    let mut quirks = match (&args.quirks, args.target) {
+        (Some(name), _) => config::quirk_profile(name)?,
+        (None, Some(target)) => Target::from(target).quirks(),
+        (None, None) => profile.unwrap_or_default(),
+    };
+    if let Some(load_store) = args.quirk_load_store {
+        quirks.index_increment_on_load_store = load_store.into();
+    }
+    if args.quirk_display_wait {
+        quirks.display_wait = true;
+    }
+    if args.quirk_draw_wraps {
+        quirks.draw_wraps = true;
+    }
+    if args.quirk_index_overflow {
+        quirks.index_overflow_flag = true;
+    }
+    Ok(quirks)
+}
+
+fn print_about() {
+    println!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+    let variants: Vec<_> = Mode::ALL.iter().map(|mode| mode.name()).collect();
+    println!("Supported variants: {}", variants.join(", "));
+    println!("Quirk profiles: {}", QUIRK_PROFILES.join(", "));
+    println!("Default quirks: {:?}", Quirks::default());
+}
+
+fn print_pacing_stats((emulated, rendered): &(PacingStats, PacingStats)) {
+    for (name, stats) in [("Emulated", emulated), ("Rendered", rendered)] {
+        match stats.summary() {
+            Some(summary) => println!("{}: {}", name, summary),
+            None => println!("{}: no frames", name),
+        }
+    }
+}
+
+/// Reads the ROM at `path`, or from stdin if `path` is `-`, unpacking gzip and zip files.
+fn read_rom(path: &Path) -> anyhow::Result<Vec<u8>> {
+    let program = if path == Path::new("-") {
+        let mut program = vec![];
+        io::stdin()
+            .read_to_end(&mut program)
+            .context("Could not read ROM from stdin.")?;
+        program
+    } else {
+        fs::read(path).context("Could not read ROM file.")?
+    };
+    archive::unpack(program)
+}
+
+/// Replaces the running program with the ROM at `path` and returns the new window title.
+fn load_rom(emulator: &mut emulator::Emulator, path: &Path) -> anyhow::Result<String> {
+    let program = archive::unpack(fs::read(path)?)?;
+    emulator.load_program(&program)?;
+    Ok(window_title(Some(&program)))
+}
+
+/// `program` is `None` while the splash screen is shown.
+fn window_title(program: Option<&[u8]>) -> String {
+    match program.map(rom::rom_title) {
+        None => String::from("Chip8-Emulator - drop a ROM file here"),
+        Some(Some(rom_title)) => format!("Chip8-Emulator - {}", rom_title),
+        Some(None) => String::from("Chip8-Emulator"),
+    }
+}
+
+fn load_icon() -> anyhow::Result<Icon> {
+    let mut reader = png::Decoder::new(ICON).read_info()?;
+    let mut rgba = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut rgba)?;
+    rgba.truncate(info.buffer_size());
+    Ok(Icon::from_rgba(rgba, info.width, info.height)?)
 }
 
 fn main() -> anyhow::Result<()> {
     let args = Args::parse();
+    if args.about {
+        print_about();
+        return Ok(());
+    }
+    chip8_emulator_lib::set_quiet(args.quiet);
+    let clock_speed = clock_speed(&args);
 
-    let config = config::load()?;
+    let config = config::load(args.quiet)?;
+    let quirks = quirks(&args, config.quirks)?;
 
     let size = PixelBufferSize {
         width: emulator::WIDTH as u32,
         height: emulator::HEIGHT as u32,
-        pixel_size: config.pixel_size,
+        pixel_width: config.pixel_width,
+        pixel_height: config.pixel_height,
     };
 
+    let program = match &args.rom_path {
+        Some(rom_path) => read_rom(rom_path)?,
+        None => SPLASH.to_vec(),
+    };
+    if let Some(path) = args.disassemble_to {
+        fs::write(path, instruction::disassemble_annotated(&program))
+            .context("Could not write disassembly.")?;
+        return Ok(());
+    }
+    let title = window_title(args.rom_path.is_some().then_some(&program[..]));
+
+    let headless = args.headless && args.frames.is_some() && args.out.is_some();
+    let beeper = if headless {
+        Beeper::silent()
+    } else {
+        let mut beeper = match &config.beep_sound {
+            Some(path) => Beeper::with_sound(path),
+            None => Beeper::with_tone(config.beep_frequency, config.beep_waveform),
+        };
+        beeper.set_volume(config.volume);
+        if beeper.is_silent() && !args.quiet {
+            eprintln!("No audio output device found, running without sound.");
+        }
+        beeper
+    };
+    let mut emulator = emulator::Emulator::new_with_audio(clock_speed, vec![], Box::new(beeper))
+        .context("Could not create emulator.")?;
+    emulator.set_quirks(quirks);
+    if let Some(target) = args.target {
+        emulator.set_draw_rows_per_slot(Target::from(target).draw_rows_per_slot());
+    }
+    emulator.set_xo_chip(args.xo_chip)?;
+    if let Some(seed) = args.seed {
+        emulator.set_seed(seed);
+    }
+    emulator
+        .load_program(&program)
+        .context("Could not load ROM.")?;
+    if !args.quiet {
+        println!(
+            "Load/store quirk: {:?}",
+            quirks.index_increment_on_load_store
+        );
+    }
+
+    let json_trace = args
+        .dump_trace_json
+        .as_deref()
+        .map(JsonTraceWriter::create)
+        .transpose()
+        .context("Could not create trace file.")?;
+    let mut trace_hook = json_trace.as_ref().map(JsonTraceWriter::hook);
+    let debug = args.debug;
+    let step = args.step;
+    if debug {
+        trace_hook = Some(trace::debug_hook(trace_hook));
+    }
+    if args.diagnostics {
+        emulator.set_diagnostics(true);
+        trace_hook = Some(trace::diagnostic_hook(trace_hook));
+    }
+    emulator.set_trace_hook(trace_hook);
+    emulator.set_flicker_log(args.flicker_log);
+
+    let mut input_script = args
+        .input_script
+        .as_deref()
+        .map(InputScript::load)
+        .transpose()?;
+
+    if let (true, Some(frames), Some(out)) = (headless, args.frames, &args.out) {
+        let result = headless::run(&mut emulator, frames, out, &config, input_script);
+        if let Some(json_trace) = &json_trace {
+            json_trace.flush()?;
+        }
+        return result;
+    }
+
+    emulator.set_rewind_depth(REWIND_FRAMES);
+
     let event_loop = EventLoop::new();
     let window = WindowBuilder::new()
-        .with_title("Chip8-Emulator")
+        .with_title(title)
         .with_inner_size(size.logical_size())
-        .with_resizable(false)
+        .with_min_inner_size(size.logical_size())
+        .with_window_icon(Some(load_icon().context("Could not load window icon.")?))
         .build(&event_loop)
         .context("Could not crate window.")?;
 
     let mut input = WinitInputHelper::new();
-    let mut pb = PixelBuffer::new(&window, size, config.on_color)
-        .context("Could not create frame buffer.")?;
+    // only consulted for the keypad with the scancode key mapping
+    let mut scancodes = ScancodeInput::default();
+    let mut pb = PixelBuffer::new(
+        &window,
+        size,
+        config.on_color,
+        config.off_color,
+        args.present_mode,
+        args.force_software,
+        args.flicker,
+    )
+    .context("Could not create frame buffer.")?;
+    pb.set_scanlines(config.scanlines);
+
+    let mut autofire = Autofire::new(config.autofire_rate, config.autofire_keys.clone());
+    // without a script or auto-fire, every key press and release is passed on as it
+    // happens, otherwise the keys held on the keyboard are passed on once per frame
+    let key_events = input_script.is_none() && autofire.is_none();
+    let keys_held: Rc<RefCell<HashSet<emulator::Key>>> = Rc::default();
+    let keys_held_input = Rc::clone(&keys_held);
+    // keys down as far as key events are concerned
+    let mut keys_down = HashSet::new();
+    // toggled by the pause key
+    let paused: Rc<Cell<bool>> = Rc::default();
+    let paused_input = Rc::clone(&paused);
+    // while the rewind key is held
+    let rewinding: Rc<Cell<bool>> = Rc::default();
+    let rewinding_input = Rc::clone(&rewinding);
+    // while the fast-forward key is held
+    let fast_forward: Rc<Cell<bool>> = Rc::default();
+    let fast_forward_input = Rc::clone(&fast_forward);
+    // the new window size, set by the input callback until the picture is fitted to it
+    let resized: Rc<Cell<Option<PhysicalSize<u32>>>> = Rc::default();
+    let resized_input = Rc::clone(&resized);
+
+    let mut collision_beeper = config.collision_sound.then(|| {
+        let mut beeper = Beeper::new();
+        beeper.set_volume(config.volume);
+        beeper
+    });
+
+    // real time between emulated and between rendered frames
+    let pacing: Option<Rc<RefCell<(PacingStats, PacingStats)>>> =
+        args.pacing_stats.then(Rc::default);
+    let pacing_render = pacing.clone();
+    let pacing_input = pacing.clone();
+    let mut last_rendered_frame = 0;
 
-    let program = fs::read(args.rom_path).context("Could not read ROM file.")?;
-    let emulator =
-        emulator::Emulator::new(args.clock_speed, program).context("Could not create emulator.")?;
+    #[cfg(feature = "stream")]
+    let mut frame_stream = args
+        .stream
+        .as_deref()
+        .map(stream::FrameStream::connect)
+        .transpose()
+        .context("Could not connect to stream viewer.")?;
 
     game_loop(
         event_loop,
@@ -59,34 +427,314 @@ fn main() -> anyhow::Result<()> {
         emulator::FPS,
         0.1,
         move |g| {
-            g.game.run_frame().unwrap_or_else(|e| {
-                eprintln!("Error while running emulator: {}.", e);
-                std::process::exit(1);
-            });
-        },
-        move |g| {
-            if g.game.should_redraw() {
-                let fb = g.game.get_framebuffer();
-                pb.set_pixels(|x, y| fb[y][x]).unwrap_or_else(|e| {
-                    eprintln!("Error while drawing to frame buffer: {}.", e);
+            // while stepping, instructions only run from the input callback
+            if paused.get() || step {
+                return;
+            }
+            // one frame back per frame plays the game in reverse
+            if rewinding.get() {
+                g.game.rewind(1).unwrap_or_else(|e| {
+                    eprintln!("Error while rewinding: {}.", e);
                     std::process::exit(1);
                 });
+                return;
+            }
+            if let Some(pacing) = &pacing {
+                pacing.borrow_mut().0.record(Instant::now());
+            }
+            // fast-forwarding emulates several frames per real frame, timers included
+            let frames = if fast_forward.get() {
+                FAST_FORWARD_FACTOR
+            } else {
+                1
+            };
+            for _ in 0..frames {
+                let frame = g.game.frame_count();
+                if !key_events {
+                    let mut keys_pressed = match &mut input_script {
+                        Some(input_script) => input_script.keys_pressed(frame),
+                        None => keys_held.borrow().clone(),
+                    };
+                    if let Some(autofire) = &mut autofire {
+                        autofire.apply(frame, &mut keys_pressed);
+                    }
+                    g.game.set_keys_pressed(keys_pressed);
+                }
+                g.game.run_frame().unwrap_or_else(|e| {
+                    if let Some(json_trace) = &json_trace {
+                        json_trace.flush().ok();
+                    }
+                    eprintln!("Error while running emulator: {}.", e);
+                    if debug {
+                        eprint!("{}", g.game.dump_state());
+                    }
+                    std::process::exit(1);
+                });
+                if let Some(beeper) = &mut collision_beeper {
+                    if g.game.last_collision() {
+                        beeper.click();
+                    }
+                }
+            }
+        },
+        move |g| {
+            if let Some(pacing) = &pacing_render {
+                pacing.borrow_mut().1.record(Instant::now());
+            }
+            if let Some(size) = resized.take() {
+                pb.resize_surface(size.width, size.height)
+                    .unwrap_or_else(|e| {
+                        eprintln!("Error while resizing frame buffer: {}.", e);
+                        std::process::exit(1);
+                    });
+            }
+            // fading pixels are updated once per frame, even if the emulator did not draw
+            let frame = g.game.frame_count();
+            if g.game.should_redraw() || (pb.is_fading() && last_rendered_frame != frame) {
+                last_rendered_frame = frame;
+                let (width, height) = g.game.resolution();
+                pb.set_resolution(width, height);
+                pb.set_pixels(|x, y| g.game.get_pixel(x, y))
+                    .unwrap_or_else(|e| {
+                        eprintln!("Error while drawing to frame buffer: {}.", e);
+                        std::process::exit(1);
+                    });
+                #[cfg(feature = "stream")]
+                if let Some(stream) = &mut frame_stream {
+                    if let Err(e) = stream.send(width, g.game.framebuffer_packed()) {
+                        eprintln!("Stopped streaming: {}.", e);
+                        frame_stream = None;
+                    }
+                }
+                g.game.clear_redraw();
             }
         },
         move |g, event| {
+            scancodes.handle_event(event);
             if input.update(event) {
                 if input.close_requested() {
+                    if let Some(pacing) = &pacing_input {
+                        print_pacing_stats(&pacing.borrow());
+                    }
                     g.exit();
                 }
+                if let Some(path) = input.dropped_file() {
+                    // on failure the previous program keeps running
+                    let title = load_rom(&mut g.game, &path).unwrap_or_else(|e| {
+                        eprintln!("Could not load {}: {}.", path.display(), e);
+                        format!("Chip8-Emulator - could not load {}", path.display())
+                    });
+                    g.window.set_title(&title);
+                }
+                if let Some(size) = input.window_resized() {
+                    resized_input.set(Some(size));
+                }
+                if input.key_pressed(config.fullscreen_key) {
+                    let fullscreen = match g.window.fullscreen() {
+                        Some(_) => None,
+                        None => Some(Fullscreen::Borderless(None)),
+                    };
+                    g.window.set_fullscreen(fullscreen);
+                }
+                if step && input.key_pressed(config.step_key) {
+                    match trace::step(&mut g.game) {
+                        Ok(line) => println!("{}", line),
+                        Err(e) => {
+                            eprintln!("Error while running emulator: {}.", e);
+                            eprint!("{}", g.game.dump_state());
+                            std::process::exit(1);
+                        }
+                    }
+                }
+                rewinding_input
+                    .set(input.key_pressed(config.rewind_key) || input.key_held(config.rewind_key));
+                fast_forward_input.set(
+                    input.key_pressed(config.fast_forward_key)
+                        || input.key_held(config.fast_forward_key),
+                );
+                if input.key_pressed(config.pause_key) {
+                    paused_input.set(!paused_input.get());
+                    g.game.set_sound_paused(paused_input.get());
+                }
+                if input.key_pressed(config.reset_key) {
+                    g.game.reset();
+                }
+                let clock_speed = g.game.clock_speed();
+                let new_clock_speed = if input.key_pressed(config.speed_up_key) {
+                    clock_speed.saturating_add(CLOCK_SPEED_STEP)
+                } else if input.key_pressed(config.speed_down_key) {
+                    clock_speed.saturating_sub(CLOCK_SPEED_STEP)
+                } else {
+                    clock_speed
+                };
+                if new_clock_speed != clock_speed {
+                    g.game.set_clock_speed(new_clock_speed);
+                    if !args.quiet {
+                        println!("Clock speed: {}", g.game.clock_speed());
+                    }
+                }
 
-                let mut keys_pressed: HashSet<emulator::Key> = HashSet::new();
-                for (&c, &k) in &config.keys {
-                    if input.key_held(c) || input.key_pressed(c) {
-                        keys_pressed.insert(k);
+                let key_pressed = |c| match config.key_mapping {
+                    KeyMapping::Virtual => input.key_pressed(c),
+                    KeyMapping::Scancode => scancode(c).is_some_and(|s| scancodes.key_pressed(s)),
+                };
+                let key_held = |c| match config.key_mapping {
+                    KeyMapping::Virtual => input.key_held(c),
+                    KeyMapping::Scancode => scancode(c).is_some_and(|s| scancodes.key_held(s)),
+                };
+                if key_events {
+                    // a key pressed and released within one update still gets both events
+                    for key in config.pressed_keys(|c| key_pressed(c) || key_held(c)) {
+                        if keys_down.insert(key) {
+                            g.game.push_key_event(key, true);
+                        }
                     }
+                    let held = config.pressed_keys(key_held);
+                    keys_down.retain(|key| {
+                        let released = !held.contains(key);
+                        if released {
+                            g.game.push_key_event(*key, false);
+                        }
+                        !released
+                    });
+                } else {
+                    *keys_held_input.borrow_mut() =
+                        config.pressed_keys(|c| key_held(c) || key_pressed(c));
                 }
-                g.game.set_keys_pressed(keys_pressed);
+                scancodes.step();
             }
         },
     );
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_icon() {
+        load_icon().unwrap();
+    }
+
+    #[test]
+    fn test_splash() {
+        let mut emulator = emulator::Emulator::new(400, SPLASH.to_vec()).unwrap();
+        for _ in 0..10 {
+            emulator.run_frame().unwrap();
+        }
+        assert!(emulator.lit_pixels().count() > 100);
+    }
+
+    #[test]
+    fn test_fast_forward_redraw() {
+        // draw font "0" once, then loop forever
+        let program = vec![0xA0, 0x50, 0xD0, 0x05, 0x12, 0x04];
+        let mut emulator = emulator::Emulator::new(emulator::FPS as u16, program).unwrap();
+        emulator.run_frame().unwrap();
+        emulator.clear_redraw();
+
+        // only the first of the frames run between two renders draws
+        for _ in 0..FAST_FORWARD_FACTOR {
+            emulator.run_frame().unwrap();
+        }
+        assert!(emulator.should_redraw());
+        assert!(emulator.get_pixel(0, 0));
+    }
+
+    #[test]
+    fn test_load_rom() {
+        let mut emulator = emulator::Emulator::new(400, SPLASH.to_vec()).unwrap();
+        assert!(load_rom(&mut emulator, Path::new("does-not-exist.ch8")).is_err());
+        assert_eq!(emulator.peek_next_instruction().unwrap().to_string(), "CLS");
+
+        let path = std::env::temp_dir().join("chip8-emulator-test-load-rom.ch8");
+        fs::write(&path, [0x60, 0x05]).unwrap();
+        assert_eq!(load_rom(&mut emulator, &path).unwrap(), "Chip8-Emulator");
+        fs::remove_file(&path).unwrap();
+        assert_eq!(
+            emulator.peek_next_instruction().unwrap().to_string(),
+            "LD V0, 0x05"
+        );
+    }
+
+    #[test]
+    fn test_quirk_load_store_reaches_emulator() {
+        let args = Args::try_parse_from(["chip8-emulator", "rom.ch8", "--quirk-load-store", "x+1"])
+            .unwrap();
+        let mut emulator = emulator::Emulator::new(400, vec![]).unwrap();
+        emulator.set_quirks(quirks(&args, None).unwrap());
+        assert_eq!(
+            emulator.quirks().index_increment_on_load_store,
+            IndexIncrement::XPlusOne
+        );
+
+        let args = Args::try_parse_from(["chip8-emulator", "rom.ch8"]).unwrap();
+        assert_eq!(quirks(&args, None).unwrap(), Quirks::default());
+        assert!(
+            Args::try_parse_from(["chip8-emulator", "rom.ch8", "--quirk-load-store", "y"]).is_err()
+        );
+    }
+
+    #[test]
+    fn test_target() {
+        let args = Args::try_parse_from([
+            "chip8-emulator",
+            "rom.ch8",
+            "--target",
+            "cosmac",
+            "--quirk-load-store",
+            "none",
+        ])
+        .unwrap();
+        assert_eq!(clock_speed(&args), Target::Cosmac.clock_speed());
+        assert_eq!(
+            quirks(&args, None).unwrap(),
+            Quirks {
+                index_increment_on_load_store: IndexIncrement::None,
+                ..Target::Cosmac.quirks()
+            }
+        );
+
+        let args = [
+            "chip8-emulator",
+            "rom.ch8",
+            "--target",
+            "chip48",
+            "-c",
+            "500",
+        ];
+        assert!(Args::try_parse_from(args).is_err());
+    }
+
+    #[test]
+    fn test_quirk_profile() {
+        let args = Args::try_parse_from(["chip8-emulator", "rom.ch8"]).unwrap();
+        assert_eq!(
+            quirks(&args, Some(Quirks::xochip())).unwrap(),
+            Quirks::xochip()
+        );
+
+        // the flag wins over --target and the configured profile
+        let args = Args::try_parse_from([
+            "chip8-emulator",
+            "rom.ch8",
+            "--target",
+            "cosmac",
+            "--quirks",
+            "schip",
+            "--quirk-draw-wraps",
+        ])
+        .unwrap();
+        assert_eq!(clock_speed(&args), Target::Cosmac.clock_speed());
+        assert_eq!(
+            quirks(&args, Some(Quirks::xochip())).unwrap(),
+            Quirks {
+                draw_wraps: true,
+                ..Quirks::schip()
+            }
+        );
+
+        let e = Args::try_parse_from(["chip8-emulator", "rom.ch8", "--quirks", "vip"]).unwrap_err();
+        assert!(e.to_string().contains("chip8, schip, xochip"), "{}", e);
+    }
+}
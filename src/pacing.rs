@@ -0,0 +1,100 @@
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use chip8_emulator_lib::emulator::FPS;
+
+/// Collects the real time between consecutive calls of a game loop callback.
+#[derive(Default)]
+pub struct PacingStats {
+    last: Option<Instant>,
+    intervals: Vec<Duration>,
+}
+
+impl PacingStats {
+    pub fn record(&mut self, now: Instant) {
+        if let Some(last) = self.last {
+            self.intervals.push(now - last);
+        }
+        self.last = Some(now);
+    }
+
+    pub fn summary(&self) -> Option<PacingSummary> {
+        if self.intervals.is_empty() {
+            return None;
+        }
+        let mut sorted = self.intervals.clone();
+        sorted.sort();
+        let percentile = |p: usize| sorted[(sorted.len() - 1) * p / 100];
+        // a frame counts as dropped if it took at least one and a half frame periods
+        let dropped_threshold = Duration::from_secs(1) * 3 / (2 * FPS);
+
+        Some(PacingSummary {
+            frames: sorted.len(),
+            min: sorted[0],
+            max: sorted[sorted.len() - 1],
+            avg: sorted.iter().sum::<Duration>() / sorted.len() as u32,
+            p50: percentile(50),
+            p95: percentile(95),
+            p99: percentile(99),
+            dropped: sorted.iter().filter(|&&d| d >= dropped_threshold).count(),
+        })
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct PacingSummary {
+    pub frames: usize,
+    pub min: Duration,
+    pub max: Duration,
+    pub avg: Duration,
+    pub p50: Duration,
+    pub p95: Duration,
+    pub p99: Duration,
+    pub dropped: usize,
+}
+
+impl fmt::Display for PacingSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+        write!(
+            f,
+            "{} frames, min {:.2} ms, avg {:.2} ms, max {:.2} ms, \
+            p50 {:.2} ms, p95 {:.2} ms, p99 {:.2} ms, {} dropped",
+            self.frames,
+            ms(self.min),
+            ms(self.avg),
+            ms(self.max),
+            ms(self.p50),
+            ms(self.p95),
+            ms(self.p99),
+            self.dropped
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summary() {
+        let mut stats = PacingStats::default();
+        assert_eq!(stats.summary(), None);
+
+        let start = Instant::now();
+        let mut now = start;
+        stats.record(now);
+        for ms in [16, 17, 16, 50, 17] {
+            now += Duration::from_millis(ms);
+            stats.record(now);
+        }
+
+        let summary = stats.summary().unwrap();
+        assert_eq!(summary.frames, 5);
+        assert_eq!(summary.min, Duration::from_millis(16));
+        assert_eq!(summary.max, Duration::from_millis(50));
+        assert_eq!(summary.avg, Duration::from_micros(23_200));
+        assert_eq!(summary.p50, Duration::from_millis(17));
+        assert_eq!(summary.dropped, 1);
+    }
+}
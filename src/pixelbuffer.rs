@@ -1,20 +1,72 @@
-use pixels::{Pixels, SurfaceTexture};
+use anyhow::anyhow;
+use clap::ValueEnum;
+use pixels::{wgpu, Pixels, PixelsBuilder, SurfaceTexture};
 use winit::dpi::LogicalSize;
 use winit::window::Window;
 
+const GPU_INIT_HELP: &str = "Could not initialize graphics. \
+Make sure a Vulkan, Metal, DirectX 12 or OpenGL driver is installed, \
+pick another backend with WGPU_BACKEND=vulkan|metal|dx12|gl \
+or try --force-software.";
+
+/// When rendered frames are shown, trading latency against tearing.
+#[derive(ValueEnum, Clone, Copy, Debug, Default)]
+pub enum PresentMode {
+    /// Wait for vsync, no tearing but up to a frame of latency.
+    #[default]
+    Fifo,
+    /// Replace the queued frame on each render, no tearing and less latency,
+    /// falls back to fifo where unsupported.
+    Mailbox,
+    /// Show frames right away, lowest latency but may tear.
+    Immediate,
+}
+
+impl From<PresentMode> for wgpu::PresentMode {
+    fn from(mode: PresentMode) -> Self {
+        match mode {
+            PresentMode::Fifo => wgpu::PresentMode::AutoVsync,
+            PresentMode::Mailbox => wgpu::PresentMode::Mailbox,
+            PresentMode::Immediate => wgpu::PresentMode::AutoNoVsync,
+        }
+    }
+}
+
+/// How pixels that were just switched off are shown.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Flicker {
+    /// Show each frame as it is, flicker included.
+    #[default]
+    On,
+    /// Average each frame with the previous one.
+    Blend,
+    /// Let switched off pixels fade out like a phosphor screen.
+    Phosphor,
+}
+
+/// brightness kept per frame by a switched off pixel in phosphor mode
+const PHOSPHOR_DECAY: f32 = 0.6;
+/// brightness below which a fading pixel is drawn as off
+const PHOSPHOR_CUTOFF: f32 = 0.05;
+/// brightness of lit pixels on every other row with scanlines, in 256ths (about 60%)
+const SCANLINE_LEVEL: i32 = 154;
+
 pub struct PixelBufferSize {
     pub width: u32,
     pub height: u32,
-    pub pixel_size: u32,
+    /// screen pixels per emulator pixel, horizontally
+    pub pixel_width: u32,
+    /// screen pixels per emulator pixel, vertically
+    pub pixel_height: u32,
 }
 
 impl PixelBufferSize {
     fn logical_width(&self) -> u32 {
-        self.width * self.pixel_size
+        self.width * self.pixel_width
     }
 
     fn logical_height(&self) -> u32 {
-        self.height * self.pixel_size
+        self.height * self.pixel_height
     }
 
     pub fn logical_size(&self) -> LogicalSize<u32> {
@@ -22,9 +74,63 @@ impl PixelBufferSize {
     }
 }
 
+/// Turns the on/off state of pixels into a displayed brightness according to `Flicker`.
+struct FlickerFilter {
+    flicker: Flicker,
+    /// displayed brightness of each pixel from 0 to 1
+    levels: Vec<f32>,
+    /// each pixel's state in the previous frame
+    previous: Vec<bool>,
+}
+
+impl FlickerFilter {
+    fn new(flicker: Flicker, pixel_count: usize) -> Self {
+        Self {
+            flicker,
+            levels: vec![0.0; pixel_count],
+            previous: vec![false; pixel_count],
+        }
+    }
+
+    fn is_fading(&self) -> bool {
+        self.levels.iter().any(|&level| level > 0.0 && level < 1.0)
+    }
+
+    fn level(&mut self, index: usize, on: bool) -> f32 {
+        let level = match self.flicker {
+            Flicker::On => on as u8 as f32,
+            Flicker::Blend => (on as u8 + self.previous[index] as u8) as f32 / 2.0,
+            Flicker::Phosphor if on => 1.0,
+            Flicker::Phosphor => match self.levels[index] * PHOSPHOR_DECAY {
+                level if level < PHOSPHOR_CUTOFF => 0.0,
+                level => level,
+            },
+        };
+        self.levels[index] = level;
+        self.previous[index] = on;
+        level
+    }
+}
+
+/// Mixes the off and on colors, `level` 0 being off and 1 fully on.
+pub fn color(off_color: [u8; 4], on_color: [u8; 4], level: f32) -> [u8; 4] {
+    let mut rgba = [0x0, 0x0, 0x0, 0xff];
+    for ((c, off), on) in rgba.iter_mut().zip(off_color).zip(on_color).take(3) {
+        *c = (off as f32 + (on as f32 - off as f32) * level).round() as u8;
+    }
+    rgba
+}
+
 pub struct PixelBuffer {
+    /// size of the buffer, fixed at creation
     size: PixelBufferSize,
+    /// current resolution of the emulator, scaled to fill the buffer
+    width: usize,
+    height: usize,
     on_color: [u8; 4],
+    off_color: [u8; 4],
+    filter: FlickerFilter,
+    scanlines: bool,
     pixels: Pixels,
 }
 
@@ -33,55 +139,328 @@ impl PixelBuffer {
         window: &Window,
         size: PixelBufferSize,
         on_color: (u8, u8, u8),
+        off_color: (u8, u8, u8),
+        present_mode: PresentMode,
+        force_software: bool,
+        flicker: Flicker,
     ) -> anyhow::Result<Self> {
-        let window_size = window.inner_size();
-        let surface_texture = SurfaceTexture::new(window_size.width, window_size.height, window);
-        let pixels = Pixels::new(size.logical_width(), size.logical_height(), surface_texture)?;
+        let pixels = match build_pixels(window, &size, present_mode, force_software) {
+            Err(e) if is_gpu_init_error(&e) && !force_software => {
+                eprintln!("{} Retrying with software rendering.", e);
+                build_pixels(window, &size, present_mode, true)
+            }
+            pixels => pixels,
+        }
+        .map_err(|e| match e {
+            e if is_gpu_init_error(&e) => anyhow!(e).context(GPU_INIT_HELP),
+            e => e.into(),
+        })?;
         let on_color = [on_color.0, on_color.1, on_color.2, 0xff];
+        let off_color = [off_color.0, off_color.1, off_color.2, 0xff];
 
+        let (width, height) = (size.width as usize, size.height as usize);
+        let filter = FlickerFilter::new(flicker, width * height);
         Ok(PixelBuffer {
             size,
+            width,
+            height,
             on_color,
+            off_color,
+            filter,
+            scanlines: false,
             pixels,
         })
     }
 
+    /// True while pixels are between on and off, so rendering again changes the picture.
+    pub fn is_fading(&self) -> bool {
+        self.filter.is_fading()
+    }
+
+    /// Changes the resolution `set_pixels` expects, e.g. when a SUPER-CHIP ROM
+    /// switches to 128x64. The window keeps its size and the pixels get smaller.
+    pub fn set_resolution(&mut self, width: usize, height: usize) {
+        if (width, height) != (self.width, self.height) {
+            self.width = width;
+            self.height = height;
+            self.filter = FlickerFilter::new(self.filter.flicker, width * height);
+        }
+    }
+
+    /// Dims every other row of the buffer like the scanlines of a CRT, see `apply_scanlines`.
+    pub fn set_scanlines(&mut self, scanlines: bool) {
+        self.scanlines = scanlines;
+    }
+
+    /// Fits the picture to a resized window and shows it again. It is scaled by
+    /// whole multiples, keeping its aspect ratio, with black bars around it.
+    pub fn resize_surface(&mut self, width: u32, height: u32) -> anyhow::Result<()> {
+        if width == 0 || height == 0 {
+            // minimized
+            return anyhow::Result::Ok(());
+        }
+        self.pixels.resize_surface(width, height)?;
+        self.pixels.render()?;
+        anyhow::Result::Ok(())
+    }
+
     pub fn set_pixels<F>(&mut self, f: F) -> anyhow::Result<()>
     where
         F: Fn(usize, usize) -> bool,
     {
-        let bytes_per_row = (self.size.logical_width() * 4 * self.size.pixel_size) as usize;
-
-        for (y, pixel) in self
-            .pixels
-            .frame_mut()
-            .chunks_exact_mut(bytes_per_row)
-            .enumerate()
-        {
-            // set pixels for one line
-            let mut line = Vec::with_capacity((self.size.logical_width() * 4) as usize);
-            for x in 0..self.size.width as usize {
-                let rgba = if f(x, y) {
-                    self.on_color
-                } else {
-                    [0x0, 0x0, 0x0, 0xff]
-                };
-                // copy pixel pixel_size times
-                for _ in 0..self.size.pixel_size {
-                    line.extend_from_slice(&rgba);
-                }
+        let buffer_width = self.size.logical_width() as usize;
+        let buffer_height = self.size.logical_height() as usize;
+        let (width, height) = (self.width, self.height);
+        let (off_color, on_color) = (self.off_color, self.on_color);
+        let filter = &mut self.filter;
+        fill_frame(
+            self.pixels.frame_mut(),
+            (buffer_width, buffer_height),
+            (width, height),
+            |x, y| color(off_color, on_color, filter.level(y * width + x, f(x, y))),
+        );
+        if self.scanlines {
+            apply_scanlines(self.pixels.frame_mut(), buffer_width, off_color);
+        }
+        self.pixels.render()?;
+        anyhow::Result::Ok(())
+    }
+}
+
+/// Scales a `width` x `height` picture into an RGBA `frame` of `buffer_width` x `buffer_height`
+/// without allocating. Each picture row is written once into the first buffer row it spans
+/// and copied into the others.
+fn fill_frame<F>(
+    frame: &mut [u8],
+    (buffer_width, buffer_height): (usize, usize),
+    (width, height): (usize, usize),
+    mut rgba: F,
+) where
+    F: FnMut(usize, usize) -> [u8; 4],
+{
+    let row_len = buffer_width * 4;
+    for y in 0..height {
+        let rows = y * buffer_height / height..(y + 1) * buffer_height / height;
+        if rows.is_empty() {
+            // a row too thin to show still advances the flicker filter
+            (0..width).for_each(|x| {
+                rgba(x, y);
+            });
+            continue;
+        }
+
+        // set pixels for one line, each spanning its share of the buffer width
+        let start = rows.start * row_len;
+        let line = &mut frame[start..start + row_len];
+        for x in 0..width {
+            let color = rgba(x, y);
+            let span = x * buffer_width / width..(x + 1) * buffer_width / width;
+            for pixel in line[span.start * 4..span.end * 4].chunks_exact_mut(4) {
+                pixel.copy_from_slice(&color);
             }
+        }
+
+        // copy that line into every other buffer row it spans
+        for row in rows.start + 1..rows.end {
+            frame.copy_within(start..start + row_len, row * row_len);
+        }
+    }
+}
+
+/// Dims every odd row of an RGBA `frame` of `buffer_width` pixels per row towards
+/// `off_color`, so the background keeps its color and only lit pixels get darker.
+/// Costs one more pass over half of the buffer per redraw, see `bench_fill_frame`.
+pub fn apply_scanlines(frame: &mut [u8], buffer_width: usize, off_color: [u8; 4]) {
+    for row in frame.chunks_exact_mut(buffer_width * 4).skip(1).step_by(2) {
+        // alpha is opaque in both, so it stays as it is
+        for pixel in row.chunks_exact_mut(4) {
+            for (c, off) in pixel.iter_mut().zip(off_color) {
+                let dimmed = ((*c as i32 - off as i32) * SCANLINE_LEVEL + 128) >> 8;
+                *c = (off as i32 + dimmed) as u8;
+            }
+        }
+    }
+}
+
+fn build_pixels(
+    window: &Window,
+    size: &PixelBufferSize,
+    present_mode: PresentMode,
+    force_software: bool,
+) -> Result<Pixels, pixels::Error> {
+    let window_size = window.inner_size();
+    let surface_texture = SurfaceTexture::new(window_size.width, window_size.height, window);
+    PixelsBuilder::new(size.logical_width(), size.logical_height(), surface_texture)
+        .request_adapter_options(wgpu::RequestAdapterOptions {
+            power_preference: wgpu::util::power_preference_from_env().unwrap_or_default(),
+            force_fallback_adapter: force_software,
+            compatible_surface: None,
+        })
+        .present_mode(present_mode.into())
+        .build()
+}
+
+/// Errors caused by missing or broken graphics drivers rather than by this program.
+fn is_gpu_init_error(e: &pixels::Error) -> bool {
+    matches!(
+        e,
+        pixels::Error::AdapterNotFound
+            | pixels::Error::DeviceNotFound(_)
+            | pixels::Error::CreateSurface(_)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flicker_filter() {
+        let mut filter = FlickerFilter::new(Flicker::On, 1);
+        assert_eq!(filter.level(0, true), 1.0);
+        assert_eq!(filter.level(0, false), 0.0);
+        assert!(!filter.is_fading());
+
+        let mut filter = FlickerFilter::new(Flicker::Blend, 1);
+        assert_eq!(filter.level(0, true), 0.5);
+        assert!(filter.is_fading());
+        assert_eq!(filter.level(0, true), 1.0);
+        assert_eq!(filter.level(0, false), 0.5);
+        assert_eq!(filter.level(0, false), 0.0);
+
+        let mut filter = FlickerFilter::new(Flicker::Phosphor, 1);
+        assert_eq!(filter.level(0, true), 1.0);
+        assert_eq!(filter.level(0, false), PHOSPHOR_DECAY);
+        assert!(filter.is_fading());
+        while filter.is_fading() {
+            filter.level(0, false);
+        }
+        assert_eq!(filter.level(0, false), 0.0);
+    }
 
-            // copy that line pixel_size times into frame buffer
-            for (px, src) in pixel
-                .iter_mut()
-                .zip(line.iter().cycle().take(bytes_per_row))
+    /// The former `set_pixels`, which allocated a line per row.
+    fn fill_frame_allocating(
+        frame: &mut [u8],
+        (buffer_width, buffer_height): (usize, usize),
+        (width, height): (usize, usize),
+        rgba: impl Fn(usize, usize) -> [u8; 4],
+    ) {
+        for y in 0..height {
+            let mut line = Vec::with_capacity(buffer_width * 4);
+            for x in 0..width {
+                let span = (x + 1) * buffer_width / width - x * buffer_width / width;
+                for _ in 0..span {
+                    line.extend_from_slice(&rgba(x, y));
+                }
+            }
+            let rows = y * buffer_height / height..(y + 1) * buffer_height / height;
+            for row in frame
+                .chunks_exact_mut(buffer_width * 4)
+                .take(rows.end)
+                .skip(rows.start)
             {
-                *px = *src;
+                row.copy_from_slice(&line);
             }
         }
+    }
 
-        self.pixels.render()?;
-        anyhow::Result::Ok(())
+    fn checkerboard(x: usize, y: usize) -> [u8; 4] {
+        [
+            (x * 7) as u8,
+            (y * 13) as u8,
+            ((x + y) % 2 * 255) as u8,
+            0xff,
+        ]
+    }
+
+    #[test]
+    fn test_fill_frame() {
+        let sizes = [
+            ((640, 320), (64, 32)),
+            ((640, 320), (128, 64)),
+            ((64, 32), (128, 64)),
+            ((100, 75), (64, 32)),
+            // wide pixels, 2:1 and 3:2
+            ((1280, 320), (64, 32)),
+            ((384, 128), (128, 64)),
+        ];
+        for (buffer, picture) in sizes {
+            let len = buffer.0 * buffer.1 * 4;
+            let mut expected = vec![0; len];
+            fill_frame_allocating(&mut expected, buffer, picture, checkerboard);
+            let mut actual = vec![0; len];
+            let mut calls = 0;
+            fill_frame(&mut actual, buffer, picture, |x, y| {
+                calls += 1;
+                checkerboard(x, y)
+            });
+            assert!(actual == expected, "{:?} into {:?}", picture, buffer);
+            // every pixel is filtered, even if it is too small to show
+            assert_eq!(calls, picture.0 * picture.1);
+        }
+    }
+
+    /// Run with `cargo test --release -- --ignored --nocapture bench_fill_frame`.
+    #[test]
+    #[ignore]
+    fn bench_fill_frame() {
+        let (buffer, picture) = ((1280, 640), (128, 64));
+        let mut frame = vec![0; buffer.0 * buffer.1 * 4];
+        let frames = 1000;
+
+        let start = std::time::Instant::now();
+        for _ in 0..frames {
+            fill_frame(&mut frame, buffer, picture, checkerboard);
+        }
+        let in_place = start.elapsed();
+
+        let start = std::time::Instant::now();
+        for _ in 0..frames {
+            fill_frame_allocating(&mut frame, buffer, picture, checkerboard);
+        }
+        let allocating = start.elapsed();
+
+        let start = std::time::Instant::now();
+        for _ in 0..frames {
+            fill_frame(&mut frame, buffer, picture, checkerboard);
+            apply_scanlines(&mut frame, buffer.0, [0, 0, 0, 0xff]);
+        }
+        println!("with scanlines {:?}", start.elapsed());
+        println!(
+            "{} full screen updates: in place {:?} without allocations, \
+            allocating {:?} with {} allocations",
+            frames,
+            in_place,
+            allocating,
+            frames * picture.1
+        );
+    }
+
+    #[test]
+    fn test_scanlines() {
+        let off = [60, 30, 10, 0xff];
+        let on = [255, 176, 0, 0xff];
+        // 2x4 buffer, left column on, right column off
+        let mut frame: Vec<u8> = (0..4).flat_map(|_| [on, off].concat()).collect();
+        apply_scanlines(&mut frame, 2, off);
+        let dimmed = [177, 118, 4, 0xff];
+        let rows: Vec<&[u8]> = frame.chunks(8).collect();
+        assert_eq!(rows[0], [on, off].concat());
+        assert_eq!(rows[1], [dimmed, off].concat());
+        assert_eq!(rows[2], [on, off].concat());
+        assert_eq!(rows[3], [dimmed, off].concat());
+    }
+
+    #[test]
+    fn test_color() {
+        let black = [0, 0, 0, 0xff];
+        assert_eq!(color(black, [200, 100, 0, 0xff], 1.0), [200, 100, 0, 0xff]);
+        assert_eq!(color(black, [200, 100, 0, 0xff], 0.5), [100, 50, 0, 0xff]);
+        assert_eq!(color(black, [200, 100, 0, 0xff], 0.0), black);
+
+        let brown = [60, 30, 10, 0xff];
+        assert_eq!(color(brown, [255, 176, 0, 0xff], 0.0), brown);
+        assert_eq!(color(brown, [255, 176, 0, 0xff], 0.5), [158, 103, 5, 0xff]);
+        assert_eq!(color(brown, [255, 176, 0, 0xff], 1.0), [255, 176, 0, 0xff]);
     }
 }
@@ -0,0 +1,156 @@
+use std::collections::HashSet;
+
+use winit::event::{ElementState, Event, KeyboardInput, ScanCode, VirtualKeyCode, WindowEvent};
+
+/// The scancode of the key that produces `code` on a US QWERTY keyboard, for the
+/// digits and letters. These are the PC scancodes that winit reports on Windows
+/// and Linux.
+pub fn scancode(code: VirtualKeyCode) -> Option<ScanCode> {
+    use VirtualKeyCode::*;
+    let scancode = match code {
+        Key1 => 0x02,
+        Key2 => 0x03,
+        Key3 => 0x04,
+        Key4 => 0x05,
+        Key5 => 0x06,
+        Key6 => 0x07,
+        Key7 => 0x08,
+        Key8 => 0x09,
+        Key9 => 0x0A,
+        Key0 => 0x0B,
+        Q => 0x10,
+        W => 0x11,
+        E => 0x12,
+        R => 0x13,
+        T => 0x14,
+        Y => 0x15,
+        U => 0x16,
+        I => 0x17,
+        O => 0x18,
+        P => 0x19,
+        A => 0x1E,
+        S => 0x1F,
+        D => 0x20,
+        F => 0x21,
+        G => 0x22,
+        H => 0x23,
+        J => 0x24,
+        K => 0x25,
+        L => 0x26,
+        Z => 0x2C,
+        X => 0x2D,
+        C => 0x2E,
+        V => 0x2F,
+        B => 0x30,
+        N => 0x31,
+        M => 0x32,
+        _ => return None,
+    };
+    Some(scancode)
+}
+
+/// Physical keys held and pressed since the last `step`, tracked by scancode
+/// like `WinitInputHelper` tracks `VirtualKeyCode`s.
+#[derive(Default)]
+pub struct ScancodeInput {
+    held: HashSet<ScanCode>,
+    pressed: HashSet<ScanCode>,
+}
+
+impl ScancodeInput {
+    pub fn handle_event<T>(&mut self, event: &Event<T>) {
+        if let Event::WindowEvent {
+            event:
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            scancode, state, ..
+                        },
+                    ..
+                },
+            ..
+        } = event
+        {
+            match state {
+                // key repeat sends presses without releases
+                ElementState::Pressed => {
+                    if self.held.insert(*scancode) {
+                        self.pressed.insert(*scancode);
+                    }
+                }
+                ElementState::Released => {
+                    self.held.remove(scancode);
+                }
+            }
+        }
+    }
+
+    /// Forgets the presses, to be called once they have been handled.
+    pub fn step(&mut self) {
+        self.pressed.clear();
+    }
+
+    /// True if the key went down since the last `step`, even if it is up again.
+    pub fn key_pressed(&self, scancode: ScanCode) -> bool {
+        self.pressed.contains(&scancode)
+    }
+
+    pub fn key_held(&self, scancode: ScanCode) -> bool {
+        self.held.contains(&scancode)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use winit::event::{DeviceId, ModifiersState};
+    use winit::window::WindowId;
+
+    fn key_event(scancode: ScanCode, state: ElementState) -> Event<'static, ()> {
+        #[allow(deprecated)]
+        let input = KeyboardInput {
+            scancode,
+            state,
+            virtual_keycode: None,
+            modifiers: ModifiersState::empty(),
+        };
+        // SAFETY: the ids are only compared, never used to reach a window or device
+        let (window_id, device_id) = unsafe { (WindowId::dummy(), DeviceId::dummy()) };
+        Event::WindowEvent {
+            window_id,
+            event: WindowEvent::KeyboardInput {
+                device_id,
+                input,
+                is_synthetic: false,
+            },
+        }
+    }
+
+    #[test]
+    fn test_scancode() {
+        // Y and Z name the positions on QWERTY, whatever the layout prints on them
+        assert_eq!(scancode(VirtualKeyCode::Q), Some(0x10));
+        assert_eq!(scancode(VirtualKeyCode::Y), Some(0x15));
+        assert_eq!(scancode(VirtualKeyCode::Z), Some(0x2C));
+        assert_eq!(scancode(VirtualKeyCode::F5), None);
+    }
+
+    #[test]
+    fn test_scancode_input() {
+        let mut input = ScancodeInput::default();
+        input.handle_event(&key_event(0x10, ElementState::Pressed));
+        assert!(input.key_pressed(0x10) && input.key_held(0x10));
+        input.step();
+        input.handle_event(&key_event(0x10, ElementState::Pressed));
+        assert!(!input.key_pressed(0x10) && input.key_held(0x10));
+
+        // pressed and released before the next step
+        input.handle_event(&key_event(0x11, ElementState::Pressed));
+        input.handle_event(&key_event(0x11, ElementState::Released));
+        input.handle_event(&key_event(0x10, ElementState::Released));
+        assert!(input.key_pressed(0x11) && !input.key_held(0x11));
+        assert!(!input.key_held(0x10));
+        input.step();
+        assert!(!input.key_pressed(0x11));
+    }
+}
@@ -0,0 +1,124 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+use anyhow::Context;
+use serde::Deserialize;
+
+use chip8_emulator_lib::emulator::Key;
+
+use crate::config::str_to_key;
+
+#[derive(Deserialize, Debug)]
+struct TomlScript {
+    input: Vec<TomlInput>,
+}
+
+#[derive(Deserialize, Debug)]
+struct TomlInput {
+    frame: u64,
+    #[serde(default)]
+    press: Vec<String>,
+    #[serde(default)]
+    release: Vec<String>,
+}
+
+/// Key presses and releases scheduled by frame number, e.g.
+/// ```toml
+/// [[input]]
+/// frame = 60
+/// press = ["5"]
+///
+/// [[input]]
+/// frame = 75
+/// release = ["5"]
+/// ```
+/// Keys stay in their state until a later entry changes them.
+pub struct InputScript {
+    events: BTreeMap<u64, Vec<(Key, bool)>>,
+    keys_pressed: HashSet<Key>,
+}
+
+impl InputScript {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let toml_str = fs::read_to_string(path)
+            .context(format!("Could not open file: {}.", path.display()))?;
+        Self::from_toml(&toml_str)
+    }
+
+    fn from_toml(toml_str: &str) -> anyhow::Result<Self> {
+        let toml_script: TomlScript =
+            toml::from_str(toml_str).context("Could not parse input script.")?;
+
+        let mut events: BTreeMap<u64, Vec<(Key, bool)>> = BTreeMap::new();
+        for input in &toml_script.input {
+            let frame_events = events.entry(input.frame).or_default();
+            for key in &input.press {
+                frame_events.push((str_to_key(key)?, true));
+            }
+            for key in &input.release {
+                frame_events.push((str_to_key(key)?, false));
+            }
+        }
+
+        Ok(Self {
+            events,
+            keys_pressed: HashSet::new(),
+        })
+    }
+
+    /// Applies all events scheduled up to `frame` and returns the keys held down.
+    pub fn keys_pressed(&mut self, frame: u64) -> HashSet<Key> {
+        let later = self.events.split_off(&(frame + 1));
+        for (key, pressed) in std::mem::replace(&mut self.events, later)
+            .into_values()
+            .flatten()
+        {
+            if pressed {
+                self.keys_pressed.insert(key);
+            } else {
+                self.keys_pressed.remove(&key);
+            }
+        }
+        self.keys_pressed.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keys_pressed() {
+        let mut script = InputScript::from_toml(
+            r#"
+            [[input]]
+            frame = 2
+            press = ["5", "a"]
+
+            [[input]]
+            frame = 4
+            release = ["5"]
+            "#,
+        )
+        .unwrap();
+
+        let expected = [
+            vec![],
+            vec![],
+            vec![Key::Key5, Key::KeyA],
+            vec![Key::Key5, Key::KeyA],
+            vec![Key::KeyA],
+            vec![Key::KeyA],
+        ];
+        for (frame, keys) in expected.into_iter().enumerate() {
+            assert_eq!(script.keys_pressed(frame as u64), HashSet::from_iter(keys));
+        }
+    }
+
+    #[test]
+    fn test_invalid_key() {
+        let result = InputScript::from_toml("[[input]]\nframe = 1\npress = [\"10\"]");
+        assert!(result.is_err());
+    }
+}
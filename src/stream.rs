@@ -0,0 +1,61 @@
+use std::io::Write;
+use std::net::{TcpStream, ToSocketAddrs};
+
+/// Sends every redrawn screen to a listening viewer, see the README for the protocol.
+pub struct FrameStream {
+    stream: TcpStream,
+}
+
+impl FrameStream {
+    pub fn connect<A: ToSocketAddrs>(addr: A) -> anyhow::Result<Self> {
+        let stream = TcpStream::connect(addr)?;
+        stream.set_nodelay(true)?;
+        Ok(Self { stream })
+    }
+
+    pub fn send(&mut self, width: usize, rows: &[u128]) -> anyhow::Result<()> {
+        self.stream.write_all(&encode_frame(width, rows))?;
+        Ok(())
+    }
+}
+
+/// A 4 byte big-endian payload length followed by the payload: width and height as
+/// big-endian u16, then each row as `width / 8` big-endian bytes, the MSB being the
+/// leftmost pixel.
+fn encode_frame(width: usize, rows: &[u128]) -> Vec<u8> {
+    let row_len = width / 8;
+    let payload_len = 4 + rows.len() * row_len;
+    let mut frame = Vec::with_capacity(4 + payload_len);
+    frame.extend_from_slice(&(payload_len as u32).to_be_bytes());
+    frame.extend_from_slice(&(width as u16).to_be_bytes());
+    frame.extend_from_slice(&(rows.len() as u16).to_be_bytes());
+    for row in rows {
+        frame.extend_from_slice(&row.to_be_bytes()[16 - row_len..]);
+    }
+    frame
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::net::TcpListener;
+
+    #[test]
+    fn test_send_frame() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut stream = FrameStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (mut viewer, _) = listener.accept().unwrap();
+
+        stream.send(64, &[1 << 63, 0xFF]).unwrap();
+
+        let mut len = [0; 4];
+        viewer.read_exact(&mut len).unwrap();
+        assert_eq!(u32::from_be_bytes(len), 20);
+        let mut payload = [0; 20];
+        viewer.read_exact(&mut payload).unwrap();
+        assert_eq!(payload[..4], [0, 64, 0, 2]);
+        assert_eq!(payload[4..12], [0x80, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(payload[12..], [0, 0, 0, 0, 0, 0, 0, 0xFF]);
+    }
+}
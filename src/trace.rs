@@ -0,0 +1,172 @@
+use std::cell::RefCell;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::rc::Rc;
+
+use serde::Serialize;
+
+use chip8_emulator_lib::emulator::{Emulator, EmulatorError, Trace, TraceHook};
+
+/// Prints the diagnostics of each traced instruction to stderr, then passes it on to `inner`.
+pub fn diagnostic_hook(mut inner: Option<TraceHook>) -> TraceHook {
+    Box::new(move |t: &Trace| {
+        if let Some(diagnostic) = t.diagnostic {
+            eprintln!("Warning at {:#05X}: {}.", t.program_counter, diagnostic);
+        }
+        if let Some(inner) = &mut inner {
+            inner(t);
+        }
+    })
+}
+
+/// Prints each traced instruction to stderr, then passes it on to `inner`.
+pub fn debug_hook(mut inner: Option<TraceHook>) -> TraceHook {
+    Box::new(move |t: &Trace| {
+        eprintln!("{}", debug_line(t));
+        if let Some(inner) = &mut inner {
+            inner(t);
+        }
+    })
+}
+
+/// Address, raw opcode and mnemonic, e.g. `0x200  6005  LD V0, 0x05`.
+fn debug_line(t: &Trace) -> String {
+    format!(
+        "{:#05X}  {:04X}  {}",
+        t.program_counter, t.opcode, t.instruction
+    )
+}
+
+/// Executes the next instruction and describes it like `--debug`, followed by
+/// the registers it changed, e.g. `0x200  6005  LD V0, 0x05  V0 00 -> 05`.
+pub fn step(emulator: &mut Emulator) -> Result<String, EmulatorError> {
+    let program_counter = emulator.program_counter();
+    let bytes = emulator.dump_memory(program_counter, 2)?;
+    let opcode = u16::from_be_bytes([bytes[0], bytes[1]]);
+    let instruction = emulator.peek_next_instruction()?;
+    let registers = *emulator.registers();
+    let i = emulator.index_register();
+
+    emulator.step()?;
+
+    let trace = Trace {
+        program_counter,
+        opcode,
+        instruction: &instruction,
+        registers: &registers,
+        i,
+        diagnostic: None,
+    };
+    Ok(debug_line(&trace)
+        + &register_changes(
+            (&registers, i),
+            (emulator.registers(), emulator.index_register()),
+        ))
+}
+
+/// Lists the registers that differ between `before` and `after`, each preceded
+/// by two spaces.
+fn register_changes(before: (&[u8; 16], usize), after: (&[u8; 16], usize)) -> String {
+    let mut changes = String::new();
+    for (x, (old, new)) in before.0.iter().zip(after.0).enumerate() {
+        if old != new {
+            changes += &format!("  V{:X} {:02X} -> {:02X}", x, old, new);
+        }
+    }
+    if before.1 != after.1 {
+        changes += &format!("  I {:#05X} -> {:#05X}", before.1, after.1);
+    }
+    changes
+}
+
+/// One line of the JSON trace, see the README for the format.
+#[derive(Serialize)]
+struct JsonTraceRecord<'a> {
+    pc: usize,
+    opcode: u16,
+    mnemonic: String,
+    registers: &'a [u8; 16],
+    i: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    warning: Option<String>,
+}
+
+/// Writes every executed instruction as a JSON object per line.
+pub struct JsonTraceWriter {
+    writer: Rc<RefCell<BufWriter<File>>>,
+}
+
+impl JsonTraceWriter {
+    pub fn create(path: &Path) -> anyhow::Result<Self> {
+        let file = File::create(path)?;
+        Ok(Self {
+            writer: Rc::new(RefCell::new(BufWriter::new(file))),
+        })
+    }
+
+    pub fn hook(&self) -> TraceHook {
+        let writer = Rc::clone(&self.writer);
+        Box::new(move |t: &Trace| {
+            let record = JsonTraceRecord {
+                pc: t.program_counter,
+                opcode: t.opcode,
+                mnemonic: t.instruction.to_string(),
+                registers: t.registers,
+                i: t.i,
+                warning: t.diagnostic.map(|d| d.to_string()),
+            };
+            let mut writer = writer.borrow_mut();
+            serde_json::to_writer(&mut *writer, &record)
+                .map_err(anyhow::Error::from)
+                .and_then(|_| writeln!(writer).map_err(anyhow::Error::from))
+                .unwrap_or_else(|e| {
+                    eprintln!("Error while writing trace: {}.", e);
+                    std::process::exit(1);
+                });
+        })
+    }
+
+    pub fn flush(&self) -> anyhow::Result<()> {
+        self.writer.borrow_mut().flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chip8_emulator_lib::instruction::Instruction;
+
+    #[test]
+    fn test_debug_line() {
+        let trace = Trace {
+            program_counter: 0x200,
+            opcode: 0x6005,
+            instruction: &Instruction::SetRegisterToValue(0, 5),
+            registers: &[0; 16],
+            i: 0,
+            diagnostic: None,
+        };
+        assert_eq!(debug_line(&trace), "0x200  6005  LD V0, 0x05");
+    }
+
+    #[test]
+    fn test_step() {
+        // V0 = 5, I = 0x300, V1 = V1 + V0
+        let program = vec![0x60, 0x05, 0xA3, 0x00, 0x81, 0x04];
+        let mut e = Emulator::new(400, program).unwrap();
+        assert_eq!(
+            step(&mut e).unwrap(),
+            "0x200  6005  LD V0, 0x05  V0 00 -> 05"
+        );
+        assert_eq!(
+            step(&mut e).unwrap(),
+            "0x202  A300  LD I, 0x300  I 0x000 -> 0x300"
+        );
+        assert_eq!(
+            step(&mut e).unwrap(),
+            "0x204  8104  ADD V1, V0  V1 00 -> 05"
+        );
+    }
+}